@@ -0,0 +1,221 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use actix_web::dev::ServiceRequest;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::get_current_timestamp;
+use ring::hmac;
+use serde::Serialize;
+
+use crate::{signing::hash_token, RegisteredClaims};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    pub outcome: AuditOutcome,
+    // `JwtDecodeErrors::kind` of failed attempts, `denied` when the success handler rejected
+    // the request and `throttled` for clients over the failure limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'static str>,
+}
+
+// called on the request path, implementations doing I/O should be wrapped in `NonBlockingAuditSink`
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: AuditEvent);
+}
+
+// appends one JSON object per line, the file is flushed after every event
+pub struct JsonLinesAuditSink {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl JsonLinesAuditSink {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesAuditSink {
+    fn record(&self, event: AuditEvent) {
+        let Ok(mut line) = serde_json::to_vec(&event) else {
+            return;
+        };
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+        // an audit log that can't be written shouldn't take the service down
+        let _ = file.write_all(&line).and_then(|_| file.flush());
+    }
+}
+
+// hands events to a background thread through a bounded channel,
+// events are dropped instead of blocking requests when the channel is full
+pub struct NonBlockingAuditSink {
+    sender: SyncSender<AuditEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl NonBlockingAuditSink {
+    pub fn new<S>(sink: S, capacity: usize) -> io::Result<Self>
+    where
+        S: AuditSink + 'static,
+    {
+        let (sender, receiver) = sync_channel::<AuditEvent>(capacity);
+        thread::Builder::new()
+            .name("jwt-audit-sink".into())
+            .spawn(move || {
+                for event in receiver {
+                    sink.record(event);
+                }
+            })?;
+        Ok(Self {
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl AuditSink for NonBlockingAuditSink {
+    fn record(&self, event: AuditEvent) {
+        if let Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) =
+            self.sender.try_send(event)
+        {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FieldPolicy {
+    #[default]
+    Keep,
+    Hash,
+    Omit,
+}
+
+// by default every field is logged as is
+#[derive(Clone, Default)]
+pub struct AuditPolicy {
+    client_ip: FieldPolicy,
+    subject: FieldPolicy,
+    jti: FieldPolicy,
+    issuer: FieldPolicy,
+    hash_key: Option<hmac::Key>,
+}
+
+impl AuditPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn client_ip(mut self, policy: FieldPolicy) -> Self {
+        self.client_ip = policy;
+        self
+    }
+
+    pub fn subject(mut self, policy: FieldPolicy) -> Self {
+        self.subject = policy;
+        self
+    }
+
+    pub fn jti(mut self, policy: FieldPolicy) -> Self {
+        self.jti = policy;
+        self
+    }
+
+    pub fn issuer(mut self, policy: FieldPolicy) -> Self {
+        self.issuer = policy;
+        self
+    }
+
+    // hashed fields use HMAC-SHA256 with this key instead of plain SHA-256,
+    // so low entropy values like IP addresses can't be recovered by brute force
+    pub fn hash_key(mut self, key: &[u8]) -> Self {
+        self.hash_key = Some(hmac::Key::new(hmac::HMAC_SHA256, key));
+        self
+    }
+
+    fn apply(&self, policy: FieldPolicy, value: Option<String>) -> Option<String> {
+        match policy {
+            FieldPolicy::Keep => value,
+            FieldPolicy::Hash => value.map(|value| match &self.hash_key {
+                Some(key) => URL_SAFE_NO_PAD.encode(hmac::sign(key, value.as_bytes())),
+                None => hash_token(&value),
+            }),
+            FieldPolicy::Omit => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Audit {
+    sink: Arc<dyn AuditSink>,
+    policy: AuditPolicy,
+}
+
+impl Audit {
+    pub(crate) fn new(sink: Arc<dyn AuditSink>, policy: AuditPolicy) -> Self {
+        Self { sink, policy }
+    }
+
+    // rejected tokens carry no claims, they weren't verified and can't be trusted
+    pub(crate) fn record(
+        &self,
+        req: &ServiceRequest,
+        registered: Option<&RegisteredClaims>,
+        error: Option<&'static str>,
+    ) {
+        let policy = &self.policy;
+        let claim = |claim: fn(&RegisteredClaims) -> &Option<String>| {
+            registered.and_then(|registered| claim(registered).clone())
+        };
+        // the socket address, forwarded headers can be set by the client
+        let client_ip = req.peer_addr().map(|addr| addr.ip().to_string());
+        self.sink.record(AuditEvent {
+            timestamp: get_current_timestamp(),
+            client_ip: policy.apply(policy.client_ip, client_ip),
+            method: req.method().to_string(),
+            path: req.path().to_owned(),
+            subject: policy.apply(policy.subject, claim(|claims| &claims.sub)),
+            jti: policy.apply(policy.jti, claim(|claims| &claims.jti)),
+            issuer: policy.apply(policy.issuer, claim(|claims| &claims.iss)),
+            outcome: match error {
+                Some(_) => AuditOutcome::Failure,
+                None => AuditOutcome::Success,
+            },
+            error,
+        });
+    }
+}
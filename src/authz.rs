@@ -0,0 +1,306 @@
+use std::{
+    future::{ready, Ready},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Extensions, Service, ServiceRequest, ServiceResponse, Transform},
+    guard::{Guard, GuardContext},
+    Error, HttpMessage,
+};
+use futures::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Claims, JwtDecodeErrors, Rfc6750};
+
+pub trait Authorities {
+    fn scopes(&self) -> Vec<&str>;
+
+    fn roles(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn permissions(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    // a single string is treated as a space-delimited list (RFC 8693 section 4.2)
+    pub fn values(&self) -> Vec<&str> {
+        match self {
+            OneOrMany::One(value) => value.split_whitespace().collect(),
+            OneOrMany::Many(values) => values.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RealmAccess {
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StandardAuthorities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<OneOrMany>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scp: Option<OneOrMany>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<OneOrMany>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub realm_access: Option<RealmAccess>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<OneOrMany>,
+}
+
+impl Authorities for StandardAuthorities {
+    fn scopes(&self) -> Vec<&str> {
+        self.scope
+            .iter()
+            .chain(self.scp.iter())
+            .flat_map(OneOrMany::values)
+            .collect()
+    }
+
+    fn roles(&self) -> Vec<&str> {
+        self.roles
+            .iter()
+            .flat_map(OneOrMany::values)
+            .chain(
+                self.realm_access
+                    .iter()
+                    .flat_map(|realm_access| realm_access.roles.iter().map(String::as_str)),
+            )
+            .collect()
+    }
+
+    fn permissions(&self) -> Vec<&str> {
+        self.permissions
+            .iter()
+            .flat_map(OneOrMany::values)
+            .collect()
+    }
+}
+
+impl Authorities for Value {
+    fn scopes(&self) -> Vec<&str> {
+        ["scope", "scp"]
+            .into_iter()
+            .flat_map(|path| json_values(self, path))
+            .collect()
+    }
+
+    fn roles(&self) -> Vec<&str> {
+        ["roles", "realm_access.roles"]
+            .into_iter()
+            .flat_map(|path| json_values(self, path))
+            .collect()
+    }
+
+    fn permissions(&self) -> Vec<&str> {
+        json_values(self, "permissions")
+    }
+}
+
+pub fn json_values<'a>(value: &'a Value, path: &str) -> Vec<&'a str> {
+    let mut value = value;
+    for segment in path.split('.') {
+        match value.get(segment) {
+            Some(next) => value = next,
+            None => return Vec::new(),
+        }
+    }
+    match value {
+        Value::String(value) => value.split_whitespace().collect(),
+        Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AuthorityKind {
+    Scope,
+    Role,
+    Permission,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MatchMode {
+    All,
+    Any,
+}
+
+struct Requirement<T> {
+    kind: AuthorityKind,
+    mode: MatchMode,
+    required: Arc<[String]>,
+    rfc6750: Arc<Rfc6750>,
+    _token_data_type: PhantomData<T>,
+}
+
+impl<T> Clone for Requirement<T> {
+    fn clone(&self) -> Self {
+        Self {
+            kind: self.kind,
+            mode: self.mode,
+            required: self.required.clone(),
+            rfc6750: self.rfc6750.clone(),
+            _token_data_type: PhantomData,
+        }
+    }
+}
+
+impl<T: Authorities + 'static> Requirement<T> {
+    fn new<I, S>(kind: AuthorityKind, mode: MatchMode, required: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            kind,
+            mode,
+            required: required.into_iter().map(Into::into).collect(),
+            rfc6750: Arc::new(Rfc6750::default()),
+            _token_data_type: PhantomData,
+        }
+    }
+
+    fn is_satisfied_by(&self, claims: &T) -> bool {
+        let granted = match self.kind {
+            AuthorityKind::Scope => claims.scopes(),
+            AuthorityKind::Role => claims.roles(),
+            AuthorityKind::Permission => claims.permissions(),
+        };
+        let mut required = self.required.iter();
+        match self.mode {
+            MatchMode::All => required.all(|r| granted.contains(&r.as_str())),
+            MatchMode::Any => required.any(|r| granted.contains(&r.as_str())),
+        }
+    }
+
+    // `None` when the request wasn't authenticated by `JwtMiddleware<T>`
+    fn check(&self, extensions: &Extensions) -> Option<bool> {
+        if let Some(claims) = extensions.get::<T>() {
+            return Some(self.is_satisfied_by(claims));
+        }
+        extensions
+            .get::<Claims<T>>()
+            .map(|claims| self.is_satisfied_by(claims))
+    }
+}
+
+pub struct RequirementService<S, T> {
+    service: S,
+    requirement: Requirement<T>,
+}
+
+impl<S, B, T> Service<ServiceRequest> for RequirementService<S, T>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+    T: Authorities + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let checked = self.requirement.check(&req.extensions());
+        let error = match checked {
+            Some(true) => {
+                let fut = self.service.call(req);
+                return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+            }
+            Some(false) => self
+                .requirement
+                .rfc6750
+                .insufficient_scope(self.requirement.required.join(" ")),
+            None => self
+                .requirement
+                .rfc6750
+                .error(&JwtDecodeErrors::MissingToken),
+        };
+        Box::pin(ready(Ok(req.error_response(error).map_into_right_body())))
+    }
+}
+
+macro_rules! requirement {
+    ($name:ident, $kind:expr) => {
+        pub struct $name<T>(Requirement<T>);
+
+        impl<T> Clone for $name<T> {
+            fn clone(&self) -> Self {
+                Self(self.0.clone())
+            }
+        }
+
+        impl<T: Authorities + 'static> $name<T> {
+            pub fn all<I, S>(required: I) -> Self
+            where
+                I: IntoIterator<Item = S>,
+                S: Into<String>,
+            {
+                Self(Requirement::new($kind, MatchMode::All, required))
+            }
+
+            pub fn any<I, S>(required: I) -> Self
+            where
+                I: IntoIterator<Item = S>,
+                S: Into<String>,
+            {
+                Self(Requirement::new($kind, MatchMode::Any, required))
+            }
+
+            pub fn rfc6750(mut self, rfc6750: Rfc6750) -> Self {
+                self.0.rfc6750 = Arc::new(rfc6750);
+                self
+            }
+        }
+
+        impl<T: Authorities + 'static> Guard for $name<T> {
+            fn check(&self, ctx: &GuardContext<'_>) -> bool {
+                self.0.check(&ctx.req_data()).unwrap_or(false)
+            }
+        }
+
+        impl<S, B, T> Transform<S, ServiceRequest> for $name<T>
+        where
+            S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+            S::Future: 'static,
+            B: 'static,
+            T: Authorities + 'static,
+        {
+            type Response = ServiceResponse<EitherBody<B>>;
+            type Error = Error;
+            type InitError = ();
+            type Transform = RequirementService<S, T>;
+            type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+            fn new_transform(&self, service: S) -> Self::Future {
+                ready(Ok(RequirementService {
+                    service,
+                    requirement: self.0.clone(),
+                }))
+            }
+        }
+    };
+}
+
+requirement!(RequireScopes, AuthorityKind::Scope);
+requirement!(RequireRoles, AuthorityKind::Role);
+requirement!(RequirePermissions, AuthorityKind::Permission);
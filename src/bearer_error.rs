@@ -0,0 +1,305 @@
+use std::fmt;
+
+use actix_web::{
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use jsonwebtoken::errors::ErrorKind;
+
+use crate::{JwtDecodeErrors, JwtMiddleware};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BearerErrorCode {
+    InvalidRequest,
+    InvalidToken,
+    InsufficientScope,
+    InvalidDpopProof,
+    UseDpopNonce,
+}
+
+impl BearerErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BearerErrorCode::InvalidRequest => "invalid_request",
+            BearerErrorCode::InvalidToken => "invalid_token",
+            BearerErrorCode::InsufficientScope => "insufficient_scope",
+            BearerErrorCode::InvalidDpopProof => "invalid_dpop_proof",
+            BearerErrorCode::UseDpopNonce => "use_dpop_nonce",
+        }
+    }
+
+    // DPoP errors are challenged with the `DPoP` scheme (RFC 9449 section 7.1)
+    fn scheme(&self) -> &'static str {
+        match self {
+            BearerErrorCode::InvalidDpopProof | BearerErrorCode::UseDpopNonce => "DPoP",
+            _ => "Bearer",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BearerError {
+    status: StatusCode,
+    code: Option<BearerErrorCode>,
+    description: Option<String>,
+    scope: Option<String>,
+    realm: Option<String>,
+    dpop_nonce: Option<String>,
+    json_body: bool,
+}
+
+impl BearerError {
+    pub fn new(status: StatusCode, code: Option<BearerErrorCode>) -> Self {
+        Self {
+            status,
+            code,
+            description: None,
+            scope: None,
+            realm: None,
+            dpop_nonce: None,
+            json_body: false,
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    pub fn realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    pub fn dpop_nonce(mut self, dpop_nonce: impl Into<String>) -> Self {
+        self.dpop_nonce = Some(dpop_nonce.into());
+        self
+    }
+
+    pub fn json_body(mut self, json_body: bool) -> Self {
+        self.json_body = json_body;
+        self
+    }
+
+    pub fn code(&self) -> Option<BearerErrorCode> {
+        self.code
+    }
+
+    fn www_authenticate(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(realm) = &self.realm {
+            params.push(format!("realm=\"{}\"", quote(realm)));
+        }
+        if let Some(code) = self.code {
+            params.push(format!("error=\"{}\"", code.as_str()));
+        }
+        if let Some(description) = &self.description {
+            params.push(format!("error_description=\"{}\"", quote(description)));
+        }
+        if let Some(scope) = &self.scope {
+            params.push(format!("scope=\"{}\"", quote(scope)));
+        }
+        let scheme = self.code.map_or("Bearer", |code| code.scheme());
+        if params.is_empty() {
+            scheme.into()
+        } else {
+            format!("{} {}", scheme, params.join(", "))
+        }
+    }
+}
+
+// RFC 6750 only allows %x20-21 / %x23-5B / %x5D-7E inside quoted parameter values
+fn quote(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '\'',
+            ' '..='~' => c,
+            _ => '?',
+        })
+        .collect()
+}
+
+impl fmt::Display for BearerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.code, &self.description) {
+            (Some(code), Some(description)) => write!(f, "{}: {}", code.as_str(), description),
+            (Some(code), None) => f.write_str(code.as_str()),
+            (None, Some(description)) => f.write_str(description),
+            (None, None) => f.write_str(self.status.canonical_reason().unwrap_or("error")),
+        }
+    }
+}
+
+impl ResponseError for BearerError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status);
+        // server side failures (e.g. broken key material) are not an authentication challenge
+        if self.status == StatusCode::UNAUTHORIZED
+            || self.status == StatusCode::BAD_REQUEST
+            || self.status == StatusCode::FORBIDDEN
+        {
+            if let Ok(value) = HeaderValue::from_str(&self.www_authenticate()) {
+                res.insert_header((header::WWW_AUTHENTICATE, value));
+            }
+        }
+        if let Some(dpop_nonce) = &self.dpop_nonce {
+            if let Ok(value) = HeaderValue::from_str(dpop_nonce) {
+                res.insert_header(("DPoP-Nonce", value));
+            }
+        }
+        if self.json_body {
+            let mut body = serde_json::Map::new();
+            if let Some(code) = self.code {
+                body.insert("error".into(), code.as_str().into());
+            }
+            if let Some(description) = &self.description {
+                body.insert("error_description".into(), description.as_str().into());
+            }
+            if let Some(scope) = &self.scope {
+                body.insert("scope".into(), scope.as_str().into());
+            }
+            res.json(body)
+        } else {
+            res.finish()
+        }
+    }
+}
+
+impl JwtDecodeErrors {
+    pub fn bearer_error_code(&self) -> (StatusCode, Option<BearerErrorCode>) {
+        match self {
+            JwtDecodeErrors::MissingToken => (StatusCode::UNAUTHORIZED, None),
+            JwtDecodeErrors::InvalidAuthHeader | JwtDecodeErrors::InvalidJWTHeader => (
+                StatusCode::BAD_REQUEST,
+                Some(BearerErrorCode::InvalidRequest),
+            ),
+            JwtDecodeErrors::InvalidJWTToken(e) => match e.kind() {
+                ErrorKind::InvalidEcdsaKey
+                | ErrorKind::InvalidRsaKey(_)
+                | ErrorKind::RsaFailedSigning
+                | ErrorKind::InvalidAlgorithmName
+                | ErrorKind::InvalidKeyFormat
+                | ErrorKind::MissingAlgorithm
+                | ErrorKind::Crypto(_) => (StatusCode::INTERNAL_SERVER_ERROR, None),
+                _ => (
+                    StatusCode::UNAUTHORIZED,
+                    Some(BearerErrorCode::InvalidToken),
+                ),
+            },
+            JwtDecodeErrors::UnknownKey
+            | JwtDecodeErrors::UnknownIssuer
+            | JwtDecodeErrors::Revoked
+            | JwtDecodeErrors::InactiveToken
+            | JwtDecodeErrors::InvalidJWE
+            | JwtDecodeErrors::UnsupportedJWEAlgorithm
+            | JwtDecodeErrors::JWEDecryptionFailed
+            | JwtDecodeErrors::CertificateMismatch => (
+                StatusCode::UNAUTHORIZED,
+                Some(BearerErrorCode::InvalidToken),
+            ),
+            JwtDecodeErrors::KeysUnavailable
+            | JwtDecodeErrors::RevocationUnavailable
+            | JwtDecodeErrors::IntrospectionUnavailable => (StatusCode::SERVICE_UNAVAILABLE, None),
+            JwtDecodeErrors::InvalidDPoPProof => (
+                StatusCode::UNAUTHORIZED,
+                Some(BearerErrorCode::InvalidDpopProof),
+            ),
+            JwtDecodeErrors::UseDPoPNonce(_) => (
+                StatusCode::UNAUTHORIZED,
+                Some(BearerErrorCode::UseDpopNonce),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rfc6750 {
+    realm: Option<String>,
+    error_description: bool,
+    json_body: bool,
+}
+
+impl Default for Rfc6750 {
+    fn default() -> Self {
+        Self {
+            realm: None,
+            error_description: true,
+            json_body: false,
+        }
+    }
+}
+
+impl Rfc6750 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    pub fn error_description(mut self, error_description: bool) -> Self {
+        self.error_description = error_description;
+        self
+    }
+
+    pub fn json_body(mut self, json_body: bool) -> Self {
+        self.json_body = json_body;
+        self
+    }
+
+    pub fn error(&self, e: &JwtDecodeErrors) -> BearerError {
+        let (status, code) = e.bearer_error_code();
+        let error = self.build(status, code, e.to_error_string());
+        match e {
+            JwtDecodeErrors::UseDPoPNonce(nonce) => error.dpop_nonce(nonce.clone()),
+            _ => error,
+        }
+    }
+
+    pub fn insufficient_scope(&self, scope: impl Into<String>) -> BearerError {
+        self.build(
+            StatusCode::FORBIDDEN,
+            Some(BearerErrorCode::InsufficientScope),
+            "Insufficient scope - token does not grant access to this resource".into(),
+        )
+        .scope(scope)
+    }
+
+    fn build(
+        &self,
+        status: StatusCode,
+        code: Option<BearerErrorCode>,
+        description: String,
+    ) -> BearerError {
+        let mut error = BearerError::new(status, code).json_body(self.json_body);
+        if let Some(realm) = &self.realm {
+            error = error.realm(realm.clone());
+        }
+        // a request without any credentials must not receive error details (RFC 6750 section 3.1)
+        if self.error_description && code.is_some() {
+            error = error.description(description);
+        }
+        error
+    }
+}
+
+impl<T> JwtMiddleware<T> {
+    pub fn rfc6750_errors(self, rfc6750: Rfc6750) -> Self {
+        self.error_handler(move |e| rfc6750.error(&e).into())
+    }
+}
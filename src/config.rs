@@ -0,0 +1,762 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use actix_web::http::header::{self, HeaderName};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::LocalBoxFuture;
+use jsonwebtoken::{Algorithm, DecodingKey, Header, Validation};
+use serde::Deserialize;
+
+use crate::{
+    CookieSource, FileKeyResolver, FormSource, HeaderSource, JwksError, JwtDecodeErrors,
+    JwtMiddleware, KeyFile, KeyFileError, KeyResolver, KeySet, QuerySource, ResolvedKey, Rfc6750,
+    TokenScheme, TokenSourceChain,
+};
+#[cfg(feature = "http")]
+use crate::{JwksKeyResolver, JwksSource};
+
+// claims `Validation::set_required_spec_claims` knows how to check
+const SPEC_CLAIMS: [&str; 5] = ["exp", "nbf", "aud", "iss", "sub"];
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtConfig {
+    pub keys: Vec<KeySourceConfig>,
+    pub algorithms: Vec<Algorithm>,
+    #[serde(default)]
+    pub issuers: Vec<String>,
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    #[serde(default = "default_leeway")]
+    pub leeway: u64,
+    // defaults to `exp`, plus `iss` and `aud` when issuers or audiences are configured
+    #[serde(default)]
+    pub required_claims: Option<Vec<String>>,
+    // tried in order, defaults to the `Authorization` header with the `Bearer` scheme
+    #[serde(default)]
+    pub token_sources: Vec<TokenSourceConfig>,
+    #[serde(default)]
+    pub error_style: ErrorStyleConfig,
+}
+
+fn default_leeway() -> u64 {
+    Validation::default().leeway
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum KeySourceConfig {
+    Secret {
+        secret: String,
+        #[serde(default)]
+        base64: bool,
+        #[serde(default)]
+        kid: Option<String>,
+        #[serde(default)]
+        algorithm: Option<Algorithm>,
+    },
+    // the algorithm defaults to the only HMAC algorithm in `algorithms`
+    SecretFile {
+        path: PathBuf,
+        #[serde(default)]
+        kid: Option<String>,
+        #[serde(default)]
+        algorithm: Option<Algorithm>,
+    },
+    // a key without `kid` also verifies tokens whose `kid` no other key matches, so there
+    // can only be one such key among the secrets and among the public keys
+    Pem {
+        path: PathBuf,
+        #[serde(default)]
+        kid: Option<String>,
+        #[serde(default)]
+        algorithm: Option<Algorithm>,
+    },
+    JwksFile {
+        path: PathBuf,
+    },
+    // requires the `http` feature, `preload` fetches the keys in `from_config` instead of on first use
+    JwksUrl {
+        url: String,
+        #[serde(default)]
+        refresh_interval_secs: Option<u64>,
+        #[serde(default)]
+        preload: bool,
+    },
+}
+
+impl fmt::Debug for KeySourceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // configs end up in startup logs, the secret doesn't
+            KeySourceConfig::Secret {
+                secret: _,
+                base64,
+                kid,
+                algorithm,
+            } => f
+                .debug_struct("Secret")
+                .field("base64", base64)
+                .field("kid", kid)
+                .field("algorithm", algorithm)
+                .finish_non_exhaustive(),
+            KeySourceConfig::SecretFile {
+                path,
+                kid,
+                algorithm,
+            } => f
+                .debug_struct("SecretFile")
+                .field("path", path)
+                .field("kid", kid)
+                .field("algorithm", algorithm)
+                .finish(),
+            KeySourceConfig::Pem {
+                path,
+                kid,
+                algorithm,
+            } => f
+                .debug_struct("Pem")
+                .field("path", path)
+                .field("kid", kid)
+                .field("algorithm", algorithm)
+                .finish(),
+            KeySourceConfig::JwksFile { path } => {
+                f.debug_struct("JwksFile").field("path", path).finish()
+            }
+            KeySourceConfig::JwksUrl {
+                url,
+                refresh_interval_secs,
+                preload,
+            } => f
+                .debug_struct("JwksUrl")
+                .field("url", url)
+                .field("refresh_interval_secs", refresh_interval_secs)
+                .field("preload", preload)
+                .finish(),
+        }
+    }
+}
+
+impl KeySourceConfig {
+    fn is_hmac(&self) -> bool {
+        matches!(
+            self,
+            KeySourceConfig::Secret { .. } | KeySourceConfig::SecretFile { .. }
+        )
+    }
+
+    fn algorithm(&self) -> Option<Algorithm> {
+        match self {
+            KeySourceConfig::Secret { algorithm, .. }
+            | KeySourceConfig::SecretFile { algorithm, .. }
+            | KeySourceConfig::Pem { algorithm, .. } => *algorithm,
+            KeySourceConfig::JwksFile { .. } | KeySourceConfig::JwksUrl { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TokenSourceConfig {
+    // `schemes` defaults to `Bearer` for the `Authorization` header, an empty list takes the raw value
+    Header {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        schemes: Option<Vec<String>>,
+    },
+    Cookie {
+        name: String,
+    },
+    Query {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    Form {
+        #[serde(default)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ErrorStyleConfig {
+    #[default]
+    Default,
+    Rfc6750 {
+        #[serde(default)]
+        realm: Option<String>,
+        #[serde(default = "default_error_description")]
+        error_description: bool,
+        #[serde(default)]
+        json_body: bool,
+    },
+}
+
+fn default_error_description() -> bool {
+    true
+}
+
+#[derive(Debug)]
+pub enum JwtConfigError {
+    Io(PathBuf, io::Error),
+    Parse(&'static str, String),
+    UnsupportedFormat(PathBuf),
+    FeatureDisabled(&'static str),
+    Env(String, String),
+    NoKeys,
+    NoAlgorithms,
+    // index into `keys` and the reason the key source is unusable
+    InvalidKey(usize, String),
+    KeyFile(KeyFileError),
+    Jwks(usize, JwksError),
+    UnknownRequiredClaim(String),
+    InvalidTokenSource(usize, String),
+}
+
+impl fmt::Display for JwtConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtConfigError::Io(path, e) => {
+                write!(f, "failed to read config file {}: {}", path.display(), e)
+            }
+            JwtConfigError::Parse(format, e) => write!(f, "invalid {} config: {}", format, e),
+            JwtConfigError::UnsupportedFormat(path) => write!(
+                f,
+                "unsupported config file {}, expected a .json, .toml, .yaml or .yml extension",
+                path.display()
+            ),
+            JwtConfigError::FeatureDisabled(feature) => {
+                write!(f, "the `{}` feature is required for this config", feature)
+            }
+            JwtConfigError::Env(var, e) => write!(f, "invalid environment variable {}: {}", var, e),
+            JwtConfigError::NoKeys => f.write_str("`keys` must contain at least one key source"),
+            JwtConfigError::NoAlgorithms => {
+                f.write_str("`algorithms` must contain at least one algorithm")
+            }
+            JwtConfigError::InvalidKey(index, e) => write!(f, "keys[{}]: {}", index, e),
+            JwtConfigError::KeyFile(e) => e.fmt(f),
+            JwtConfigError::Jwks(index, e) => write!(f, "keys[{}]: {}", index, e),
+            JwtConfigError::UnknownRequiredClaim(claim) => write!(
+                f,
+                "unknown required claim `{}`, expected one of {}",
+                claim,
+                SPEC_CLAIMS.join(", ")
+            ),
+            JwtConfigError::InvalidTokenSource(index, e) => {
+                write!(f, "token_sources[{}]: {}", index, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JwtConfigError {}
+
+impl JwtConfig {
+    pub fn from_json_str(json: &str) -> Result<Self, JwtConfigError> {
+        serde_json::from_str(json).map_err(|e| JwtConfigError::Parse("JSON", e.to_string()))
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(toml: &str) -> Result<Self, JwtConfigError> {
+        toml::from_str(toml).map_err(|e| JwtConfigError::Parse("TOML", e.to_string()))
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(yaml: &str) -> Result<Self, JwtConfigError> {
+        serde_yaml::from_str(yaml).map_err(|e| JwtConfigError::Parse("YAML", e.to_string()))
+    }
+
+    // the format is picked by the file extension
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, JwtConfigError> {
+        let path = path.as_ref();
+        let read = || fs::read_to_string(path).map_err(|e| JwtConfigError::Io(path.into(), e));
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json_str(&read()?),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml_str(&read()?),
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => Err(JwtConfigError::FeatureDisabled("toml")),
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Self::from_yaml_str(&read()?),
+            #[cfg(not(feature = "yaml"))]
+            Some("yaml" | "yml") => Err(JwtConfigError::FeatureDisabled("yaml")),
+            _ => Err(JwtConfigError::UnsupportedFormat(path.into())),
+        }
+    }
+
+    // reads `{prefix}_ALGORITHMS`, `_ISSUERS`, `_AUDIENCES`, `_LEEWAY`, `_REQUIRED_CLAIMS`,
+    // `_SECRET`, `_SECRET_FILE`, `_PEM_FILE`, `_JWKS_FILE`, `_JWKS_URL`, `_TOKEN_SOURCES`,
+    // `_ERROR_STYLE` and `_ERROR_REALM`, lists are comma-separated and token sources are
+    // written as `header:<name>[:<scheme>]`, `cookie:<name>`, `query:<name>` or `form:<name>`
+    pub fn from_env(prefix: &str) -> Result<Self, JwtConfigError> {
+        Self::from_vars(prefix, |name| match std::env::var(name) {
+            Ok(value) => Ok(Some(value)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(JwtConfigError::Env(name.into(), e.to_string())),
+        })
+    }
+
+    fn from_vars(
+        prefix: &str,
+        var: impl Fn(&str) -> Result<Option<String>, JwtConfigError>,
+    ) -> Result<Self, JwtConfigError> {
+        let name = |suffix: &str| format!("{}_{}", prefix, suffix);
+        let get = |suffix: &str| var(&name(suffix));
+        let list = |suffix: &str| -> Result<Vec<String>, JwtConfigError> {
+            Ok(get(suffix)?.as_deref().map(split_list).unwrap_or_default())
+        };
+        let invalid = |suffix: &str, e: String| JwtConfigError::Env(name(suffix), e);
+
+        let algorithms = list("ALGORITHMS")?
+            .iter()
+            .map(|algorithm| {
+                Algorithm::from_str(algorithm).map_err(|_| {
+                    invalid("ALGORITHMS", format!("unknown algorithm `{}`", algorithm))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let leeway = match get("LEEWAY")? {
+            Some(leeway) => leeway.trim().parse().map_err(|_| {
+                invalid("LEEWAY", format!("`{}` is not a number of seconds", leeway))
+            })?,
+            None => default_leeway(),
+        };
+        let required_claims = get("REQUIRED_CLAIMS")?.as_deref().map(split_list);
+
+        let mut keys = Vec::new();
+        if let Some(secret) = get("SECRET")? {
+            keys.push(KeySourceConfig::Secret {
+                secret,
+                base64: false,
+                kid: None,
+                algorithm: None,
+            });
+        }
+        if let Some(path) = get("SECRET_FILE")? {
+            keys.push(KeySourceConfig::SecretFile {
+                path: path.into(),
+                kid: None,
+                algorithm: None,
+            });
+        }
+        if let Some(path) = get("PEM_FILE")? {
+            keys.push(KeySourceConfig::Pem {
+                path: path.into(),
+                kid: None,
+                algorithm: None,
+            });
+        }
+        if let Some(path) = get("JWKS_FILE")? {
+            keys.push(KeySourceConfig::JwksFile { path: path.into() });
+        }
+        if let Some(url) = get("JWKS_URL")? {
+            keys.push(KeySourceConfig::JwksUrl {
+                url,
+                refresh_interval_secs: None,
+                preload: false,
+            });
+        }
+
+        let token_sources = list("TOKEN_SOURCES")?
+            .iter()
+            .map(|source| {
+                let mut parts = source.splitn(3, ':');
+                let kind = parts.next().unwrap_or_default();
+                let source_name = parts.next().map(str::to_owned);
+                let scheme = parts.next();
+                let unknown = || {
+                    invalid(
+                        "TOKEN_SOURCES",
+                        format!("invalid token source `{}`", source),
+                    )
+                };
+                match (kind, source_name, scheme) {
+                    ("header", name, scheme) => Ok(TokenSourceConfig::Header {
+                        name,
+                        schemes: scheme.map(|scheme| vec![scheme.to_owned()]),
+                    }),
+                    ("cookie", Some(name), None) => Ok(TokenSourceConfig::Cookie { name }),
+                    ("query", name, None) => Ok(TokenSourceConfig::Query { name }),
+                    ("form", name, None) => Ok(TokenSourceConfig::Form { name }),
+                    _ => Err(unknown()),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        let error_style = match get("ERROR_STYLE")?.as_deref().map(str::trim) {
+            None | Some("default") => ErrorStyleConfig::Default,
+            Some("rfc6750") => ErrorStyleConfig::Rfc6750 {
+                realm: get("ERROR_REALM")?,
+                error_description: default_error_description(),
+                json_body: false,
+            },
+            Some(style) => {
+                return Err(invalid(
+                    "ERROR_STYLE",
+                    format!(
+                        "unknown error style `{}`, expected `default` or `rfc6750`",
+                        style
+                    ),
+                ))
+            }
+        };
+
+        Ok(Self {
+            keys,
+            algorithms,
+            issuers: list("ISSUERS")?,
+            audiences: list("AUDIENCES")?,
+            leeway,
+            required_claims,
+            token_sources,
+            error_style,
+        })
+    }
+
+    pub fn validation(&self) -> Result<Validation, JwtConfigError> {
+        let Some(&first) = self.algorithms.first() else {
+            return Err(JwtConfigError::NoAlgorithms);
+        };
+        let mut validation = Validation::new(first);
+        validation.algorithms = self.algorithms.clone();
+        validation.leeway = self.leeway;
+        let mut required = vec!["exp".to_owned()];
+        if !self.issuers.is_empty() {
+            validation.set_issuer(&self.issuers);
+            required.push("iss".into());
+        }
+        if self.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audiences);
+            required.push("aud".into());
+        }
+        let required = self.required_claims.as_ref().unwrap_or(&required);
+        if let Some(claim) = required
+            .iter()
+            .find(|claim| !SPEC_CLAIMS.contains(&claim.as_str()))
+        {
+            return Err(JwtConfigError::UnknownRequiredClaim(claim.clone()));
+        }
+        validation.set_required_spec_claims(required);
+        Ok(validation)
+    }
+
+    fn key_resolver(&self) -> Result<ConfiguredKeys, JwtConfigError> {
+        if self.keys.is_empty() {
+            return Err(JwtConfigError::NoKeys);
+        }
+        let hmac: Vec<Algorithm> = self
+            .algorithms
+            .iter()
+            .copied()
+            .filter(|algorithm| is_hmac(*algorithm))
+            .collect();
+        let mut secrets = Vec::new();
+        let mut secret_files = Vec::new();
+        let mut public_files = Vec::new();
+        let mut unnamed_hmac: Option<Box<dyn KeyResolver>> = None;
+        let mut unnamed_public: Option<Box<dyn KeyResolver>> = None;
+        let mut keys = ConfiguredKeys::default();
+        for (index, source) in self.keys.iter().enumerate() {
+            let invalid = |e: &str| JwtConfigError::InvalidKey(index, e.into());
+            // a key that can't verify any allowed algorithm is most likely a typo in the config
+            if let Some(algorithm) = source.algorithm() {
+                if !self.algorithms.contains(&algorithm) {
+                    return Err(invalid(&format!(
+                        "algorithm {:?} is not listed in `algorithms`",
+                        algorithm
+                    )));
+                }
+            }
+            if source.is_hmac() && hmac.is_empty() {
+                return Err(invalid(
+                    "HMAC secret but `algorithms` has no HMAC algorithm",
+                ));
+            }
+            if !source.is_hmac() && hmac.len() == self.algorithms.len() {
+                return Err(invalid(
+                    "public key but `algorithms` only has HMAC algorithms",
+                ));
+            }
+            match source {
+                KeySourceConfig::Secret {
+                    secret,
+                    base64,
+                    kid,
+                    algorithm,
+                } => {
+                    let secret = match base64 {
+                        true => STANDARD
+                            .decode(secret.trim())
+                            .map_err(|e| invalid(&format!("invalid base64 secret: {}", e)))?,
+                        false => secret.clone().into_bytes(),
+                    };
+                    if secret.is_empty() {
+                        return Err(invalid("secret is empty"));
+                    }
+                    let mut key = ResolvedKey::new(DecodingKey::from_secret(&secret));
+                    key.kid = kid.clone();
+                    key.algorithm = *algorithm;
+                    match kid {
+                        Some(_) => secrets.push(key),
+                        None => set_unnamed(&mut unnamed_hmac, index, Box::new(key))?,
+                    }
+                }
+                KeySourceConfig::SecretFile {
+                    path,
+                    kid,
+                    algorithm,
+                } => {
+                    let algorithm = match (algorithm, hmac.as_slice()) {
+                        (Some(algorithm), _) => *algorithm,
+                        (None, [algorithm]) => *algorithm,
+                        (None, _) => return Err(invalid(
+                            "`algorithm` is required when `algorithms` has several HMAC algorithms",
+                        )),
+                    };
+                    let file = KeyFile::secret(path, algorithm);
+                    match kid {
+                        Some(kid) => secret_files.push(file.kid(kid)),
+                        None => set_unnamed(&mut unnamed_hmac, index, load_file(file)?)?,
+                    }
+                }
+                KeySourceConfig::Pem {
+                    path,
+                    kid,
+                    algorithm,
+                } => {
+                    let mut file = KeyFile::pem(path);
+                    if let Some(algorithm) = algorithm {
+                        file = file.algorithm(*algorithm);
+                    }
+                    match kid {
+                        Some(kid) => public_files.push(file.kid(kid)),
+                        None => set_unnamed(&mut unnamed_public, index, load_file(file)?)?,
+                    }
+                }
+                KeySourceConfig::JwksFile { path } => public_files.push(KeyFile::jwks(path)),
+                #[cfg(feature = "http")]
+                KeySourceConfig::JwksUrl {
+                    url,
+                    refresh_interval_secs,
+                    preload,
+                } => {
+                    if !url.starts_with("https://") && !url.starts_with("http://") {
+                        return Err(invalid(&format!("`{}` is not an http(s) URL", url)));
+                    }
+                    let mut resolver = JwksKeyResolver::new(JwksSource::Url(url.clone()));
+                    if let Some(secs) = refresh_interval_secs {
+                        resolver = resolver.refresh_interval(std::time::Duration::from_secs(*secs));
+                    }
+                    if *preload {
+                        resolver
+                            .refresh()
+                            .map_err(|e| JwtConfigError::Jwks(index, e))?;
+                    }
+                    keys.public.push(Box::new(resolver));
+                }
+                #[cfg(not(feature = "http"))]
+                KeySourceConfig::JwksUrl { .. } => {
+                    return Err(JwtConfigError::FeatureDisabled("http"))
+                }
+            }
+        }
+        if !secrets.is_empty() {
+            keys.hmac.push(Box::new(KeySet::from_keys(secrets)));
+        }
+        for (files, unnamed, resolvers) in [
+            (secret_files, unnamed_hmac, &mut keys.hmac),
+            (public_files, unnamed_public, &mut keys.public),
+        ] {
+            if !files.is_empty() {
+                let resolver = FileKeyResolver::load(files).map_err(JwtConfigError::KeyFile)?;
+                resolvers.insert(0, Box::new(resolver));
+            }
+            // only tried once every key with a `kid` turned the token down
+            if let Some(unnamed) = unnamed {
+                resolvers.push(Box::new(UnnamedKey(unnamed)));
+            }
+        }
+        Ok(keys)
+    }
+
+    fn token_source(&self) -> Result<Option<TokenSourceChain>, JwtConfigError> {
+        if self.token_sources.is_empty() {
+            return Ok(None);
+        }
+        let mut chain = TokenSourceChain::new();
+        for (index, source) in self.token_sources.iter().enumerate() {
+            let invalid = |e: String| JwtConfigError::InvalidTokenSource(index, e);
+            chain = match source {
+                TokenSourceConfig::Header { name, schemes } => {
+                    let name = match name {
+                        Some(name) => HeaderName::from_str(name)
+                            .map_err(|_| invalid(format!("invalid header name `{}`", name)))?,
+                        None => header::AUTHORIZATION,
+                    };
+                    let schemes = match schemes {
+                        Some(schemes) => {
+                            schemes.iter().map(|scheme| token_scheme(scheme)).collect()
+                        }
+                        None if name == header::AUTHORIZATION => vec![TokenScheme::Bearer],
+                        None => Vec::new(),
+                    };
+                    let mut source = HeaderSource::new(name);
+                    let mut schemes = schemes.into_iter();
+                    if let Some(scheme) = schemes.next() {
+                        source = source.scheme(scheme);
+                    }
+                    chain.source(schemes.fold(source, HeaderSource::or_scheme))
+                }
+                TokenSourceConfig::Cookie { name } if name.is_empty() => {
+                    return Err(invalid("cookie name is empty".into()))
+                }
+                TokenSourceConfig::Cookie { name } => chain.source(CookieSource::new(name)),
+                TokenSourceConfig::Query { name } => chain.source(match name {
+                    Some(name) => QuerySource::new(name),
+                    None => QuerySource::access_token(),
+                }),
+                TokenSourceConfig::Form { name } => chain.source(match name {
+                    Some(name) => FormSource::new(name),
+                    None => FormSource::access_token(),
+                }),
+            };
+        }
+        Ok(Some(chain))
+    }
+}
+
+fn load_file(file: KeyFile) -> Result<Box<dyn KeyResolver>, JwtConfigError> {
+    let resolver = FileKeyResolver::load(vec![file]).map_err(JwtConfigError::KeyFile)?;
+    Ok(Box::new(resolver))
+}
+
+// which key a token without a matching `kid` belongs to would be a guess with several of them
+fn set_unnamed(
+    unnamed: &mut Option<Box<dyn KeyResolver>>,
+    index: usize,
+    resolver: Box<dyn KeyResolver>,
+) -> Result<(), JwtConfigError> {
+    if unnamed.is_some() {
+        return Err(JwtConfigError::InvalidKey(
+            index,
+            "only one key of each family can be configured without `kid`".into(),
+        ));
+    }
+    *unnamed = Some(resolver);
+    Ok(())
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn token_scheme(scheme: &str) -> TokenScheme {
+    match scheme {
+        _ if scheme.eq_ignore_ascii_case("bearer") => TokenScheme::Bearer,
+        _ => TokenScheme::Custom(scheme.to_owned()),
+    }
+}
+
+fn is_hmac(algorithm: Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    )
+}
+
+// HMAC secrets and public keys are kept apart so a token is only matched against keys of
+// its algorithm's family, within a family files are tried before JWKS URLs
+#[derive(Default)]
+struct ConfiguredKeys {
+    hmac: Vec<Box<dyn KeyResolver>>,
+    public: Vec<Box<dyn KeyResolver>>,
+}
+
+impl KeyResolver for ConfiguredKeys {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(async move {
+            let resolvers = match is_hmac(header.alg) {
+                true => &self.hmac,
+                false => &self.public,
+            };
+            let mut error = JwtDecodeErrors::UnknownKey;
+            for resolver in resolvers {
+                match resolver.resolve(header).await {
+                    Ok(key) => return Ok(key),
+                    // an unavailable JWKS endpoint is more useful to report than an unknown key
+                    Err(JwtDecodeErrors::UnknownKey) => {}
+                    Err(e) => error = e,
+                }
+            }
+            Err(error)
+        })
+    }
+
+    fn generation(&self) -> u64 {
+        self.hmac
+            .iter()
+            .chain(&self.public)
+            .fold(0, |generation, resolver| {
+                generation.wrapping_add(resolver.generation())
+            })
+    }
+}
+
+// a key configured without `kid`, e.g. a PEM file, verifies tokens with any `kid`
+struct UnnamedKey(Box<dyn KeyResolver>);
+
+impl KeyResolver for UnnamedKey {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(async move {
+            let mut header = header.clone();
+            header.kid = None;
+            self.0.resolve(&header).await
+        })
+    }
+
+    fn generation(&self) -> u64 {
+        self.0.generation()
+    }
+}
+
+impl<T> JwtMiddleware<T> {
+    // validates the whole config and loads key files, so mistakes surface at startup
+    pub fn from_config(config: &JwtConfig) -> Result<Self, JwtConfigError> {
+        let validation = config.validation()?;
+        let mut middleware = Self::with_key_resolver(config.key_resolver()?, validation);
+        if let Some(token_source) = config.token_source()? {
+            middleware = middleware.token_source(token_source);
+        }
+        if let ErrorStyleConfig::Rfc6750 {
+            realm,
+            error_description,
+            json_body,
+        } = &config.error_style
+        {
+            let mut rfc6750 = Rfc6750::new()
+                .error_description(*error_description)
+                .json_body(*json_body);
+            if let Some(realm) = realm {
+                rfc6750 = rfc6750.realm(realm);
+            }
+            middleware = middleware.rfc6750_errors(rfc6750);
+        }
+        Ok(middleware)
+    }
+}
@@ -0,0 +1,163 @@
+use std::{fmt, sync::Arc};
+
+use arc_swap::ArcSwap;
+use futures::future::LocalBoxFuture;
+use jsonwebtoken::{DecodingKey, Header, Validation};
+
+use crate::{IssuerRegistry, JwtDecodeErrors, JwtVerifier, KeyResolver, KeySet, ResolvedKey};
+
+#[derive(Debug)]
+pub enum ConfigUpdateError {
+    // the middleware verifies tokens of several issuers, or no JWTs at all
+    NotSingleKey,
+    MissingKeyId,
+}
+
+impl fmt::Display for ConfigUpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigUpdateError::NotSingleKey => f.write_str(
+                "middleware is not configured with a single key resolver, use `replace` or `set_issuers`",
+            ),
+            ConfigUpdateError::MissingKeyId => f.write_str("additional keys need a `kid`"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigUpdateError {}
+
+// swaps the keys and validation used by every `JwtService` created from the middleware,
+// requests in flight finish with the configuration they started with
+#[derive(Clone)]
+pub struct JwtConfigHandle {
+    verifier: Arc<ArcSwap<JwtVerifier>>,
+}
+
+impl JwtConfigHandle {
+    pub(crate) fn new(verifier: Arc<ArcSwap<JwtVerifier>>) -> Self {
+        Self { verifier }
+    }
+
+    pub fn replace<R>(&self, key_resolver: R, validation: Validation)
+    where
+        R: KeyResolver + 'static,
+    {
+        self.verifier.store(Arc::new(JwtVerifier::Key {
+            key_resolver: Arc::new(key_resolver),
+            validation,
+        }));
+    }
+
+    pub fn set_issuers(&self, issuers: IssuerRegistry) {
+        self.verifier.store(Arc::new(JwtVerifier::Issuers(issuers)));
+    }
+
+    pub fn set_key(&self, decoding_key: DecodingKey) -> Result<(), ConfigUpdateError> {
+        self.set_key_resolver(ResolvedKey::new(decoding_key))
+    }
+
+    // tokens are matched by `kid`, a token without one is only accepted when there's a single key
+    pub fn set_keys(
+        &self,
+        keys: impl IntoIterator<Item = ResolvedKey>,
+    ) -> Result<(), ConfigUpdateError> {
+        self.set_key_resolver(KeySet::from_keys(keys))
+    }
+
+    pub fn set_key_resolver<R>(&self, key_resolver: R) -> Result<(), ConfigUpdateError>
+    where
+        R: KeyResolver + 'static,
+    {
+        let key_resolver: Arc<dyn KeyResolver> = Arc::new(key_resolver);
+        self.update(|current| match current {
+            JwtVerifier::Key { validation, .. } => Some(JwtVerifier::Key {
+                key_resolver: key_resolver.clone(),
+                validation: validation.clone(),
+            }),
+            _ => None,
+        })
+    }
+
+    // accepted next to the current keys, e.g. an emergency key while the JWKS isn't updated yet
+    pub fn add_key(&self, key: ResolvedKey) -> Result<(), ConfigUpdateError> {
+        if key.kid.is_none() {
+            return Err(ConfigUpdateError::MissingKeyId);
+        }
+        let keys = Arc::new(KeySet::from_keys([key]));
+        self.update(|current| match current {
+            JwtVerifier::Key {
+                key_resolver,
+                validation,
+            } => Some(JwtVerifier::Key {
+                key_resolver: Arc::new(AdditionalKeys {
+                    keys: keys.clone(),
+                    fallback: key_resolver.clone(),
+                }),
+                validation: validation.clone(),
+            }),
+            _ => None,
+        })
+    }
+
+    pub fn set_validation(&self, validation: Validation) -> Result<(), ConfigUpdateError> {
+        self.update(|current| match current {
+            JwtVerifier::Key { key_resolver, .. } => Some(JwtVerifier::Key {
+                key_resolver: key_resolver.clone(),
+                validation: validation.clone(),
+            }),
+            _ => None,
+        })
+    }
+
+    // the current validation, e.g. to change a single setting and pass it to `set_validation`
+    pub fn validation(&self) -> Option<Validation> {
+        match &**self.verifier.load() {
+            JwtVerifier::Key { validation, .. } => Some(validation.clone()),
+            _ => None,
+        }
+    }
+
+    // `update` can run more than once when another update is stored concurrently
+    fn update(
+        &self,
+        update: impl Fn(&JwtVerifier) -> Option<JwtVerifier>,
+    ) -> Result<(), ConfigUpdateError> {
+        let mut result = Ok(());
+        self.verifier.rcu(|current| match update(current) {
+            Some(verifier) => {
+                result = Ok(());
+                Arc::new(verifier)
+            }
+            None => {
+                result = Err(ConfigUpdateError::NotSingleKey);
+                current.clone()
+            }
+        });
+        result
+    }
+}
+
+struct AdditionalKeys {
+    keys: Arc<KeySet>,
+    fallback: Arc<dyn KeyResolver>,
+}
+
+impl KeyResolver for AdditionalKeys {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        match header
+            .kid
+            .as_deref()
+            .and_then(|kid| self.keys.find(Some(kid)))
+        {
+            Some(key) => Box::pin(futures::future::ready(Ok(key))),
+            None => self.fallback.resolve(header),
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.fallback.generation()
+    }
+}
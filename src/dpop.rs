@@ -0,0 +1,436 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    dev::ServiceRequest,
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::{get_current_timestamp, Algorithm, DecodingKey, Validation};
+use ring::{digest, rand::SystemRandom};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    signing::{hash_token, random_token},
+    JwtDecodeErrors, RegisteredClaims, TokenScheme,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DpopMode {
+    // bearer tokens are still accepted unless they are bound to a key with `cnf.jkt`
+    #[default]
+    Optional,
+    Required,
+}
+
+// backends that can't reach their storage should return `false` so proofs fail closed
+pub trait DpopReplayCache: Send + Sync {
+    fn insert<'a>(&'a self, key: &'a str, expires_at: u64) -> LocalBoxFuture<'a, bool>;
+}
+
+// expired entries are purged at most once per interval instead of on every proof
+const PURGE_INTERVAL: u64 = 30;
+
+#[derive(Default)]
+struct SeenProofs {
+    seen: HashMap<String, u64>,
+    next_purge: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryDpopReplayCache {
+    seen: Arc<Mutex<SeenProofs>>,
+}
+
+impl MemoryDpopReplayCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DpopReplayCache for MemoryDpopReplayCache {
+    fn insert<'a>(&'a self, key: &'a str, expires_at: u64) -> LocalBoxFuture<'a, bool> {
+        let now = get_current_timestamp();
+        let mut proofs = self.seen.lock().unwrap();
+        if now >= proofs.next_purge {
+            proofs.seen.retain(|_, expires_at| *expires_at > now);
+            proofs.next_purge = now + PURGE_INTERVAL;
+        }
+        let fresh = proofs.seen.get(key).is_none_or(|seen| *seen <= now);
+        if fresh {
+            proofs.seen.insert(key.to_owned(), expires_at);
+        }
+        Box::pin(ready(fresh))
+    }
+}
+
+pub trait DpopNonceSource: Send + Sync {
+    fn current(&self) -> String;
+
+    fn is_valid(&self, nonce: &str) -> bool;
+}
+
+struct NonceState {
+    current: String,
+    previous: Option<String>,
+    rotated_at: Instant,
+}
+
+// the previous nonce stays valid for one more interval so clients racing a rotation aren't rejected
+pub struct RotatingDpopNonce {
+    interval: Duration,
+    random: SystemRandom,
+    state: Mutex<NonceState>,
+}
+
+impl RotatingDpopNonce {
+    pub fn new(interval: Duration) -> Self {
+        let random = SystemRandom::new();
+        Self {
+            interval,
+            state: Mutex::new(NonceState {
+                current: random_token(&random, 16).unwrap_or_default(),
+                previous: None,
+                rotated_at: Instant::now(),
+            }),
+            random,
+        }
+    }
+
+    fn rotate(&self, state: &mut NonceState) {
+        if state.rotated_at.elapsed() < self.interval {
+            return;
+        }
+        let Some(next) = random_token(&self.random, 16) else {
+            return;
+        };
+        state.previous = Some(std::mem::replace(&mut state.current, next));
+        state.rotated_at = Instant::now();
+    }
+}
+
+impl DpopNonceSource for RotatingDpopNonce {
+    fn current(&self) -> String {
+        let mut state = self.state.lock().unwrap();
+        self.rotate(&mut state);
+        state.current.clone()
+    }
+
+    fn is_valid(&self, nonce: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.rotate(&mut state);
+        state.current == nonce || state.previous.as_deref() == Some(nonce)
+    }
+}
+
+#[derive(Deserialize)]
+struct ProofClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: u64,
+    ath: Option<String>,
+    nonce: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Dpop {
+    mode: DpopMode,
+    algorithms: Vec<Algorithm>,
+    max_age: Duration,
+    leeway: Duration,
+    origin: Option<String>,
+    forwarded_origin: bool,
+    replay_cache: Arc<dyn DpopReplayCache>,
+    nonces: Option<Arc<dyn DpopNonceSource>>,
+}
+
+impl Default for Dpop {
+    fn default() -> Self {
+        Self {
+            mode: DpopMode::default(),
+            algorithms: vec![
+                Algorithm::ES256,
+                Algorithm::ES384,
+                Algorithm::RS256,
+                Algorithm::RS384,
+                Algorithm::RS512,
+                Algorithm::PS256,
+                Algorithm::PS384,
+                Algorithm::PS512,
+                Algorithm::EdDSA,
+            ],
+            max_age: Duration::from_secs(60),
+            leeway: Duration::from_secs(5),
+            origin: None,
+            forwarded_origin: false,
+            replay_cache: Arc::new(MemoryDpopReplayCache::new()),
+            nonces: None,
+        }
+    }
+}
+
+impl Dpop {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(mut self, mode: DpopMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    // only asymmetric algorithms make sense here, proofs are signed with the client's private key
+    pub fn algorithms(mut self, algorithms: Vec<Algorithm>) -> Self {
+        self.algorithms = algorithms;
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    // the public origin `htu` is compared against, e.g. when running behind a reverse proxy
+    pub fn origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    // take scheme and host from `Forwarded` / `X-Forwarded-*` when no origin is set, only safe
+    // behind a proxy that overwrites these headers, otherwise clients can pick the `htu` to match
+    pub fn forwarded_origin(mut self, forwarded_origin: bool) -> Self {
+        self.forwarded_origin = forwarded_origin;
+        self
+    }
+
+    pub fn replay_cache<C>(mut self, replay_cache: C) -> Self
+    where
+        C: DpopReplayCache + 'static,
+    {
+        self.replay_cache = Arc::new(replay_cache);
+        self
+    }
+
+    pub fn nonce<N>(mut self, nonces: N) -> Self
+    where
+        N: DpopNonceSource + 'static,
+    {
+        self.nonces = Some(Arc::new(nonces));
+        self
+    }
+
+    pub(crate) fn is_required(&self) -> bool {
+        self.mode == DpopMode::Required
+    }
+
+    pub(crate) async fn check(
+        &self,
+        req: &ServiceRequest,
+        token: &str,
+        registered: &RegisteredClaims,
+    ) -> Result<(), JwtDecodeErrors> {
+        let jkt = registered.cnf.as_ref().and_then(|cnf| cnf.jkt.as_deref());
+        let dpop_scheme = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| TokenScheme::Custom("DPoP".into()).strip(value).is_some());
+        if !dpop_scheme {
+            // a key-bound token presented as a bearer token must be rejected (RFC 9449 section 7.2)
+            if jkt.is_some() || self.is_required() {
+                return Err(JwtDecodeErrors::InvalidDPoPProof);
+            }
+            return Ok(());
+        }
+
+        let mut proofs = req.headers().get_all("DPoP");
+        let (Some(proof), None) = (proofs.next(), proofs.next()) else {
+            return Err(JwtDecodeErrors::InvalidDPoPProof);
+        };
+        let proof = proof
+            .to_str()
+            .map_err(|_| JwtDecodeErrors::InvalidDPoPProof)?;
+        let thumbprint = self.verify_proof(proof, req, token).await?;
+        match jkt {
+            Some(jkt) if jkt == thumbprint => Ok(()),
+            _ => Err(JwtDecodeErrors::InvalidDPoPProof),
+        }
+    }
+
+    // returns the JWK thumbprint of the key that signed the proof
+    async fn verify_proof(
+        &self,
+        proof: &str,
+        req: &ServiceRequest,
+        token: &str,
+    ) -> Result<String, JwtDecodeErrors> {
+        let invalid = |_| JwtDecodeErrors::InvalidDPoPProof;
+        let header = jsonwebtoken::decode_header(proof).map_err(invalid)?;
+        if header.typ.as_deref() != Some("dpop+jwt") || !self.algorithms.contains(&header.alg) {
+            return Err(JwtDecodeErrors::InvalidDPoPProof);
+        }
+        let raw_jwk = raw_header_jwk(proof).ok_or(JwtDecodeErrors::InvalidDPoPProof)?;
+        let jwk = header.jwk.ok_or(JwtDecodeErrors::InvalidDPoPProof)?;
+        if raw_jwk.contains_key("d") {
+            return Err(JwtDecodeErrors::InvalidDPoPProof);
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(invalid)?;
+        let mut validation = Validation::new(header.alg);
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        validation.required_spec_claims.clear();
+        let claims = jsonwebtoken::decode::<ProofClaims>(proof, &key, &validation)
+            .map_err(invalid)?
+            .claims;
+
+        let now = get_current_timestamp();
+        // `iat` comes from the client, a huge value must not overflow into the past
+        let fresh = claims.iat.saturating_add(self.max_age.as_secs()) >= now
+            && claims.iat <= now + self.leeway.as_secs();
+        let ath = URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, token.as_bytes()));
+        let htu = normalize_htu(&claims.htu)
+            .is_some_and(|htu| Some(htu) == normalize_htu(&self.request_htu(req)));
+        if !claims.htm.eq_ignore_ascii_case(req.method().as_str())
+            || !htu
+            || !fresh
+            || claims.ath.as_deref() != Some(ath.as_str())
+        {
+            return Err(JwtDecodeErrors::InvalidDPoPProof);
+        }
+        if let Some(nonces) = &self.nonces {
+            if !claims
+                .nonce
+                .as_deref()
+                .is_some_and(|nonce| nonces.is_valid(nonce))
+            {
+                return Err(JwtDecodeErrors::UseDPoPNonce(nonces.current()));
+            }
+        }
+
+        let thumbprint = jwk_thumbprint(&raw_jwk).ok_or(JwtDecodeErrors::InvalidDPoPProof)?;
+        let replay_key = hash_token(&format!("{}:{}", thumbprint, claims.jti));
+        let expires_at = claims
+            .iat
+            .saturating_add(self.max_age.as_secs())
+            .saturating_add(self.leeway.as_secs());
+        if !self.replay_cache.insert(&replay_key, expires_at).await {
+            return Err(JwtDecodeErrors::InvalidDPoPProof);
+        }
+        Ok(thumbprint)
+    }
+
+    // the token from an `Authorization` header with the `DPoP` scheme, checked before the
+    // configured token source so DPoP works with any of them
+    pub(crate) fn extract(&self, req: &ServiceRequest) -> Option<Result<String, JwtDecodeErrors>> {
+        let value = req.headers().get(header::AUTHORIZATION)?;
+        let Ok(value) = value.to_str() else {
+            return Some(Err(JwtDecodeErrors::InvalidAuthHeader));
+        };
+        match TokenScheme::Custom("DPoP".into()).strip(value)? {
+            "" => Some(Err(JwtDecodeErrors::InvalidJWTHeader)),
+            token => Some(Ok(token.to_owned())),
+        }
+    }
+
+    fn request_htu(&self, req: &ServiceRequest) -> String {
+        if let Some(origin) = &self.origin {
+            return format!("{}{}", origin.trim_end_matches('/'), req.path());
+        }
+        if self.forwarded_origin {
+            let info = req.connection_info();
+            return format!("{}://{}{}", info.scheme(), info.host(), req.path());
+        }
+        let scheme = match req.app_config().secure() {
+            true => "https",
+            false => "http",
+        };
+        let host = req
+            .uri()
+            .authority()
+            .map(|authority| authority.as_str())
+            .or_else(|| {
+                req.headers()
+                    .get(header::HOST)
+                    .and_then(|host| host.to_str().ok())
+            })
+            .unwrap_or_else(|| req.app_config().host());
+        format!("{}://{}{}", scheme, host, req.path())
+    }
+}
+
+// added to every `use_dpop_nonce` response, whatever the error handler built, so clients
+// can retry with the nonce (RFC 9449 section 9)
+pub(crate) fn insert_nonce_challenge(headers: &mut HeaderMap, nonce: &str) {
+    let Ok(nonce) = HeaderValue::from_str(nonce) else {
+        return;
+    };
+    headers.insert(HeaderName::from_static("dpop-nonce"), nonce);
+    if !headers.contains_key(header::WWW_AUTHENTICATE) {
+        headers.insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static(
+                "DPoP error=\"use_dpop_nonce\", error_description=\"Resource server requires nonce in DPoP proof\"",
+            ),
+        );
+    }
+}
+
+fn raw_header_jwk(proof: &str) -> Option<Map<String, Value>> {
+    let header = URL_SAFE_NO_PAD.decode(proof.split('.').next()?).ok()?;
+    match serde_json::from_slice::<Value>(&header)
+        .ok()?
+        .get_mut("jwk")?
+        .take()
+    {
+        Value::Object(jwk) => Some(jwk),
+        _ => None,
+    }
+}
+
+// RFC 7638, only the required members in lexicographic order
+fn jwk_thumbprint(jwk: &Map<String, Value>) -> Option<String> {
+    let members: &[&str] = match jwk.get("kty")?.as_str()? {
+        "EC" => &["crv", "kty", "x", "y"],
+        "RSA" => &["e", "kty", "n"],
+        "OKP" => &["crv", "kty", "x"],
+        _ => return None,
+    };
+    let members = members
+        .iter()
+        .map(|member| {
+            let value = jwk.get(*member)?.as_str()?;
+            Some(format!("\"{}\":{}", member, Value::from(value)))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let canonical = format!("{{{}}}", members.join(","));
+    Some(URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, canonical.as_bytes())))
+}
+
+// `htu` is compared without query and fragment, with case-insensitive scheme and host
+// and without default ports (RFC 9449 section 4.3)
+fn normalize_htu(htu: &str) -> Option<String> {
+    let htu = htu.split(['?', '#']).next()?;
+    let (scheme, rest) = htu.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let authority = authority.to_ascii_lowercase();
+    let authority = match (scheme.as_str(), authority.rsplit_once(':')) {
+        ("https", Some((host, "443"))) | ("http", Some((host, "80"))) => host.to_owned(),
+        _ => authority,
+    };
+    Some(format!("{}://{}{}", scheme, authority, path))
+}
@@ -0,0 +1,105 @@
+use std::{
+    fmt,
+    future::{ready, Ready},
+    ops::Deref,
+    sync::Arc,
+};
+
+use actix_web::{
+    dev::Payload, error::ErrorUnauthorized, web, Error, FromRequest, HttpMessage, HttpRequest,
+};
+
+pub struct Claims<T>(Arc<T>);
+
+impl<T> Claims<T> {
+    pub fn new(claims: T) -> Self {
+        Self(Arc::new(claims))
+    }
+
+    pub(crate) fn from_arc(claims: Arc<T>) -> Self {
+        Self(claims)
+    }
+
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T> Clone for Claims<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Claims<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Claims<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Claims").field(&self.0).finish()
+    }
+}
+
+impl<T: Clone + 'static> Claims<T> {
+    pub fn from_request_extensions(req: &HttpRequest) -> Option<Self> {
+        let mut extensions = req.extensions_mut();
+        if let Some(claims) = extensions.get::<Claims<T>>() {
+            return Some(claims.clone());
+        }
+        // plain `T` stays in place for `extensions().get::<T>()`, so the first extraction in a
+        // request clones the claims once and later ones share that copy; with
+        // `JwtMiddleware::store_verified_token` the middleware stores a shared `Claims<T>`
+        // directly and nothing is cloned
+        let claims = Claims::new(extensions.get::<T>()?.clone());
+        extensions.insert(claims.clone());
+        Some(claims)
+    }
+}
+
+impl<T: Clone + 'static> FromRequest for Claims<T> {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            Self::from_request_extensions(req)
+                .ok_or_else(|| ClaimsConfig::from_req(req).error(req)),
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ClaimsConfig {
+    #[allow(clippy::type_complexity)]
+    err_handler: Option<Arc<dyn Fn(&HttpRequest) -> Error + Send + Sync>>,
+}
+
+impl ClaimsConfig {
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Error + Send + Sync + 'static,
+    {
+        self.err_handler = Some(Arc::new(f));
+        self
+    }
+
+    pub(crate) fn from_req(req: &HttpRequest) -> &Self {
+        req.app_data::<Self>()
+            .or_else(|| req.app_data::<web::Data<Self>>().map(|d| d.as_ref()))
+            .unwrap_or(&DEFAULT_CLAIMS_CONFIG)
+    }
+
+    pub(crate) fn error(&self, req: &HttpRequest) -> Error {
+        match &self.err_handler {
+            Some(err_handler) => (err_handler)(req),
+            None => ErrorUnauthorized("Unauthorized - request does not contain valid JWT claims"),
+        }
+    }
+}
+
+static DEFAULT_CLAIMS_CONFIG: ClaimsConfig = ClaimsConfig { err_handler: None };
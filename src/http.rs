@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+pub(crate) fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .into()
+}
+
+pub(crate) fn get(agent: &ureq::Agent, url: &str) -> Result<String, String> {
+    agent
+        .get(url)
+        .header("Accept", "application/json")
+        .call()
+        .and_then(|mut res| res.body_mut().read_to_string())
+        .map_err(|e| e.to_string())
+}
+
+// RFC 6749 section 2.3.1 requires form-encoding the credentials before base64
+pub(crate) fn basic_auth(username: &str, password: &str) -> String {
+    let encode =
+        |value: &str| form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+    format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", encode(username), encode(password)))
+    )
+}
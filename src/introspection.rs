@@ -0,0 +1,201 @@
+use std::{fmt, sync::Arc};
+
+#[cfg(feature = "http")]
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+#[cfg(feature = "http")]
+use actix_web::rt::task::spawn_blocking;
+use futures::future::LocalBoxFuture;
+#[cfg(feature = "http")]
+use jsonwebtoken::get_current_timestamp;
+use serde_json::{Map, Value};
+
+#[cfg(feature = "http")]
+use crate::{signing::hash_token, telemetry};
+
+pub type IntrospectionResponse = Arc<Map<String, Value>>;
+
+// `None` means the authorization server reported the token as inactive
+pub trait TokenIntrospector: Send + Sync {
+    fn introspect<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<IntrospectionResponse>, IntrospectionError>>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntrospectionMode {
+    // only tokens that aren't JWTs are introspected, JWTs are still verified locally
+    #[default]
+    Fallback,
+    Only,
+}
+
+#[derive(Debug)]
+pub enum IntrospectionError {
+    Http(String),
+    Json(serde_json::Error),
+    InvalidResponse,
+}
+
+impl fmt::Display for IntrospectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntrospectionError::Http(e) => write!(f, "introspection request failed: {}", e),
+            IntrospectionError::Json(e) => {
+                write!(f, "failed to parse introspection response: {}", e)
+            }
+            IntrospectionError::InvalidResponse => {
+                f.write_str("introspection response does not contain the `active` member")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntrospectionError {}
+
+#[cfg(feature = "http")]
+fn parse_response(body: &str) -> Result<Option<Map<String, Value>>, IntrospectionError> {
+    let Value::Object(response) = serde_json::from_str(body).map_err(IntrospectionError::Json)?
+    else {
+        return Err(IntrospectionError::InvalidResponse);
+    };
+    match response.get("active") {
+        Some(Value::Bool(true)) => Ok(Some(response)),
+        Some(Value::Bool(false)) => Ok(None),
+        _ => Err(IntrospectionError::InvalidResponse),
+    }
+}
+
+#[cfg(feature = "http")]
+struct CachedIntrospection {
+    response: IntrospectionResponse,
+    expires_at: u64,
+}
+
+// RFC 7662 introspection with `client_secret_basic` client authentication
+#[cfg(feature = "http")]
+#[derive(Clone)]
+pub struct HttpIntrospector {
+    endpoint: Arc<str>,
+    credentials: Option<(Arc<str>, Arc<str>)>,
+    token_type_hint: Option<Arc<str>>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<HashMap<String, CachedIntrospection>>>,
+}
+
+#[cfg(feature = "http")]
+impl HttpIntrospector {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into().into(),
+            credentials: None,
+            token_type_hint: Some("access_token".into()),
+            timeout: Duration::from_secs(10),
+            cache_ttl: Duration::from_secs(5 * 60),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn client_credentials(
+        mut self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some((client_id.into().into(), client_secret.into().into()));
+        self
+    }
+
+    pub fn token_type_hint(mut self, token_type_hint: Option<&str>) -> Self {
+        self.token_type_hint = token_type_hint.map(Into::into);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // responses are cached until the token's `exp`, but never longer than `cache_ttl`
+    pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    fn cached(&self, token_hash: &str, now: u64) -> Option<IntrospectionResponse> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(token_hash)
+            .filter(|cached| cached.expires_at > now)
+            .map(|cached| cached.response.clone())
+    }
+
+    fn store(&self, token_hash: String, response: IntrospectionResponse, now: u64) {
+        let max_expires_at = now + self.cache_ttl.as_secs();
+        let expires_at = response
+            .get("exp")
+            .and_then(Value::as_u64)
+            .map_or(max_expires_at, |exp| exp.min(max_expires_at));
+        if expires_at <= now {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, cached| cached.expires_at > now);
+        cache.insert(
+            token_hash,
+            CachedIntrospection {
+                response,
+                expires_at,
+            },
+        );
+    }
+
+    fn request(&self, token: &str) -> Result<Option<Map<String, Value>>, IntrospectionError> {
+        let mut request = crate::http::agent(self.timeout)
+            .post(&*self.endpoint)
+            .header("Accept", "application/json");
+        if let Some((client_id, client_secret)) = &self.credentials {
+            request = request.header(
+                "Authorization",
+                crate::http::basic_auth(client_id, client_secret),
+            );
+        }
+        let mut form = vec![("token", token)];
+        if let Some(token_type_hint) = &self.token_type_hint {
+            form.push(("token_type_hint", token_type_hint));
+        }
+        let body = request
+            .send_form(form)
+            .and_then(|mut res| res.body_mut().read_to_string())
+            .map_err(|e| IntrospectionError::Http(e.to_string()))?;
+        parse_response(&body)
+    }
+}
+
+#[cfg(feature = "http")]
+impl TokenIntrospector for HttpIntrospector {
+    fn introspect<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<IntrospectionResponse>, IntrospectionError>> {
+        Box::pin(async move {
+            let token_hash = hash_token(token);
+            let cached = self.cached(&token_hash, get_current_timestamp());
+            telemetry::cache_lookup("introspection", cached.is_some());
+            if let Some(response) = cached {
+                return Ok(Some(response));
+            }
+            let this = self.clone();
+            let token = token.to_owned();
+            let response = spawn_blocking(move || this.request(&token))
+                .await
+                .map_err(|e| IntrospectionError::Http(e.to_string()))??;
+            let Some(response) = response.map(Arc::new) else {
+                return Ok(None);
+            };
+            self.store(token_hash, response.clone(), get_current_timestamp());
+            Ok(Some(response))
+        })
+    }
+}
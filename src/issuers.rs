@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{ready, Ready},
+    ops::Deref,
+    sync::Arc,
+};
+
+use actix_web::{dev::Payload, Error, FromRequest, HttpMessage, HttpRequest};
+use jsonwebtoken::{errors::ErrorKind, Algorithm, Validation};
+
+use crate::{ClaimsConfig, JwtDecodeErrors, KeyResolver};
+
+pub struct TrustedIssuer {
+    issuer: Arc<str>,
+    key_resolver: Arc<dyn KeyResolver>,
+    validation: Validation,
+}
+
+impl TrustedIssuer {
+    // the algorithms are required since `Validation::default()` only allows HS256
+    pub fn new<R>(issuer: impl Into<String>, key_resolver: R, algorithms: &[Algorithm]) -> Self
+    where
+        R: KeyResolver + 'static,
+    {
+        let issuer: String = issuer.into();
+        let mut validation = Validation::default();
+        validation.algorithms = algorithms.to_vec();
+        validation.set_issuer(&[&issuer]);
+        Self {
+            issuer: issuer.into(),
+            key_resolver: Arc::new(key_resolver),
+            validation,
+        }
+    }
+
+    pub fn algorithms(mut self, algorithms: &[Algorithm]) -> Self {
+        self.validation.algorithms = algorithms.to_vec();
+        self
+    }
+
+    pub fn audience<A: ToString>(mut self, audience: &[A]) -> Self {
+        self.validation.set_audience(audience);
+        self
+    }
+
+    pub fn leeway(mut self, leeway: u64) -> Self {
+        self.validation.leeway = leeway;
+        self
+    }
+
+    pub fn required_spec_claims<C: ToString>(mut self, claims: &[C]) -> Self {
+        self.validation.set_required_spec_claims(claims);
+        self
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+}
+
+#[derive(Default)]
+pub struct IssuerRegistry {
+    issuers: HashMap<String, TrustedIssuer>,
+}
+
+impl IssuerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn issuer(mut self, issuer: TrustedIssuer) -> Self {
+        self.issuers.insert(issuer.issuer.to_string(), issuer);
+        self
+    }
+
+    pub fn get(&self, issuer: &str) -> Option<&TrustedIssuer> {
+        self.issuers.get(issuer)
+    }
+}
+
+pub(crate) enum JwtVerifier {
+    Key {
+        key_resolver: Arc<dyn KeyResolver>,
+        validation: Validation,
+    },
+    Issuers(IssuerRegistry),
+    // JWTs are not accepted at all, e.g. when only introspection or another token format is used
+    Disabled,
+}
+
+impl JwtVerifier {
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn select(
+        &self,
+        issuer: Option<&str>,
+    ) -> Result<(&dyn KeyResolver, &Validation, Option<&Arc<str>>), JwtDecodeErrors> {
+        match self {
+            JwtVerifier::Key {
+                key_resolver,
+                validation,
+            } => Ok((&**key_resolver, validation, None)),
+            JwtVerifier::Disabled => Err(JwtDecodeErrors::InvalidJWTToken(
+                ErrorKind::InvalidToken.into(),
+            )),
+            JwtVerifier::Issuers(registry) => {
+                // the `iss` claim is read before the signature is verified only to pick the keys,
+                // the issuer validation then checks it again against the selected issuer
+                let trusted = issuer
+                    .and_then(|issuer| registry.get(issuer))
+                    .ok_or(JwtDecodeErrors::UnknownIssuer)?;
+                Ok((
+                    &*trusted.key_resolver,
+                    &trusted.validation,
+                    Some(&trusted.issuer),
+                ))
+            }
+        }
+    }
+
+    // every resolver's generation only grows, so the sum changes whenever one of them does
+    pub(crate) fn generation(&self) -> u64 {
+        match self {
+            JwtVerifier::Key { key_resolver, .. } => key_resolver.generation(),
+            JwtVerifier::Issuers(registry) => {
+                registry.issuers.values().fold(0, |generation, trusted| {
+                    generation.wrapping_add(trusted.key_resolver.generation())
+                })
+            }
+            JwtVerifier::Disabled => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifiedIssuer(pub(crate) Arc<str>);
+
+impl VerifiedIssuer {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for VerifiedIssuer {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for VerifiedIssuer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for VerifiedIssuer {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<VerifiedIssuer>()
+                .cloned()
+                .ok_or_else(|| ClaimsConfig::from_req(req).error(req)),
+        )
+    }
+}
@@ -0,0 +1,294 @@
+use std::{fmt, sync::Arc};
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes128Gcm, Aes256Gcm, KeyInit, Nonce,
+};
+use aes_kw::{KekAes128, KekAes256};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use p256::{ecdh::diffie_hellman, pkcs8::DecodePrivateKey, PublicKey, SecretKey};
+#[cfg(feature = "jwe-rsa")]
+use rsa::{pkcs1::DecodeRsaPrivateKey, rand_core::OsRng, Oaep, RsaPrivateKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::JwtDecodeErrors;
+
+#[derive(Debug)]
+pub enum JweKeyError {
+    #[cfg(feature = "jwe-rsa")]
+    Rsa(String),
+    Ec(String),
+}
+
+impl fmt::Display for JweKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "jwe-rsa")]
+            JweKeyError::Rsa(e) => write!(f, "invalid RSA decryption key: {}", e),
+            JweKeyError::Ec(e) => write!(f, "invalid EC decryption key: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JweKeyError {}
+
+#[derive(Clone)]
+enum DecryptionKey {
+    #[cfg(feature = "jwe-rsa")]
+    Rsa(Arc<RsaPrivateKey>),
+    P256(Arc<SecretKey>),
+}
+
+#[derive(Clone)]
+pub struct JweKey {
+    key: DecryptionKey,
+    kid: Option<String>,
+}
+
+impl JweKey {
+    // requires the `jwe-rsa` feature, see the note on it in Cargo.toml
+    #[cfg(feature = "jwe-rsa")]
+    pub fn rsa(key: RsaPrivateKey) -> Self {
+        Self {
+            key: DecryptionKey::Rsa(Arc::new(key)),
+            kid: None,
+        }
+    }
+
+    // accepts both PKCS#8 and PKCS#1 PEM
+    #[cfg(feature = "jwe-rsa")]
+    pub fn rsa_pem(pem: &str) -> Result<Self, JweKeyError> {
+        RsaPrivateKey::from_pkcs8_pem(pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+            .map(Self::rsa)
+            .map_err(|e| JweKeyError::Rsa(e.to_string()))
+    }
+
+    pub fn p256(key: SecretKey) -> Self {
+        Self {
+            key: DecryptionKey::P256(Arc::new(key)),
+            kid: None,
+        }
+    }
+
+    // accepts both PKCS#8 and SEC1 PEM
+    pub fn p256_pem(pem: &str) -> Result<Self, JweKeyError> {
+        SecretKey::from_pkcs8_pem(pem)
+            .or_else(|_| SecretKey::from_sec1_pem(pem))
+            .map(Self::p256)
+            .map_err(|e| JweKeyError::Ec(e.to_string()))
+    }
+
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct EphemeralKey {
+    kty: String,
+    crv: String,
+    x: String,
+    y: String,
+}
+
+#[derive(Deserialize)]
+struct JweHeader {
+    alg: String,
+    enc: String,
+    kid: Option<String>,
+    epk: Option<EphemeralKey>,
+    apu: Option<String>,
+    apv: Option<String>,
+    zip: Option<String>,
+    crit: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy)]
+enum ContentEncryption {
+    A128Gcm,
+    A256Gcm,
+}
+
+impl ContentEncryption {
+    fn key_len(&self) -> usize {
+        match self {
+            ContentEncryption::A128Gcm => 16,
+            ContentEncryption::A256Gcm => 32,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct JweDecryptor {
+    keys: Vec<JweKey>,
+}
+
+impl JweDecryptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn key(mut self, key: JweKey) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    pub(crate) fn is_jwe(token: &str) -> bool {
+        token.split('.').count() == 5
+    }
+
+    // returns the payload of the JWE, which is expected to be the nested JWS
+    pub(crate) fn decrypt(&self, token: &str) -> Result<String, JwtDecodeErrors> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [protected, encrypted_key, iv, ciphertext, tag] = parts[..] else {
+            return Err(JwtDecodeErrors::InvalidJWE);
+        };
+        let header: JweHeader = serde_json::from_slice(&decode_part(protected)?)
+            .map_err(|_| JwtDecodeErrors::InvalidJWE)?;
+        if header.zip.is_some() {
+            return Err(JwtDecodeErrors::UnsupportedJWEAlgorithm);
+        }
+        // no extensions are understood, so any critical one has to be rejected (RFC 7516 section 4.1.13)
+        match header.crit {
+            Some(crit) if crit.is_empty() => return Err(JwtDecodeErrors::InvalidJWE),
+            Some(_) => return Err(JwtDecodeErrors::UnsupportedJWEAlgorithm),
+            None => {}
+        }
+        let enc = match header.enc.as_str() {
+            "A128GCM" => ContentEncryption::A128Gcm,
+            "A256GCM" => ContentEncryption::A256Gcm,
+            _ => return Err(JwtDecodeErrors::UnsupportedJWEAlgorithm),
+        };
+        let encrypted_key = decode_part(encrypted_key)?;
+        let iv = decode_part(iv)?;
+        let mut sealed = decode_part(ciphertext)?;
+        sealed.extend_from_slice(&decode_part(tag)?);
+        if iv.len() != 12 {
+            return Err(JwtDecodeErrors::InvalidJWE);
+        }
+
+        // without `kid` every configured key is tried
+        let candidates = self
+            .keys
+            .iter()
+            .filter(|key| match (&header.kid, &key.kid) {
+                (Some(kid), Some(key_kid)) => kid == key_kid,
+                _ => true,
+            });
+        for key in candidates {
+            let Some(cek) = unwrap_cek(key, &header, enc, &encrypted_key)? else {
+                continue;
+            };
+            if cek.len() != enc.key_len() {
+                continue;
+            }
+            let payload = Payload {
+                msg: &sealed,
+                aad: protected.as_bytes(),
+            };
+            let nonce = Nonce::from_slice(&iv);
+            let plaintext = match enc {
+                ContentEncryption::A128Gcm => Aes128Gcm::new_from_slice(&cek)
+                    .ok()
+                    .and_then(|cipher| cipher.decrypt(nonce, payload).ok()),
+                ContentEncryption::A256Gcm => Aes256Gcm::new_from_slice(&cek)
+                    .ok()
+                    .and_then(|cipher| cipher.decrypt(nonce, payload).ok()),
+            };
+            if let Some(plaintext) = plaintext {
+                return String::from_utf8(plaintext).map_err(|_| JwtDecodeErrors::InvalidJWE);
+            }
+        }
+        Err(JwtDecodeErrors::JWEDecryptionFailed)
+    }
+}
+
+// `Ok(None)` when the key doesn't fit the token's key management algorithm
+fn unwrap_cek(
+    key: &JweKey,
+    header: &JweHeader,
+    enc: ContentEncryption,
+    encrypted_key: &[u8],
+) -> Result<Option<Vec<u8>>, JwtDecodeErrors> {
+    match (header.alg.as_str(), &key.key) {
+        // not constant time (RUSTSEC-2023-0071), hence behind its own feature
+        #[cfg(feature = "jwe-rsa")]
+        ("RSA-OAEP", DecryptionKey::Rsa(key)) => Ok(key
+            .decrypt_blinded(&mut OsRng, Oaep::new::<sha1::Sha1>(), encrypted_key)
+            .ok()),
+        #[cfg(feature = "jwe-rsa")]
+        ("RSA-OAEP-256", DecryptionKey::Rsa(key)) => Ok(key
+            .decrypt_blinded(&mut OsRng, Oaep::new::<Sha256>(), encrypted_key)
+            .ok()),
+        ("ECDH-ES", DecryptionKey::P256(key)) => {
+            if !encrypted_key.is_empty() {
+                return Err(JwtDecodeErrors::InvalidJWE);
+            }
+            derive_key(key, header, header.enc.as_bytes(), enc.key_len()).map(Some)
+        }
+        ("ECDH-ES+A128KW", DecryptionKey::P256(key)) => {
+            let kek = derive_key(key, header, header.alg.as_bytes(), 16)?;
+            Ok(KekAes128::try_from(&kek[..])
+                .ok()
+                .and_then(|kek| kek.unwrap_vec(encrypted_key).ok()))
+        }
+        ("ECDH-ES+A256KW", DecryptionKey::P256(key)) => {
+            let kek = derive_key(key, header, header.alg.as_bytes(), 32)?;
+            Ok(KekAes256::try_from(&kek[..])
+                .ok()
+                .and_then(|kek| kek.unwrap_vec(encrypted_key).ok()))
+        }
+        #[cfg(feature = "jwe-rsa")]
+        ("RSA-OAEP" | "RSA-OAEP-256" | "ECDH-ES" | "ECDH-ES+A128KW" | "ECDH-ES+A256KW", _) => {
+            Ok(None)
+        }
+        _ => Err(JwtDecodeErrors::UnsupportedJWEAlgorithm),
+    }
+}
+
+// ECDH key agreement followed by the Concat KDF from RFC 7518 section 4.6.2
+fn derive_key(
+    key: &SecretKey,
+    header: &JweHeader,
+    algorithm_id: &[u8],
+    key_len: usize,
+) -> Result<Vec<u8>, JwtDecodeErrors> {
+    let epk = header.epk.as_ref().ok_or(JwtDecodeErrors::InvalidJWE)?;
+    if epk.kty != "EC" || epk.crv != "P-256" {
+        return Err(JwtDecodeErrors::UnsupportedJWEAlgorithm);
+    }
+    let mut point = vec![0x04];
+    point.extend_from_slice(&decode_part(&epk.x)?);
+    point.extend_from_slice(&decode_part(&epk.y)?);
+    let epk = PublicKey::from_sec1_bytes(&point).map_err(|_| JwtDecodeErrors::InvalidJWE)?;
+    let shared = diffie_hellman(key.to_nonzero_scalar(), epk.as_affine());
+
+    let apu = header.apu.as_deref().map(decode_part).transpose()?;
+    let apv = header.apv.as_deref().map(decode_part).transpose()?;
+    let mut other_info = Vec::new();
+    for value in [
+        algorithm_id,
+        apu.as_deref().unwrap_or_default(),
+        apv.as_deref().unwrap_or_default(),
+    ] {
+        other_info.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        other_info.extend_from_slice(value);
+    }
+    other_info.extend_from_slice(&((key_len * 8) as u32).to_be_bytes());
+
+    // a single SHA-256 round covers every supported key length
+    let mut hasher = Sha256::new();
+    hasher.update(1u32.to_be_bytes());
+    hasher.update(shared.raw_secret_bytes());
+    hasher.update(&other_info);
+    Ok(hasher.finalize()[..key_len].to_vec())
+}
+
+fn decode_part(part: &str) -> Result<Vec<u8>, JwtDecodeErrors> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| JwtDecodeErrors::InvalidJWE)
+}
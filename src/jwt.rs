@@ -1,37 +1,141 @@
+use arc_swap::ArcSwap;
 use futures::future::LocalBoxFuture;
-use jsonwebtoken::{DecodingKey, Validation};
-use serde::de::DeserializeOwned;
+use jsonwebtoken::{errors::ErrorKind, DecodingKey, Header, Validation};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
 use std::{
-    future::{ready, Ready},
+    borrow::Cow,
+    future::{ready, Future, Ready},
     marker::PhantomData,
+    rc::Rc,
     sync::Arc,
+    time::{Instant, SystemTime},
 };
 
 use actix_web::{
     body::EitherBody,
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     error::ErrorBadRequest,
-    http::header::{self, HeaderValue},
-    Error, HttpMessage,
+    http::header,
+    Error, HttpMessage, HttpRequest, HttpResponse,
 };
 
+#[cfg(feature = "jwe")]
+use crate::JweDecryptor;
+#[cfg(feature = "tracing")]
+use crate::SubjectRedaction;
+use crate::{
+    audit::Audit, AuditPolicy, AuditSink, CertificateBinding, Claims, Dpop, FailureThrottle,
+    HeaderSource, IntrospectionMode, IssuerRegistry, JwtConfigHandle, JwtVerifier, KeyResolver,
+    RegisteredClaims, RejectedTokenCache, ResolvedKey, RevocationStore, TokenFormat,
+    TokenIntrospector, TokenSource, VerifiedIssuer, VerifiedToken, VerifiedTokenCache,
+};
+use crate::{dpop::insert_nonce_challenge, telemetry};
+
 pub struct JwtMiddleware<T> {
-    decoding_key: Arc<DecodingKey>,
-    validation: Arc<Validation>,
-    #[allow(clippy::type_complexity)]
-    err_handler: Option<Arc<dyn Fn(JwtDecodeErrors) -> Error + Send + Sync>>,
-    #[allow(clippy::type_complexity)]
-    success_handler: Option<Arc<dyn Fn(&mut ServiceRequest, T) + Send + Sync>>,
+    verifier: Arc<ArcSwap<JwtVerifier>>,
+    token_source: Arc<dyn TokenSource>,
+    revocation_store: Option<Arc<dyn RevocationStore>>,
+    token_formats: Arc<[Arc<dyn TokenFormat>]>,
+    introspection: Option<Introspection>,
+    #[cfg(feature = "jwe")]
+    jwe_decryptor: Option<Arc<JweDecryptor>>,
+    dpop: Option<Arc<Dpop>>,
+    certificate_binding: Option<Arc<CertificateBinding>>,
+    audit: Option<Audit>,
+    token_cache: Option<VerifiedTokenCache<T>>,
+    rejected_tokens: Option<RejectedTokenCache>,
+    failure_throttle: Option<FailureThrottle>,
+    #[cfg(feature = "tracing")]
+    subject_redaction: SubjectRedaction,
+    err_handler: Option<ErrorHandler>,
+    success_handler: Option<SuccessHandler<T>>,
+    auth_requirement: AuthRequirement,
+    // clones the claims for `VerifiedToken` while plain `T` still goes to extensions or the success handler
+    verified_token: Option<fn(&T) -> T>,
     _token_data_type: PhantomData<T>,
 }
 
+#[derive(Clone)]
+struct Introspection {
+    introspector: Arc<dyn TokenIntrospector>,
+    mode: IntrospectionMode,
+}
+
+#[allow(clippy::type_complexity)]
+enum ErrorHandler {
+    Sync(Arc<dyn Fn(JwtDecodeErrors) -> Error + Send + Sync>),
+    WithRequest(Arc<dyn Fn(&HttpRequest, JwtDecodeErrors) -> Error + Send + Sync>),
+    Async(
+        Arc<dyn Fn(HttpRequest, JwtDecodeErrors) -> LocalBoxFuture<'static, Error> + Send + Sync>,
+    ),
+}
+
+impl Clone for ErrorHandler {
+    fn clone(&self) -> Self {
+        match self {
+            ErrorHandler::Sync(f) => ErrorHandler::Sync(f.clone()),
+            ErrorHandler::WithRequest(f) => ErrorHandler::WithRequest(f.clone()),
+            ErrorHandler::Async(f) => ErrorHandler::Async(f.clone()),
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+enum SuccessHandler<T> {
+    Sync(Arc<dyn Fn(&mut ServiceRequest, T) + Send + Sync>),
+    Async(
+        Arc<
+            dyn Fn(HttpRequest, T) -> LocalBoxFuture<'static, Result<SuccessAction, Error>>
+                + Send
+                + Sync,
+        >,
+    ),
+}
+
+impl<T> Clone for SuccessHandler<T> {
+    fn clone(&self) -> Self {
+        match self {
+            SuccessHandler::Sync(f) => SuccessHandler::Sync(f.clone()),
+            SuccessHandler::Async(f) => SuccessHandler::Async(f.clone()),
+        }
+    }
+}
+
+pub enum SuccessAction {
+    Continue,
+    Respond(HttpResponse),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuthRequirement {
+    Required,
+    #[default]
+    Optional,
+}
+
 impl<T> Clone for JwtMiddleware<T> {
     fn clone(&self) -> Self {
         Self {
-            decoding_key: self.decoding_key.clone(),
-            validation: self.validation.clone(),
+            verifier: self.verifier.clone(),
+            token_source: self.token_source.clone(),
+            revocation_store: self.revocation_store.clone(),
+            token_formats: self.token_formats.clone(),
+            introspection: self.introspection.clone(),
+            #[cfg(feature = "jwe")]
+            jwe_decryptor: self.jwe_decryptor.clone(),
+            dpop: self.dpop.clone(),
+            certificate_binding: self.certificate_binding.clone(),
+            audit: self.audit.clone(),
+            token_cache: self.token_cache.clone(),
+            rejected_tokens: self.rejected_tokens.clone(),
+            failure_throttle: self.failure_throttle.clone(),
+            #[cfg(feature = "tracing")]
+            subject_redaction: self.subject_redaction,
             err_handler: self.err_handler.clone(),
             success_handler: self.success_handler.clone(),
+            auth_requirement: self.auth_requirement,
+            verified_token: self.verified_token,
             _token_data_type: PhantomData,
         }
     }
@@ -39,20 +143,205 @@ impl<T> Clone for JwtMiddleware<T> {
 
 impl<T> JwtMiddleware<T> {
     pub fn new(decoding_key: DecodingKey, validation: Validation) -> Self {
+        Self::with_key_resolver(ResolvedKey::new(decoding_key), validation)
+    }
+
+    pub fn with_key_resolver<R>(key_resolver: R, validation: Validation) -> Self
+    where
+        R: KeyResolver + 'static,
+    {
+        Self::with_verifier(JwtVerifier::Key {
+            key_resolver: Arc::new(key_resolver),
+            validation,
+        })
+    }
+
+    pub fn with_issuers(issuers: IssuerRegistry) -> Self {
+        Self::with_verifier(JwtVerifier::Issuers(issuers))
+    }
+
+    pub fn with_introspector<I>(introspector: I) -> Self
+    where
+        I: TokenIntrospector + 'static,
+    {
+        Self::with_verifier(JwtVerifier::Disabled)
+            .introspection(introspector, IntrospectionMode::Only)
+    }
+
+    // JWTs are rejected, only tokens accepted by `token_format` are verified
+    pub fn with_token_format<F>(token_format: F) -> Self
+    where
+        F: TokenFormat + 'static,
+    {
+        Self::with_verifier(JwtVerifier::Disabled).token_format(token_format)
+    }
+
+    // updates apply to every service created from this middleware and its clones
+    pub fn config_handle(&self) -> JwtConfigHandle {
+        JwtConfigHandle::new(self.verifier.clone())
+    }
+
+    fn with_verifier(verifier: JwtVerifier) -> Self {
         Self {
-            decoding_key: Arc::new(decoding_key),
-            validation: Arc::new(validation),
+            verifier: Arc::new(ArcSwap::from_pointee(verifier)),
+            token_source: Arc::new(HeaderSource::authorization()),
+            revocation_store: None,
+            token_formats: Arc::new([]),
+            introspection: None,
+            #[cfg(feature = "jwe")]
+            jwe_decryptor: None,
+            dpop: None,
+            certificate_binding: None,
+            audit: None,
+            token_cache: None,
+            rejected_tokens: None,
+            failure_throttle: None,
+            #[cfg(feature = "tracing")]
+            subject_redaction: SubjectRedaction::default(),
             err_handler: None,
             success_handler: None,
+            auth_requirement: AuthRequirement::default(),
+            verified_token: None,
             _token_data_type: PhantomData,
         }
     }
 
+    pub fn auth_requirement(mut self, auth_requirement: AuthRequirement) -> Self {
+        self.auth_requirement = auth_requirement;
+        self
+    }
+
+    pub fn required(&self) -> Self {
+        self.clone().auth_requirement(AuthRequirement::Required)
+    }
+
+    pub fn optional(&self) -> Self {
+        self.clone().auth_requirement(AuthRequirement::Optional)
+    }
+
+    pub fn token_source<S>(mut self, token_source: S) -> Self
+    where
+        S: TokenSource + 'static,
+    {
+        self.token_source = Arc::new(token_source);
+        self
+    }
+
+    pub fn revocation_store<R>(mut self, revocation_store: R) -> Self
+    where
+        R: RevocationStore + 'static,
+    {
+        self.revocation_store = Some(Arc::new(revocation_store));
+        self
+    }
+
+    pub fn token_format<F>(mut self, token_format: F) -> Self
+    where
+        F: TokenFormat + 'static,
+    {
+        let mut token_formats = self.token_formats.to_vec();
+        token_formats.push(Arc::new(token_format));
+        self.token_formats = token_formats.into();
+        self
+    }
+
+    pub fn introspection<I>(mut self, introspector: I, mode: IntrospectionMode) -> Self
+    where
+        I: TokenIntrospector + 'static,
+    {
+        self.introspection = Some(Introspection {
+            introspector: Arc::new(introspector),
+            mode,
+        });
+        self
+    }
+
+    // compact JWE tokens are decrypted and their payload verified as the nested JWS
+    #[cfg(feature = "jwe")]
+    pub fn jwe_decryptor(mut self, jwe_decryptor: JweDecryptor) -> Self {
+        self.jwe_decryptor = Some(Arc::new(jwe_decryptor));
+        self
+    }
+
+    // `Authorization: DPoP` is checked before the configured token source,
+    // which keeps providing bearer tokens unless DPoP is required
+    pub fn dpop(mut self, dpop: Dpop) -> Self {
+        self.dpop = Some(Arc::new(dpop));
+        self
+    }
+
+    pub fn certificate_binding(mut self, certificate_binding: CertificateBinding) -> Self {
+        self.certificate_binding = Some(Arc::new(certificate_binding));
+        self
+    }
+
+    // every authentication decision is recorded, requests without a token only when it's required
+    pub fn audit<A>(mut self, sink: A, policy: AuditPolicy) -> Self
+    where
+        A: AuditSink + 'static,
+    {
+        self.audit = Some(Audit::new(Arc::new(sink), policy));
+        self
+    }
+
+    // skips decoding for tokens verified before, revocation is still checked on every request
+    pub fn verified_token_cache(mut self, token_cache: VerifiedTokenCache<T>) -> Self {
+        self.token_cache = Some(token_cache);
+        self
+    }
+
+    pub fn rejected_token_cache(mut self, rejected_tokens: RejectedTokenCache) -> Self {
+        self.rejected_tokens = Some(rejected_tokens);
+        self
+    }
+
+    // throttled requests are answered with 429 and `Retry-After` before the error handler is involved
+    pub fn failure_throttle(mut self, failure_throttle: FailureThrottle) -> Self {
+        self.failure_throttle = Some(failure_throttle);
+        self
+    }
+
+    // how `sub` is recorded on the verification span
+    #[cfg(feature = "tracing")]
+    pub fn subject_redaction(mut self, subject_redaction: SubjectRedaction) -> Self {
+        self.subject_redaction = subject_redaction;
+        self
+    }
+
+    // claims are then stored once and shared by `VerifiedToken<T>` and `Claims<T>`,
+    // instead of as plain `T` in extensions
+    pub fn store_verified_token(mut self) -> Self
+    where
+        T: Clone,
+    {
+        self.verified_token = Some(T::clone);
+        self
+    }
+
     pub fn error_handler<F>(mut self, f: F) -> Self
     where
         F: Fn(JwtDecodeErrors) -> Error + Send + Sync + 'static,
     {
-        self.err_handler = Some(Arc::new(f));
+        self.err_handler = Some(ErrorHandler::Sync(Arc::new(f)));
+        self
+    }
+
+    pub fn error_handler_with_request<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&HttpRequest, JwtDecodeErrors) -> Error + Send + Sync + 'static,
+    {
+        self.err_handler = Some(ErrorHandler::WithRequest(Arc::new(f)));
+        self
+    }
+
+    pub fn async_error_handler<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(HttpRequest, JwtDecodeErrors) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Error> + 'static,
+    {
+        self.err_handler = Some(ErrorHandler::Async(Arc::new(move |req, e| {
+            Box::pin(f(req, e))
+        })));
         self
     }
 
@@ -60,14 +349,25 @@ impl<T> JwtMiddleware<T> {
     where
         F: Fn(&mut ServiceRequest, T) + Send + Sync + 'static,
     {
-        self.success_handler = Some(Arc::new(f));
+        self.success_handler = Some(SuccessHandler::Sync(Arc::new(f)));
+        self
+    }
+
+    pub fn async_success_handler<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(HttpRequest, T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<SuccessAction, Error>> + 'static,
+    {
+        self.success_handler = Some(SuccessHandler::Async(Arc::new(move |req, claims| {
+            Box::pin(f(req, claims))
+        })));
         self
     }
 }
 
 impl<S, B, T> Transform<S, ServiceRequest> for JwtMiddleware<T>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
     T: DeserializeOwned + 'static,
@@ -80,66 +380,281 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(JwtService {
-            service,
-            decoding_key: self.decoding_key.clone(),
-            validation: self.validation.clone(),
+            service: Rc::new(service),
+            verifier: self.verifier.clone(),
+            token_source: self.token_source.clone(),
+            revocation_store: self.revocation_store.clone(),
+            token_formats: self.token_formats.clone(),
+            introspection: self.introspection.clone(),
+            #[cfg(feature = "jwe")]
+            jwe_decryptor: self.jwe_decryptor.clone(),
+            dpop: self.dpop.clone(),
+            certificate_binding: self.certificate_binding.clone(),
+            audit: self.audit.clone(),
+            token_cache: self.token_cache.clone(),
+            rejected_tokens: self.rejected_tokens.clone(),
+            failure_throttle: self.failure_throttle.clone(),
+            #[cfg(feature = "tracing")]
+            subject_redaction: self.subject_redaction,
             err_handler: self.err_handler.clone(),
             success_handler: self.success_handler.clone(),
+            auth_requirement: self.auth_requirement,
+            verified_token: self.verified_token,
             _token_data_type: PhantomData,
         }))
     }
 }
 
 pub struct JwtService<S, T> {
-    service: S,
-    decoding_key: Arc<DecodingKey>,
-    validation: Arc<Validation>,
-    #[allow(clippy::type_complexity)]
-    err_handler: Option<Arc<dyn Fn(JwtDecodeErrors) -> Error + Send + Sync>>,
-    #[allow(clippy::type_complexity)]
-    success_handler: Option<Arc<dyn Fn(&mut ServiceRequest, T) + Send + Sync>>,
+    service: Rc<S>,
+    verifier: Arc<ArcSwap<JwtVerifier>>,
+    token_source: Arc<dyn TokenSource>,
+    revocation_store: Option<Arc<dyn RevocationStore>>,
+    token_formats: Arc<[Arc<dyn TokenFormat>]>,
+    introspection: Option<Introspection>,
+    #[cfg(feature = "jwe")]
+    jwe_decryptor: Option<Arc<JweDecryptor>>,
+    dpop: Option<Arc<Dpop>>,
+    certificate_binding: Option<Arc<CertificateBinding>>,
+    audit: Option<Audit>,
+    token_cache: Option<VerifiedTokenCache<T>>,
+    rejected_tokens: Option<RejectedTokenCache>,
+    failure_throttle: Option<FailureThrottle>,
+    #[cfg(feature = "tracing")]
+    subject_redaction: SubjectRedaction,
+    err_handler: Option<ErrorHandler>,
+    success_handler: Option<SuccessHandler<T>>,
+    auth_requirement: AuthRequirement,
+    // clones the claims for `VerifiedToken` while plain `T` still goes to extensions or the success handler
+    verified_token: Option<fn(&T) -> T>,
     _token_data_type: PhantomData<T>,
 }
 
+impl<S, T> Clone for JwtService<S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            verifier: self.verifier.clone(),
+            token_source: self.token_source.clone(),
+            revocation_store: self.revocation_store.clone(),
+            token_formats: self.token_formats.clone(),
+            introspection: self.introspection.clone(),
+            #[cfg(feature = "jwe")]
+            jwe_decryptor: self.jwe_decryptor.clone(),
+            dpop: self.dpop.clone(),
+            certificate_binding: self.certificate_binding.clone(),
+            audit: self.audit.clone(),
+            token_cache: self.token_cache.clone(),
+            rejected_tokens: self.rejected_tokens.clone(),
+            failure_throttle: self.failure_throttle.clone(),
+            #[cfg(feature = "tracing")]
+            subject_redaction: self.subject_redaction,
+            err_handler: self.err_handler.clone(),
+            success_handler: self.success_handler.clone(),
+            auth_requirement: self.auth_requirement,
+            verified_token: self.verified_token,
+            _token_data_type: PhantomData,
+        }
+    }
+}
+
 #[allow(clippy::enum_variant_names)]
+#[derive(Clone)]
 pub enum JwtDecodeErrors {
+    MissingToken,
     InvalidAuthHeader,
     InvalidJWTHeader,
     InvalidJWTToken(jsonwebtoken::errors::Error),
+    UnknownKey,
+    KeysUnavailable,
+    UnknownIssuer,
+    Revoked,
+    RevocationUnavailable,
+    InactiveToken,
+    IntrospectionUnavailable,
+    InvalidJWE,
+    UnsupportedJWEAlgorithm,
+    JWEDecryptionFailed,
+    InvalidDPoPProof,
+    UseDPoPNonce(String),
+    CertificateMismatch,
 }
 
 impl JwtDecodeErrors {
+    // stable snake case name of the error, used as the outcome in traces and metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JwtDecodeErrors::MissingToken => "missing_token",
+            JwtDecodeErrors::InvalidAuthHeader => "invalid_auth_header",
+            JwtDecodeErrors::InvalidJWTHeader => "invalid_jwt_header",
+            JwtDecodeErrors::InvalidJWTToken(e) => match e.kind() {
+                ErrorKind::ExpiredSignature => "expired",
+                ErrorKind::ImmatureSignature => "immature",
+                ErrorKind::InvalidSignature => "invalid_signature",
+                ErrorKind::InvalidAlgorithm => "invalid_algorithm",
+                ErrorKind::InvalidIssuer => "invalid_issuer",
+                ErrorKind::InvalidAudience => "invalid_audience",
+                ErrorKind::InvalidSubject => "invalid_subject",
+                ErrorKind::MissingRequiredClaim(_) => "missing_claim",
+                _ => "invalid_token",
+            },
+            JwtDecodeErrors::UnknownKey => "unknown_key",
+            JwtDecodeErrors::KeysUnavailable => "keys_unavailable",
+            JwtDecodeErrors::UnknownIssuer => "unknown_issuer",
+            JwtDecodeErrors::Revoked => "revoked",
+            JwtDecodeErrors::RevocationUnavailable => "revocation_unavailable",
+            JwtDecodeErrors::InactiveToken => "inactive_token",
+            JwtDecodeErrors::IntrospectionUnavailable => "introspection_unavailable",
+            JwtDecodeErrors::InvalidJWE => "invalid_jwe",
+            JwtDecodeErrors::UnsupportedJWEAlgorithm => "unsupported_jwe_algorithm",
+            JwtDecodeErrors::JWEDecryptionFailed => "jwe_decryption_failed",
+            JwtDecodeErrors::InvalidDPoPProof => "invalid_dpop_proof",
+            JwtDecodeErrors::UseDPoPNonce(_) => "use_dpop_nonce",
+            JwtDecodeErrors::CertificateMismatch => "certificate_mismatch",
+        }
+    }
+
     pub fn to_error_string(&self) -> String {
         match self {
+            JwtDecodeErrors::MissingToken => "Missing authorization header - request needs to contain header with this format 'Bearer HEADER.PAYLOAD.SIGNATURE'".into(),
             JwtDecodeErrors::InvalidAuthHeader => {
                 "Invalid authorization header - header contains invalid ASCII characters".into()
             }
             JwtDecodeErrors::InvalidJWTHeader => "Invalid authorization header - header need to have this format 'Bearer HEADER.PAYLOAD.SIGNATURE' where all three parts need to be base64 encoded and separated by a dot".into(),
             JwtDecodeErrors::InvalidJWTToken(e) => format!("Invalid JWT token - an error occurred when decoding token: {}", e),
+            JwtDecodeErrors::UnknownKey => "Invalid JWT token - token was not signed by any of the trusted keys".into(),
+            JwtDecodeErrors::KeysUnavailable => "Unable to verify JWT token - signing keys are currently unavailable".into(),
+            JwtDecodeErrors::UnknownIssuer => "Invalid JWT token - token was not issued by a trusted issuer".into(),
+            JwtDecodeErrors::Revoked => "Invalid JWT token - token has been revoked".into(),
+            JwtDecodeErrors::RevocationUnavailable => "Unable to verify JWT token - revocation status could not be checked".into(),
+            JwtDecodeErrors::InactiveToken => "Invalid token - authorization server reported the token as inactive".into(),
+            JwtDecodeErrors::IntrospectionUnavailable => "Unable to verify token - introspection endpoint is currently unavailable".into(),
+            JwtDecodeErrors::InvalidJWE => "Invalid JWE token - token is not a valid compact JWE".into(),
+            JwtDecodeErrors::UnsupportedJWEAlgorithm => "Invalid JWE token - token uses an unsupported key management or content encryption algorithm".into(),
+            JwtDecodeErrors::JWEDecryptionFailed => "Invalid JWE token - token could not be decrypted with any of the configured keys".into(),
+            JwtDecodeErrors::InvalidDPoPProof => "Invalid DPoP proof - request needs to contain a single valid 'DPoP' header proof bound to the access token".into(),
+            JwtDecodeErrors::UseDPoPNonce(_) => "Invalid DPoP proof - proof needs to contain the nonce provided in the 'DPoP-Nonce' header".into(),
+            JwtDecodeErrors::CertificateMismatch => "Invalid token - token is not bound to the client certificate used for this request".into(),
         }
     }
 }
 
-fn decode_jwt<T: DeserializeOwned>(
-    header_value: &HeaderValue,
-    decoding_key: &DecodingKey,
-    validation: &Validation,
-) -> Result<T, JwtDecodeErrors> {
-    let Ok(header_value) = header_value.to_str() else {
-        return Err(JwtDecodeErrors::InvalidAuthHeader);
+pub(crate) struct DecodedJwt<T> {
+    pub(crate) claims: T,
+    pub(crate) registered: RegisteredClaims,
+    pub(crate) header: Option<Header>,
+    pub(crate) key_id: Option<String>,
+    pub(crate) issuer: Option<VerifiedIssuer>,
+    pub(crate) verified_at: SystemTime,
+}
+
+async fn decode_jwt<T: DeserializeOwned>(
+    token: &str,
+    verifier: &JwtVerifier,
+    revocation_store: Option<&dyn RevocationStore>,
+) -> Result<DecodedJwt<T>, JwtDecodeErrors> {
+    let header = jsonwebtoken::decode_header(token).map_err(JwtDecodeErrors::InvalidJWTToken)?;
+    let registered = RegisteredClaims::from_token(token)?;
+    let (key_resolver, validation, issuer) = verifier.select(registered.iss.as_deref())?;
+    let started = Instant::now();
+    let key = key_resolver.resolve(&header).await;
+    telemetry::key_resolution(started.elapsed());
+    let key = key?;
+    let validation = validation_for_key(validation, header.alg, &key)?;
+    let data = jsonwebtoken::decode::<T>(token, &key.key, &validation)
+        .map_err(JwtDecodeErrors::InvalidJWTToken)?;
+    check_revocation(&registered, revocation_store).await?;
+    Ok(DecodedJwt {
+        claims: data.claims,
+        registered,
+        header: Some(data.header),
+        key_id: key.kid,
+        issuer: issuer.cloned().map(VerifiedIssuer),
+        verified_at: SystemTime::now(),
+    })
+}
+
+async fn decode_with_format<T: DeserializeOwned>(
+    token: &str,
+    token_format: &dyn TokenFormat,
+    revocation_store: Option<&dyn RevocationStore>,
+) -> Result<DecodedJwt<T>, JwtDecodeErrors> {
+    let decoded = token_format.decode(token).await?;
+    check_revocation(&decoded.registered, revocation_store).await?;
+    Ok(DecodedJwt {
+        claims: T::deserialize(decoded.claims).map_err(invalid_claims)?,
+        registered: decoded.registered,
+        header: None,
+        key_id: decoded.key_id,
+        issuer: None,
+        verified_at: SystemTime::now(),
+    })
+}
+
+async fn introspect_token<T: DeserializeOwned>(
+    token: &str,
+    introspector: &dyn TokenIntrospector,
+    revocation_store: Option<&dyn RevocationStore>,
+) -> Result<DecodedJwt<T>, JwtDecodeErrors> {
+    let response = introspector
+        .introspect(token)
+        .await
+        .map_err(|_| JwtDecodeErrors::IntrospectionUnavailable)?
+        .ok_or(JwtDecodeErrors::InactiveToken)?;
+    let response = Value::Object(Map::clone(&response));
+    let registered = RegisteredClaims::deserialize(&response).map_err(invalid_claims)?;
+    check_revocation(&registered, revocation_store).await?;
+    Ok(DecodedJwt {
+        claims: T::deserialize(response).map_err(invalid_claims)?,
+        registered,
+        header: None,
+        key_id: None,
+        issuer: None,
+        verified_at: SystemTime::now(),
+    })
+}
+
+fn invalid_claims(e: serde_json::Error) -> JwtDecodeErrors {
+    JwtDecodeErrors::InvalidJWTToken(ErrorKind::Json(Arc::new(e)).into())
+}
+
+async fn check_revocation(
+    registered: &RegisteredClaims,
+    revocation_store: Option<&dyn RevocationStore>,
+) -> Result<(), JwtDecodeErrors> {
+    let Some(revocation_store) = revocation_store else {
+        return Ok(());
     };
-    if !header_value.starts_with("Bearer ") {
-        return Err(JwtDecodeErrors::InvalidJWTHeader);
+    match revocation_store.is_revoked(registered).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(JwtDecodeErrors::Revoked),
+        Err(_) => Err(JwtDecodeErrors::RevocationUnavailable),
     }
-    match jsonwebtoken::decode::<T>(&header_value[7..], decoding_key, validation) {
-        Ok(data) => Ok(data.claims),
-        Err(e) => Err(JwtDecodeErrors::InvalidJWTToken(e)),
+}
+
+// jsonwebtoken requires every allowed algorithm to match the key family,
+// so narrow the validation down to the algorithm the token was signed with
+fn validation_for_key<'a>(
+    validation: &'a Validation,
+    alg: jsonwebtoken::Algorithm,
+    key: &ResolvedKey,
+) -> Result<Cow<'a, Validation>, JwtDecodeErrors> {
+    if !validation.algorithms.contains(&alg) || key.algorithm.is_some_and(|a| a != alg) {
+        return Err(JwtDecodeErrors::InvalidJWTToken(
+            ErrorKind::InvalidAlgorithm.into(),
+        ));
     }
+    if validation.algorithms.len() == 1 {
+        return Ok(Cow::Borrowed(validation));
+    }
+    let mut validation = validation.clone();
+    validation.algorithms = vec![alg];
+    Ok(Cow::Owned(validation))
 }
 
 impl<S, B, T> Service<ServiceRequest> for JwtService<S, T>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
     T: DeserializeOwned + 'static,
@@ -151,33 +666,233 @@ where
     forward_ready!(service);
 
     fn call(&self, mut req: ServiceRequest) -> Self::Future {
-        let auth_header_value = req.headers().get(header::AUTHORIZATION).cloned();
-
-        if let Some(auth_header_value) = auth_header_value {
-            let claims = decode_jwt::<T>(&auth_header_value, &self.decoding_key, &self.validation);
-            match claims {
-                Ok(token_data) => {
-                    if let Some(success_handler) = self.success_handler.clone() {
-                        (success_handler)(&mut req, token_data);
-                    } else {
-                        req.extensions_mut().insert(token_data);
-                    }
+        let this = self.clone();
+
+        let fut = async move {
+            if let Some(retry_after) = this
+                .failure_throttle
+                .as_ref()
+                .and_then(|throttle| throttle.retry_after(&req))
+            {
+                telemetry::throttled();
+                if let Some(audit) = &this.audit {
+                    audit.record(&req, None, Some("throttled"));
                 }
-                Err(e) => {
-                    return Box::pin(ready(Ok(req
-                        .error_response({
-                            if let Some(err_handler) = self.err_handler.clone() {
-                                (err_handler)(e)
-                            } else {
-                                ErrorBadRequest(e.to_error_string())
+                let res = HttpResponse::TooManyRequests()
+                    .insert_header((
+                        header::RETRY_AFTER,
+                        retry_after.as_secs_f64().ceil().max(1.0) as u64,
+                    ))
+                    .finish();
+                return Ok(req.into_response(res).map_into_right_body());
+            }
+
+            let token = match this.dpop.as_ref().and_then(|dpop| dpop.extract(&req)) {
+                Some(token) => Some(token),
+                None => this.token_source.extract(&mut req).await,
+            };
+
+            if let Some(token) = token {
+                let token = match token {
+                    Ok(token) => token,
+                    Err(e) => return Ok(this.error_response(req, e).await),
+                };
+                telemetry::record_header(&token);
+                let claims = this.verify(&token).await;
+                match claims {
+                    Ok(decoded) => {
+                        #[cfg(feature = "tracing")]
+                        telemetry::record_claims(&decoded.registered, this.subject_redaction);
+                        if let Some(dpop) = &this.dpop {
+                            if let Err(e) = dpop.check(&req, &token, &decoded.registered).await {
+                                return Ok(this.error_response(req, e).await);
+                            }
+                        }
+                        if let Some(binding) = &this.certificate_binding {
+                            if let Err(e) = binding.check(&req, &decoded.registered) {
+                                return Ok(this.error_response(req, e).await);
+                            }
+                        }
+                        telemetry::success();
+                        let verified_token = |claims| {
+                            VerifiedToken::new(
+                                decoded.header,
+                                claims,
+                                token,
+                                decoded.key_id,
+                                decoded.issuer.clone(),
+                                decoded.verified_at,
+                            )
+                        };
+                        let claims = match (&this.success_handler, this.verified_token) {
+                            // the success handler takes the claims by value
+                            (Some(_), Some(clone_claims)) => {
+                                let verified_token =
+                                    verified_token(Arc::new(clone_claims(&decoded.claims)));
+                                req.extensions_mut().insert(verified_token);
+                                Some(decoded.claims)
+                            }
+                            // a single `Arc` is shared by `VerifiedToken<T>` and `Claims<T>`
+                            (None, Some(_)) => {
+                                let claims = Arc::new(decoded.claims);
+                                let verified_token = verified_token(claims.clone());
+                                let mut extensions = req.extensions_mut();
+                                extensions.insert(verified_token);
+                                extensions.insert(Claims::from_arc(claims));
+                                None
+                            }
+                            (_, None) => Some(decoded.claims),
+                        };
+                        if let Some(issuer) = decoded.issuer {
+                            req.extensions_mut().insert(issuer);
+                        }
+                        match (&this.success_handler, claims) {
+                            (_, None) => {}
+                            (Some(SuccessHandler::Sync(success_handler)), Some(claims)) => {
+                                (success_handler)(&mut req, claims);
+                            }
+                            // the cloned `HttpRequest` shares extensions with `req`
+                            (Some(SuccessHandler::Async(success_handler)), Some(claims)) => {
+                                let action = (success_handler)(req.request().clone(), claims).await;
+                                if !matches!(action, Ok(SuccessAction::Continue)) {
+                                    if let Some(audit) = &this.audit {
+                                        audit.record(
+                                            &req,
+                                            Some(&decoded.registered),
+                                            Some("denied"),
+                                        );
+                                    }
+                                }
+                                match action {
+                                    Ok(SuccessAction::Continue) => {}
+                                    Ok(SuccessAction::Respond(res)) => {
+                                        return Ok(req.into_response(res).map_into_right_body());
+                                    }
+                                    Err(e) => {
+                                        return Ok(req.error_response(e).map_into_right_body());
+                                    }
+                                }
                             }
-                        })
-                        .map_into_right_body())));
+                            (None, Some(claims)) => {
+                                req.extensions_mut().insert(claims);
+                            }
+                        }
+                        // recorded once the success handler let the request through
+                        if let Some(audit) = &this.audit {
+                            audit.record(&req, Some(&decoded.registered), None);
+                        }
+                    }
+                    Err(e) => return Ok(this.error_response(req, e).await),
                 }
+            } else if this.auth_requirement == AuthRequirement::Required {
+                return Ok(this
+                    .error_response(req, JwtDecodeErrors::MissingToken)
+                    .await);
+            }
+
+            Ok(this.service.call(req).await?.map_into_left_body())
+        };
+        #[cfg(feature = "tracing")]
+        let fut = tracing::Instrument::instrument(fut, telemetry::span());
+        Box::pin(fut)
+    }
+}
+
+impl<S, T: DeserializeOwned> JwtService<S, T> {
+    // cached results are only used while the configuration and keys they were verified with are
+    // active, the generation is read first so results of a concurrent reload aren't cached as current
+    async fn verify(&self, token: &str) -> Result<DecodedJwt<T>, JwtDecodeErrors> {
+        let verifier = self.verifier.load_full();
+        let generation = verifier.generation();
+        let Some(rejected_tokens) = &self.rejected_tokens else {
+            return self.verify_cached(token, &verifier, generation).await;
+        };
+        if let Some(e) = rejected_tokens.get(token, &verifier, generation) {
+            return Err(e);
+        }
+        let decoded = self.verify_cached(token, &verifier, generation).await;
+        if let Err(e) = &decoded {
+            rejected_tokens.insert(token, e, &verifier, generation);
+        }
+        decoded
+    }
+
+    async fn verify_cached(
+        &self,
+        token: &str,
+        verifier: &Arc<JwtVerifier>,
+        generation: u64,
+    ) -> Result<DecodedJwt<T>, JwtDecodeErrors> {
+        let Some(token_cache) = &self.token_cache else {
+            return self.verify_token(token, verifier).await;
+        };
+        if let Some(decoded) = token_cache.get(token, verifier, generation) {
+            check_revocation(&decoded.registered, self.revocation_store.as_deref()).await?;
+            return Ok(decoded);
+        }
+        let decoded = self.verify_token(token, verifier).await?;
+        token_cache.insert(token, &decoded, verifier, generation);
+        Ok(decoded)
+    }
+
+    async fn verify_token(
+        &self,
+        token: &str,
+        verifier: &JwtVerifier,
+    ) -> Result<DecodedJwt<T>, JwtDecodeErrors> {
+        #[cfg(feature = "jwe")]
+        let decrypted;
+        #[cfg(feature = "jwe")]
+        let token = match &self.jwe_decryptor {
+            Some(jwe_decryptor) if JweDecryptor::is_jwe(token) => {
+                decrypted = jwe_decryptor.decrypt(token)?;
+                decrypted.as_str()
             }
+            _ => token,
         };
+        let revocation_store = self.revocation_store.as_deref();
+        if let Some(token_format) = self.token_formats.iter().find(|f| f.accepts(token)) {
+            return decode_with_format(token, &**token_format, revocation_store).await;
+        }
+        match &self.introspection {
+            Some(introspection)
+                if introspection.mode == IntrospectionMode::Only
+                    || jsonwebtoken::decode_header(token).is_err() =>
+            {
+                introspect_token(token, &*introspection.introspector, revocation_store).await
+            }
+            _ => decode_jwt(token, verifier, revocation_store).await,
+        }
+    }
+}
 
-        let fut = self.service.call(req);
-        Box::pin(async move { Ok(fut.await?.map_into_left_body()) })
+impl<S, T> JwtService<S, T> {
+    async fn error_response<B>(
+        &self,
+        req: ServiceRequest,
+        e: JwtDecodeErrors,
+    ) -> ServiceResponse<EitherBody<B>> {
+        telemetry::failure(&e);
+        if let Some(failure_throttle) = &self.failure_throttle {
+            failure_throttle.record_failure(&req, &e);
+        }
+        if let Some(audit) = &self.audit {
+            audit.record(&req, None, Some(e.kind()));
+        }
+        let nonce = match &e {
+            JwtDecodeErrors::UseDPoPNonce(nonce) => Some(nonce.clone()),
+            _ => None,
+        };
+        let error = match &self.err_handler {
+            Some(ErrorHandler::Sync(err_handler)) => (err_handler)(e),
+            Some(ErrorHandler::WithRequest(err_handler)) => (err_handler)(req.request(), e),
+            Some(ErrorHandler::Async(err_handler)) => (err_handler)(req.request().clone(), e).await,
+            None => ErrorBadRequest(e.to_error_string()),
+        };
+        let mut res = req.error_response(error);
+        if let Some(nonce) = nonce {
+            insert_nonce_challenge(res.headers_mut(), &nonce);
+        }
+        res.map_into_right_body()
     }
 }
@@ -0,0 +1,339 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix_web::rt::task::spawn_blocking;
+use arc_swap::ArcSwap;
+use futures::future::LocalBoxFuture;
+use jsonwebtoken::{jwk::Jwk, Algorithm, DecodingKey, Header};
+
+use crate::{telemetry, JwksError, JwtDecodeErrors, KeyResolver, KeySet, ResolvedKey};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyFileFormat {
+    // RSA, EC or Ed25519 public key or certificate, the key type is detected from the PEM
+    Pem,
+    Der(Algorithm),
+    Secret(Algorithm),
+    Jwk,
+    Jwks,
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyFile {
+    path: PathBuf,
+    format: KeyFileFormat,
+    algorithm: Option<Algorithm>,
+    kid: Option<String>,
+}
+
+impl KeyFile {
+    fn new(path: impl Into<PathBuf>, format: KeyFileFormat, algorithm: Option<Algorithm>) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            algorithm,
+            kid: None,
+        }
+    }
+
+    pub fn pem(path: impl Into<PathBuf>) -> Self {
+        Self::new(path, KeyFileFormat::Pem, None)
+    }
+
+    // DER doesn't say what kind of key it holds, so the algorithm is required
+    pub fn der(path: impl Into<PathBuf>, algorithm: Algorithm) -> Self {
+        Self::new(path, KeyFileFormat::Der(algorithm), Some(algorithm))
+    }
+
+    // raw HMAC secret, e.g. a mounted Kubernetes secret
+    pub fn secret(path: impl Into<PathBuf>, algorithm: Algorithm) -> Self {
+        Self::new(path, KeyFileFormat::Secret(algorithm), Some(algorithm))
+    }
+
+    pub fn jwk(path: impl Into<PathBuf>) -> Self {
+        Self::new(path, KeyFileFormat::Jwk, None)
+    }
+
+    pub fn jwks(path: impl Into<PathBuf>) -> Self {
+        Self::new(path, KeyFileFormat::Jwks, None)
+    }
+
+    // ignored for JWK and JWKS files, which carry their own `kid` and `alg`
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Vec<ResolvedKey>, KeyFileError> {
+        let bytes = fs::read(&self.path).map_err(|e| KeyFileError::Io(self.path.clone(), e))?;
+        let invalid_key = |e| KeyFileError::Key(self.path.clone(), e);
+        let key = match self.format {
+            KeyFileFormat::Pem => DecodingKey::from_rsa_pem(&bytes)
+                .or_else(|_| DecodingKey::from_ec_pem(&bytes))
+                .or_else(|_| DecodingKey::from_ed_pem(&bytes))
+                .map_err(invalid_key)?,
+            KeyFileFormat::Der(algorithm) => match algorithm {
+                Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512 => DecodingKey::from_rsa_der(&bytes),
+                Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_der(&bytes),
+                Algorithm::EdDSA => DecodingKey::from_ed_der(&bytes),
+                Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                    DecodingKey::from_secret(&bytes)
+                }
+            },
+            // trailing newlines are almost always an artifact of how the secret was written
+            KeyFileFormat::Secret(_) => DecodingKey::from_secret(bytes.trim_ascii_end()),
+            KeyFileFormat::Jwk => {
+                let jwk: Jwk = serde_json::from_slice(&bytes)
+                    .map_err(|e| KeyFileError::Jwks(self.path.clone(), JwksError::Json(e)))?;
+                return Ok(vec![ResolvedKey::from_jwk(&jwk).map_err(invalid_key)?]);
+            }
+            KeyFileFormat::Jwks => {
+                let json = String::from_utf8_lossy(&bytes);
+                return KeySet::parse_jwks(&json)
+                    .map(KeySet::into_keys)
+                    .map_err(|e| KeyFileError::Jwks(self.path.clone(), e));
+            }
+        };
+        let mut key = ResolvedKey::new(key);
+        key.algorithm = self.algorithm;
+        key.kid = self.kid.clone();
+        Ok(vec![key])
+    }
+}
+
+#[derive(Debug)]
+pub enum KeyFileError {
+    Io(PathBuf, io::Error),
+    Key(PathBuf, jsonwebtoken::errors::Error),
+    Jwks(PathBuf, JwksError),
+    NoKeys,
+    #[cfg(feature = "notify")]
+    Watch(notify::Error),
+}
+
+impl fmt::Display for KeyFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyFileError::Io(path, e) => {
+                write!(f, "failed to read key file {}: {}", path.display(), e)
+            }
+            KeyFileError::Key(path, e) => {
+                write!(f, "failed to parse key file {}: {}", path.display(), e)
+            }
+            KeyFileError::Jwks(path, e) => write!(f, "{}: {}", path.display(), e),
+            KeyFileError::NoKeys => f.write_str("no key files configured"),
+            #[cfg(feature = "notify")]
+            KeyFileError::Watch(e) => write!(f, "failed to watch key files: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeyFileError {}
+
+#[allow(clippy::type_complexity)]
+struct FileKeysInner {
+    files: Vec<KeyFile>,
+    keys: ArcSwap<KeySet>,
+    // modification times of the files the current keys were loaded from
+    modified: Mutex<Vec<Option<SystemTime>>>,
+    last_checked: Mutex<Instant>,
+    last_reloaded: Mutex<Instant>,
+    generation: AtomicU64,
+    on_reload: Option<Arc<dyn Fn(&Result<(), KeyFileError>) + Send + Sync>>,
+}
+
+impl FileKeysInner {
+    fn modification_times(&self) -> Vec<Option<SystemTime>> {
+        self.files
+            .iter()
+            // `fs::metadata` follows symlinks, Kubernetes swaps the `..data` link on updates
+            .map(|file| fs::metadata(&file.path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    fn reload(&self) -> Result<(), KeyFileError> {
+        let modified = self.modification_times();
+        let result = self
+            .files
+            .iter()
+            .map(KeyFile::load)
+            .collect::<Result<Vec<_>, _>>()
+            .map(|keys| {
+                // the previous keys stay active until every file parses
+                self.keys
+                    .store(Arc::new(KeySet::from_keys(keys.into_iter().flatten())));
+                *self.modified.lock().unwrap() = modified;
+                *self.last_reloaded.lock().unwrap() = Instant::now();
+                self.generation.fetch_add(1, Ordering::Relaxed);
+            });
+        telemetry::key_reload(result.is_ok());
+        if let Some(on_reload) = &self.on_reload {
+            on_reload(&result);
+        }
+        result
+    }
+
+    // `None` when no file changed since the last successful reload
+    fn reload_if_modified(&self) -> Option<Result<(), KeyFileError>> {
+        *self.last_checked.lock().unwrap() = Instant::now();
+        if *self.modified.lock().unwrap() == self.modification_times() {
+            return None;
+        }
+        Some(self.reload())
+    }
+}
+
+// keys loaded from files, reloaded when a file's modification time changes;
+// existing `JwtService` instances pick up reloaded keys since they share the resolver
+#[derive(Clone)]
+pub struct FileKeyResolver {
+    inner: Arc<FileKeysInner>,
+    poll_interval: Duration,
+}
+
+impl FileKeyResolver {
+    pub fn load(files: Vec<KeyFile>) -> Result<Self, KeyFileError> {
+        Self::load_with(files, None)
+    }
+
+    // `on_reload` is called with the outcome of every reload attempt, including the initial load
+    pub fn load_with_reporting<F>(files: Vec<KeyFile>, on_reload: F) -> Result<Self, KeyFileError>
+    where
+        F: Fn(&Result<(), KeyFileError>) + Send + Sync + 'static,
+    {
+        Self::load_with(files, Some(Arc::new(on_reload)))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn load_with(
+        files: Vec<KeyFile>,
+        on_reload: Option<Arc<dyn Fn(&Result<(), KeyFileError>) + Send + Sync>>,
+    ) -> Result<Self, KeyFileError> {
+        if files.is_empty() {
+            return Err(KeyFileError::NoKeys);
+        }
+        let resolver = Self {
+            inner: Arc::new(FileKeysInner {
+                files,
+                keys: ArcSwap::from_pointee(KeySet::empty()),
+                modified: Mutex::new(Vec::new()),
+                last_checked: Mutex::new(Instant::now()),
+                last_reloaded: Mutex::new(Instant::now()),
+                generation: AtomicU64::new(0),
+                on_reload,
+            }),
+            poll_interval: Duration::from_secs(30),
+        };
+        resolver.inner.reload()?;
+        Ok(resolver)
+    }
+
+    // files are checked for changes on requests at most once per interval
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn reload(&self) -> Result<(), KeyFileError> {
+        self.inner.reload()
+    }
+
+    pub fn last_reloaded(&self) -> Instant {
+        *self.inner.last_reloaded.lock().unwrap()
+    }
+
+    // reloads as soon as the files change instead of waiting for the next poll,
+    // watching stops when the returned watcher is dropped
+    #[cfg(feature = "notify")]
+    pub fn watch(&self) -> Result<notify::RecommendedWatcher, KeyFileError> {
+        use notify::{RecursiveMode, Watcher};
+
+        let inner = Arc::downgrade(&self.inner);
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let (Ok(_), Some(inner)) = (event, inner.upgrade()) {
+                    let _ = inner.reload_if_modified();
+                }
+            })
+            .map_err(KeyFileError::Watch)?;
+        // directories are watched since files are usually replaced rather than written to
+        let mut directories: Vec<&Path> = self
+            .inner
+            .files
+            .iter()
+            .map(|file| file.path.parent().unwrap_or(Path::new(".")))
+            .collect();
+        directories.sort();
+        directories.dedup();
+        for directory in directories {
+            watcher
+                .watch(directory, RecursiveMode::NonRecursive)
+                .map_err(KeyFileError::Watch)?;
+        }
+        Ok(watcher)
+    }
+
+    fn poll_due(&self) -> bool {
+        let mut last_checked = self.inner.last_checked.lock().unwrap();
+        if last_checked.elapsed() < self.poll_interval {
+            return false;
+        }
+        *last_checked = Instant::now();
+        true
+    }
+}
+
+impl KeyResolver for FileKeyResolver {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(async move {
+            let key = self.inner.keys.load().find(header.kid.as_deref());
+            if self.poll_due() {
+                let inner = self.inner.clone();
+                let check = spawn_blocking(move || inner.reload_if_modified());
+                match &key {
+                    // the current keys are served until the check completes
+                    Some(_) => drop(check),
+                    // unknown `kid` might mean the keys were just rotated
+                    None => {
+                        let _ = check.await;
+                        return self
+                            .inner
+                            .keys
+                            .load()
+                            .find(header.kid.as_deref())
+                            .ok_or(JwtDecodeErrors::UnknownKey);
+                    }
+                }
+            }
+            key.ok_or(JwtDecodeErrors::UnknownKey)
+        })
+    }
+
+    fn generation(&self) -> u64 {
+        self.inner.generation.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use actix_web::rt::task::spawn_blocking;
+use arc_swap::ArcSwap;
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet, PublicKeyUse},
+    Algorithm, DecodingKey, Header,
+};
+
+use crate::{telemetry, JwtDecodeErrors};
+
+#[derive(Clone)]
+pub struct ResolvedKey {
+    pub key: Arc<DecodingKey>,
+    pub algorithm: Option<Algorithm>,
+    pub kid: Option<String>,
+}
+
+impl ResolvedKey {
+    pub fn new(key: DecodingKey) -> Self {
+        Self {
+            key: Arc::new(key),
+            algorithm: None,
+            kid: None,
+        }
+    }
+
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, jsonwebtoken::errors::Error> {
+        let algorithm = match jwk.common.key_algorithm {
+            Some(key_algorithm) => Some(Algorithm::from_str(&key_algorithm.to_string())?),
+            None => None,
+        };
+        Ok(Self {
+            key: Arc::new(DecodingKey::from_jwk(jwk)?),
+            algorithm,
+            kid: jwk.common.key_id.clone(),
+        })
+    }
+}
+
+pub trait KeyResolver: Send + Sync {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>>;
+
+    // changes whenever the keys change, cached verification results of older generations aren't used
+    fn generation(&self) -> u64 {
+        0
+    }
+}
+
+impl KeyResolver for ResolvedKey {
+    fn resolve<'a>(
+        &'a self,
+        _header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(ready(Ok(self.clone())))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum JwksSource {
+    File(PathBuf),
+    Json(String),
+    #[cfg(feature = "http")]
+    Url(String),
+}
+
+impl JwksSource {
+    #[cfg_attr(not(feature = "http"), allow(unused_variables))]
+    fn fetch(&self, timeout: Duration) -> Result<String, JwksError> {
+        match self {
+            JwksSource::File(path) => fs::read_to_string(path).map_err(JwksError::Io),
+            JwksSource::Json(json) => Ok(json.clone()),
+            #[cfg(feature = "http")]
+            JwksSource::Url(url) => {
+                crate::http::get(&crate::http::agent(timeout), url).map_err(JwksError::Http)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JwksError {
+    Io(io::Error),
+    Http(String),
+    Json(serde_json::Error),
+    NoUsableKeys,
+}
+
+impl fmt::Display for JwksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwksError::Io(e) => write!(f, "failed to read JWK set: {}", e),
+            JwksError::Http(e) => write!(f, "failed to fetch JWK set: {}", e),
+            JwksError::Json(e) => write!(f, "failed to parse JWK set: {}", e),
+            JwksError::NoUsableKeys => {
+                f.write_str("JWK set does not contain any usable signing keys")
+            }
+        }
+    }
+}
+
+impl std::error::Error for JwksError {}
+
+pub(crate) struct KeySet {
+    keys: HashMap<String, ResolvedKey>,
+    unnamed: Vec<ResolvedKey>,
+}
+
+impl KeySet {
+    pub(crate) fn empty() -> Self {
+        Self {
+            keys: HashMap::new(),
+            unnamed: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_keys(keys: impl IntoIterator<Item = ResolvedKey>) -> Self {
+        let mut set = Self::empty();
+        for key in keys {
+            match &key.kid {
+                Some(kid) => {
+                    set.keys.insert(kid.clone(), key);
+                }
+                None => set.unnamed.push(key),
+            }
+        }
+        set
+    }
+
+    pub(crate) fn parse_jwks(json: &str) -> Result<Self, JwksError> {
+        let jwks: JwkSet = serde_json::from_str(json).map_err(JwksError::Json)?;
+        let set = Self::from_keys(
+            jwks.keys
+                .iter()
+                .filter(|jwk| !matches!(jwk.common.public_key_use, Some(PublicKeyUse::Encryption)))
+                // keys with unsupported parameters or encryption algorithms can't verify signatures
+                .filter_map(|jwk| ResolvedKey::from_jwk(jwk).ok()),
+        );
+        if set.is_empty() {
+            return Err(JwksError::NoUsableKeys);
+        }
+        Ok(set)
+    }
+
+    pub(crate) fn into_keys(self) -> Vec<ResolvedKey> {
+        self.keys.into_values().chain(self.unnamed).collect()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.unnamed.is_empty()
+    }
+
+    pub(crate) fn find(&self, kid: Option<&str>) -> Option<ResolvedKey> {
+        match kid {
+            Some(kid) => self.keys.get(kid).cloned(),
+            // a token without `kid` is only unambiguous when there is a single key
+            None if self.keys.len() + self.unnamed.len() == 1 => self
+                .keys
+                .values()
+                .chain(self.unnamed.iter())
+                .next()
+                .cloned(),
+            None => None,
+        }
+    }
+}
+
+impl KeyResolver for KeySet {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(ready(
+            self.find(header.kid.as_deref())
+                .ok_or(JwtDecodeErrors::UnknownKey),
+        ))
+    }
+}
+
+struct CachedKeySet {
+    set: KeySet,
+    loaded_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct RefreshState {
+    last_attempt: Option<Instant>,
+    in_flight: bool,
+}
+
+struct JwksInner {
+    source: JwksSource,
+    cache: ArcSwap<CachedKeySet>,
+    state: Mutex<RefreshState>,
+    generation: AtomicU64,
+}
+
+impl JwksInner {
+    fn begin_refresh(&self, min_interval: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.in_flight {
+            return false;
+        }
+        if let Some(last_attempt) = state.last_attempt {
+            if last_attempt.elapsed() < min_interval {
+                return false;
+            }
+        }
+        state.last_attempt = Some(Instant::now());
+        state.in_flight = true;
+        true
+    }
+
+    fn refresh(&self, timeout: Duration) -> Result<(), JwksError> {
+        let result = self
+            .source
+            .fetch(timeout)
+            .and_then(|json| KeySet::parse_jwks(&json))
+            .map(|set| {
+                self.cache.store(Arc::new(CachedKeySet {
+                    set,
+                    loaded_at: Some(Instant::now()),
+                }));
+                self.generation.fetch_add(1, Ordering::Relaxed);
+            });
+        // on failure the previously cached set keeps being served
+        self.state.lock().unwrap().in_flight = false;
+        result
+    }
+}
+
+#[derive(Clone)]
+pub struct JwksKeyResolver {
+    inner: Arc<JwksInner>,
+    refresh_interval: Duration,
+    min_refresh_interval: Duration,
+    fetch_timeout: Duration,
+}
+
+impl JwksKeyResolver {
+    pub fn new(source: JwksSource) -> Self {
+        Self {
+            inner: Arc::new(JwksInner {
+                source,
+                cache: ArcSwap::from_pointee(CachedKeySet {
+                    set: KeySet::empty(),
+                    loaded_at: None,
+                }),
+                state: Mutex::new(RefreshState::default()),
+                generation: AtomicU64::new(0),
+            }),
+            refresh_interval: Duration::from_secs(60 * 60),
+            min_refresh_interval: Duration::from_secs(30),
+            fetch_timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn load(source: JwksSource) -> Result<Self, JwksError> {
+        let resolver = Self::new(source);
+        resolver.refresh()?;
+        Ok(resolver)
+    }
+
+    pub fn refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    pub fn min_refresh_interval(mut self, min_refresh_interval: Duration) -> Self {
+        self.min_refresh_interval = min_refresh_interval;
+        self
+    }
+
+    pub fn fetch_timeout(mut self, fetch_timeout: Duration) -> Self {
+        self.fetch_timeout = fetch_timeout;
+        self
+    }
+
+    pub fn refresh(&self) -> Result<(), JwksError> {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            state.last_attempt = Some(Instant::now());
+            state.in_flight = true;
+        }
+        self.inner.refresh(self.fetch_timeout)
+    }
+
+    pub fn last_refreshed(&self) -> Option<Instant> {
+        self.inner.cache.load().loaded_at
+    }
+
+    fn is_stale(&self, cached: &CachedKeySet) -> bool {
+        match cached.loaded_at {
+            Some(loaded_at) => loaded_at.elapsed() >= self.refresh_interval,
+            None => true,
+        }
+    }
+
+    fn spawn_refresh(&self) -> actix_web::rt::task::JoinHandle<Result<(), JwksError>> {
+        let inner = self.inner.clone();
+        let timeout = self.fetch_timeout;
+        spawn_blocking(move || inner.refresh(timeout))
+    }
+}
+
+impl KeyResolver for JwksKeyResolver {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(async move {
+            let cached = self.inner.cache.load_full();
+            let key = cached.set.find(header.kid.as_deref());
+            telemetry::cache_lookup("jwks", key.is_some());
+            if let Some(key) = key {
+                if self.is_stale(&cached) && self.inner.begin_refresh(self.min_refresh_interval) {
+                    // scheduled refresh, the current set is served until it completes
+                    drop(self.spawn_refresh());
+                }
+                return Ok(key);
+            }
+
+            // unknown `kid` might mean the keys were rotated, refresh right away (rate limited)
+            if self.inner.begin_refresh(self.min_refresh_interval) {
+                let _ = self.spawn_refresh().await;
+                if let Some(key) = self.inner.cache.load().set.find(header.kid.as_deref()) {
+                    return Ok(key);
+                }
+            }
+
+            if self.inner.cache.load().loaded_at.is_none() {
+                Err(JwtDecodeErrors::KeysUnavailable)
+            } else {
+                Err(JwtDecodeErrors::UnknownKey)
+            }
+        })
+    }
+
+    fn generation(&self) -> u64 {
+        self.inner.generation.load(Ordering::Relaxed)
+    }
+}
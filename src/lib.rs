@@ -1,3 +1,62 @@
+mod audit;
+mod authz;
+mod bearer_error;
+mod config;
+mod config_handle;
+mod dpop;
+mod extractor;
+#[cfg(feature = "http")]
+mod http;
+mod introspection;
+mod issuers;
+#[cfg(feature = "jwe")]
+mod jwe;
 mod jwt;
+mod key_files;
+mod keys;
+mod mtls;
+#[cfg(feature = "paseto")]
+mod paseto;
+mod refresh;
+mod registered_claims;
+mod revocation;
+mod signing;
+mod telemetry;
+#[cfg(feature = "testing")]
+mod testing;
+mod throttle;
+mod token_cache;
+mod token_format;
+mod token_source;
+mod verified_token;
 
+pub use audit::*;
+pub use authz::*;
+pub use bearer_error::*;
+pub use config::*;
+pub use config_handle::*;
+pub use dpop::*;
+pub use extractor::*;
+pub use introspection::*;
+pub use issuers::*;
+#[cfg(feature = "jwe")]
+pub use jwe::*;
 pub use jwt::*;
+pub use key_files::*;
+pub use keys::*;
+pub use mtls::*;
+#[cfg(feature = "paseto")]
+pub use paseto::*;
+pub use refresh::*;
+pub use registered_claims::*;
+pub use revocation::*;
+pub use signing::*;
+#[cfg(feature = "tracing")]
+pub use telemetry::*;
+#[cfg(feature = "testing")]
+pub use testing::*;
+pub use throttle::*;
+pub use token_cache::*;
+pub use token_format::*;
+pub use token_source::*;
+pub use verified_token::*;
@@ -0,0 +1,120 @@
+#[cfg(feature = "rustls")]
+use std::any::Any;
+
+#[cfg(feature = "rustls")]
+use actix_web::dev::Extensions;
+use actix_web::{dev::ServiceRequest, http::header::HeaderName};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::digest;
+
+use crate::{JwtDecodeErrors, RegisteredClaims};
+
+// DER encoded client certificate, stored as connection data by `CertificateBinding::on_connect`
+// or by a custom `HttpServer::on_connect` callback
+#[derive(Clone, Debug)]
+pub struct PeerCertificate(pub Vec<u8>);
+
+// RFC 8705 certificate-bound access tokens, `cnf.x5t#S256` is compared with the SHA-256
+// thumbprint of the client certificate
+#[derive(Clone, Default)]
+pub struct CertificateBinding {
+    trusted_header: Option<HeaderName>,
+    required: bool,
+}
+
+impl CertificateBinding {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the client certificate forwarded by a TLS terminating proxy, either as (URL encoded) PEM
+    // or as base64 DER, the proxy has to overwrite this header on every request
+    pub fn trusted_header(mut self, name: HeaderName) -> Self {
+        self.trusted_header = Some(name);
+        self
+    }
+
+    // rejects tokens without `cnf.x5t#S256`
+    pub fn required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    // pass to `HttpServer::on_connect` when terminating TLS with rustls in actix-web
+    #[cfg(feature = "rustls")]
+    pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+        use actix_tls::accept::rustls_0_23::TlsStream;
+        use actix_web::rt::net::TcpStream;
+
+        let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+            return;
+        };
+        let (_, session) = stream.get_ref();
+        if let Some(certificate) = session.peer_certificates().and_then(|chain| chain.first()) {
+            data.insert(PeerCertificate(certificate.to_vec()));
+        }
+    }
+
+    pub(crate) fn check(
+        &self,
+        req: &ServiceRequest,
+        registered: &RegisteredClaims,
+    ) -> Result<(), JwtDecodeErrors> {
+        let Some(expected) = registered
+            .cnf
+            .as_ref()
+            .and_then(|cnf| cnf.x5t_s256.as_deref())
+        else {
+            if self.required {
+                return Err(JwtDecodeErrors::CertificateMismatch);
+            }
+            return Ok(());
+        };
+        let thumbprint = self.peer_certificate(req).map(|certificate| {
+            URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, &certificate))
+        });
+        match thumbprint {
+            Some(thumbprint) if thumbprint == expected => Ok(()),
+            _ => Err(JwtDecodeErrors::CertificateMismatch),
+        }
+    }
+
+    fn peer_certificate(&self, req: &ServiceRequest) -> Option<Vec<u8>> {
+        if let Some(certificate) = req.conn_data::<PeerCertificate>() {
+            return Some(certificate.0.clone());
+        }
+        let value = req.headers().get(self.trusted_header.as_ref()?)?;
+        parse_forwarded_certificate(value.to_str().ok()?)
+    }
+}
+
+fn parse_forwarded_certificate(value: &str) -> Option<Vec<u8>> {
+    let value = percent_decode(value.trim())?;
+    let body = match value.split_once("-----BEGIN CERTIFICATE-----") {
+        Some((_, rest)) => rest.split_once("-----END CERTIFICATE-----")?.0,
+        None => &value,
+    };
+    let body: String = body.split_whitespace().collect();
+    STANDARD
+        .decode(&body)
+        .or_else(|_| URL_SAFE_NO_PAD.decode(&body))
+        .ok()
+}
+
+// unlike form decoding `+` is kept as is, it's part of the base64 alphabet
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [input.next()?, input.next()?];
+        bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,323 @@
+use std::collections::HashSet;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chacha20::{
+    cipher::{KeyIvInit, StreamCipher},
+    XChaCha20,
+};
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::{errors::ErrorKind, get_current_timestamp, Validation};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{Confirmation, DecodedToken, JwtDecodeErrors, RegisteredClaims, TokenFormat};
+
+const LOCAL_HEADER: &str = "v4.local.";
+const PUBLIC_HEADER: &str = "v4.public.";
+
+#[derive(Clone)]
+enum PasetoKeyMaterial {
+    Local([u8; 32]),
+    Public([u8; 32]),
+}
+
+#[derive(Clone)]
+pub struct PasetoKey {
+    material: PasetoKeyMaterial,
+    kid: Option<String>,
+}
+
+impl PasetoKey {
+    pub fn local(key: [u8; 32]) -> Self {
+        Self {
+            material: PasetoKeyMaterial::Local(key),
+            kid: None,
+        }
+    }
+
+    pub fn public(key: [u8; 32]) -> Self {
+        Self {
+            material: PasetoKeyMaterial::Public(key),
+            kid: None,
+        }
+    }
+
+    // matched against the `kid` of a JSON footer
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+}
+
+// registered claims are checked with the same `Validation` settings as JWTs,
+// except that PASETO timestamps are RFC 3339 strings and algorithms are fixed by the version
+#[derive(Clone)]
+pub struct PasetoV4 {
+    keys: Vec<PasetoKey>,
+    footer: Option<Vec<u8>>,
+    implicit_assertion: Vec<u8>,
+    validation: Validation,
+}
+
+impl PasetoV4 {
+    pub fn new(validation: Validation) -> Self {
+        Self {
+            keys: Vec::new(),
+            footer: None,
+            implicit_assertion: Vec::new(),
+            validation,
+        }
+    }
+
+    pub fn key(mut self, key: PasetoKey) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    // when set, tokens must carry exactly this footer
+    pub fn footer(mut self, footer: impl Into<Vec<u8>>) -> Self {
+        self.footer = Some(footer.into());
+        self
+    }
+
+    pub fn implicit_assertion(mut self, implicit_assertion: impl Into<Vec<u8>>) -> Self {
+        self.implicit_assertion = implicit_assertion.into();
+        self
+    }
+
+    fn decode_token(&self, token: &str) -> Result<DecodedToken, JwtDecodeErrors> {
+        let (header, rest) = if let Some(rest) = token.strip_prefix(LOCAL_HEADER) {
+            (LOCAL_HEADER, rest)
+        } else if let Some(rest) = token.strip_prefix(PUBLIC_HEADER) {
+            (PUBLIC_HEADER, rest)
+        } else {
+            return Err(invalid(ErrorKind::InvalidToken));
+        };
+        let (payload, footer) = match rest.split_once('.') {
+            Some((payload, footer)) => (decode_part(payload)?, decode_part(footer)?),
+            None => (decode_part(rest)?, Vec::new()),
+        };
+        if self
+            .footer
+            .as_ref()
+            .is_some_and(|expected| *expected != footer)
+        {
+            return Err(invalid(ErrorKind::InvalidToken));
+        }
+        let kid = footer_kid(&footer);
+
+        let candidates = self.keys.iter().filter(|key| match (&kid, &key.kid) {
+            (Some(kid), Some(key_kid)) => kid == key_kid,
+            _ => true,
+        });
+        let mut message = None;
+        let mut key_id = None;
+        for key in candidates {
+            message = match (&key.material, header) {
+                (PasetoKeyMaterial::Local(key), LOCAL_HEADER) => {
+                    decrypt_local(key, &payload, &footer, &self.implicit_assertion)
+                }
+                (PasetoKeyMaterial::Public(key), PUBLIC_HEADER) => {
+                    verify_public(key, &payload, &footer, &self.implicit_assertion)
+                }
+                _ => continue,
+            };
+            if message.is_some() {
+                key_id = key.kid.clone();
+                break;
+            }
+        }
+        let message = message.ok_or_else(|| invalid(ErrorKind::InvalidSignature))?;
+        let claims: Value =
+            serde_json::from_slice(&message).map_err(|_| invalid(ErrorKind::InvalidToken))?;
+        let registered = self.validate(&claims)?;
+        Ok(DecodedToken {
+            claims,
+            registered,
+            key_id,
+        })
+    }
+
+    fn validate(&self, claims: &Value) -> Result<RegisteredClaims, JwtDecodeErrors> {
+        let validation = &self.validation;
+        let string = |claim: &str| claims.get(claim).and_then(Value::as_str).map(str::to_owned);
+        let registered = RegisteredClaims {
+            iss: string("iss"),
+            sub: string("sub"),
+            jti: string("jti"),
+            iat: timestamp(claims, "iat")?,
+            nbf: timestamp(claims, "nbf")?,
+            exp: timestamp(claims, "exp")?,
+            cnf: claims
+                .get("cnf")
+                .and_then(|cnf| Confirmation::deserialize(cnf).ok()),
+        };
+        for claim in &validation.required_spec_claims {
+            if claims.get(claim).is_none() {
+                return Err(invalid(ErrorKind::MissingRequiredClaim(claim.clone())));
+            }
+        }
+
+        let now = get_current_timestamp();
+        if validation.validate_exp
+            && registered
+                .exp
+                .is_some_and(|exp| exp < now.saturating_sub(validation.leeway))
+        {
+            return Err(invalid(ErrorKind::ExpiredSignature));
+        }
+        if validation.validate_nbf
+            && registered
+                .nbf
+                .is_some_and(|nbf| nbf > now + validation.leeway)
+        {
+            return Err(invalid(ErrorKind::ImmatureSignature));
+        }
+        if let Some(expected) = &validation.iss {
+            if !registered
+                .iss
+                .as_ref()
+                .is_some_and(|iss| expected.contains(iss))
+            {
+                return Err(invalid(ErrorKind::InvalidIssuer));
+            }
+        }
+        if let Some(expected) = &validation.sub {
+            if registered.sub.as_ref() != Some(expected) {
+                return Err(invalid(ErrorKind::InvalidSubject));
+            }
+        }
+        if let (true, Some(expected)) = (validation.validate_aud, &validation.aud) {
+            if audiences(claims).is_none_or(|aud| aud.is_disjoint(expected)) {
+                return Err(invalid(ErrorKind::InvalidAudience));
+            }
+        }
+        Ok(registered)
+    }
+}
+
+impl TokenFormat for PasetoV4 {
+    fn accepts(&self, token: &str) -> bool {
+        token.starts_with(LOCAL_HEADER) || token.starts_with(PUBLIC_HEADER)
+    }
+
+    fn decode<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> LocalBoxFuture<'a, Result<DecodedToken, JwtDecodeErrors>> {
+        Box::pin(ready(self.decode_token(token)))
+    }
+}
+
+fn verify_public(
+    key: &[u8; 32],
+    payload: &[u8],
+    footer: &[u8],
+    implicit: &[u8],
+) -> Option<Vec<u8>> {
+    let split = payload.len().checked_sub(64)?;
+    let (message, signature) = payload.split_at(split);
+    let pre_auth = pae(&[PUBLIC_HEADER.as_bytes(), message, footer, implicit]);
+    UnparsedPublicKey::new(&ED25519, key)
+        .verify(&pre_auth, signature)
+        .ok()?;
+    Some(message.to_vec())
+}
+
+fn decrypt_local(
+    key: &[u8; 32],
+    payload: &[u8],
+    footer: &[u8],
+    implicit: &[u8],
+) -> Option<Vec<u8>> {
+    if payload.len() < 64 {
+        return None;
+    }
+    let (nonce, rest) = payload.split_at(32);
+    let (ciphertext, tag) = rest.split_at(rest.len() - 32);
+
+    let derived = blake2b_simd::Params::new()
+        .hash_length(56)
+        .key(key)
+        .to_state()
+        .update(b"paseto-encryption-key")
+        .update(nonce)
+        .finalize();
+    let (encryption_key, counter_nonce) = derived.as_bytes().split_at(32);
+    let auth_key = blake2b_simd::Params::new()
+        .hash_length(32)
+        .key(key)
+        .to_state()
+        .update(b"paseto-auth-key-for-aead")
+        .update(nonce)
+        .finalize();
+
+    let pre_auth = pae(&[LOCAL_HEADER.as_bytes(), nonce, ciphertext, footer, implicit]);
+    let expected = blake2b_simd::Params::new()
+        .hash_length(32)
+        .key(auth_key.as_bytes())
+        .hash(&pre_auth);
+    // `blake2b_simd::Hash` compares in constant time
+    if expected != *tag {
+        return None;
+    }
+
+    let mut message = ciphertext.to_vec();
+    XChaCha20::new(encryption_key.into(), counter_nonce.into()).apply_keystream(&mut message);
+    Some(message)
+}
+
+// pre-authentication encoding, see the PASETO specification
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    let le64 = |n: usize| ((n as u64) & (u64::MAX >> 1)).to_le_bytes();
+    let mut output = le64(pieces.len()).to_vec();
+    for piece in pieces {
+        output.extend_from_slice(&le64(piece.len()));
+        output.extend_from_slice(piece);
+    }
+    output
+}
+
+fn footer_kid(footer: &[u8]) -> Option<String> {
+    if !footer.starts_with(b"{") {
+        return None;
+    }
+    let footer: Value = serde_json::from_slice(footer).ok()?;
+    footer.get("kid")?.as_str().map(str::to_owned)
+}
+
+fn timestamp(claims: &Value, claim: &str) -> Result<Option<u64>, JwtDecodeErrors> {
+    let Some(value) = claims.get(claim) else {
+        return Ok(None);
+    };
+    value
+        .as_str()
+        .and_then(|value| OffsetDateTime::parse(value, &Rfc3339).ok())
+        .map(|time| Some(time.unix_timestamp().max(0) as u64))
+        .ok_or_else(|| invalid(ErrorKind::InvalidToken))
+}
+
+fn audiences(claims: &Value) -> Option<HashSet<String>> {
+    match claims.get("aud")? {
+        Value::String(aud) => Some(HashSet::from([aud.clone()])),
+        Value::Array(aud) => Some(
+            aud.iter()
+                .filter_map(Value::as_str)
+                .map(str::to_owned)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn decode_part(part: &str) -> Result<Vec<u8>, JwtDecodeErrors> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|e| invalid(ErrorKind::Base64(e)))
+}
+
+fn invalid(kind: ErrorKind) -> JwtDecodeErrors {
+    JwtDecodeErrors::InvalidJWTToken(kind.into())
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::{http::StatusCode, web, Either, HttpResponse, Resource, ResponseError};
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::get_current_timestamp;
+use ring::rand::SystemRandom;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    signing::{hash_token, random_token},
+    IssueError, JwtIssuer,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RefreshTokenRecord {
+    pub family_id: String,
+    // access token claims without the registered claims the issuer stamps on every issuance
+    pub claims: Map<String, Value>,
+    pub expires_at: u64,
+    pub used: bool,
+    pub revoked: bool,
+}
+
+// refresh tokens are looked up by their SHA-256 hash, stores never see the tokens themselves
+pub trait RefreshTokenStore: Send + Sync {
+    // must fail with `RefreshError::InvalidGrant` when the record's family was revoked,
+    // a rotation racing with the detection of a reused token must not outlive the revocation
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        record: RefreshTokenRecord,
+    ) -> LocalBoxFuture<'a, Result<(), RefreshError>>;
+
+    // must atomically mark the record as used and return it as it was before
+    fn mark_used<'a>(
+        &'a self,
+        token_hash: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<RefreshTokenRecord>, RefreshError>>;
+
+    // revokes existing records of the family and every record inserted for it later on
+    fn revoke_family<'a>(
+        &'a self,
+        family_id: &'a str,
+    ) -> LocalBoxFuture<'a, Result<(), RefreshError>>;
+
+    fn is_family_revoked<'a>(
+        &'a self,
+        family_id: &'a str,
+    ) -> LocalBoxFuture<'a, Result<bool, RefreshError>>;
+}
+
+// expired records are purged at most once per interval instead of on every insert
+const PURGE_INTERVAL: u64 = 60;
+
+#[derive(Default)]
+struct MemoryRecords {
+    records: HashMap<String, RefreshTokenRecord>,
+    // revoked family ids and until when they have to be remembered
+    revoked_families: HashMap<String, u64>,
+    next_purge: u64,
+}
+
+impl MemoryRecords {
+    fn purge(&mut self, now: u64) {
+        if now < self.next_purge {
+            return;
+        }
+        self.records.retain(|_, record| record.expires_at > now);
+        self.revoked_families
+            .retain(|_, expires_at| *expires_at > now);
+        self.next_purge = now + PURGE_INTERVAL;
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryRefreshTokenStore {
+    records: Arc<Mutex<MemoryRecords>>,
+}
+
+impl MemoryRefreshTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RefreshTokenStore for MemoryRefreshTokenStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        record: RefreshTokenRecord,
+    ) -> LocalBoxFuture<'a, Result<(), RefreshError>> {
+        let now = get_current_timestamp();
+        let mut records = self.records.lock().unwrap();
+        records.purge(now);
+        if records.revoked_families.contains_key(&record.family_id) {
+            return Box::pin(ready(Err(RefreshError::InvalidGrant)));
+        }
+        records.records.insert(token_hash.to_owned(), record);
+        Box::pin(ready(Ok(())))
+    }
+
+    fn mark_used<'a>(
+        &'a self,
+        token_hash: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<RefreshTokenRecord>, RefreshError>> {
+        let record = self
+            .records
+            .lock()
+            .unwrap()
+            .records
+            .get_mut(token_hash)
+            .map(|record| {
+                let previous = record.clone();
+                record.used = true;
+                previous
+            });
+        Box::pin(ready(Ok(record)))
+    }
+
+    fn revoke_family<'a>(
+        &'a self,
+        family_id: &'a str,
+    ) -> LocalBoxFuture<'a, Result<(), RefreshError>> {
+        let mut records = self.records.lock().unwrap();
+        // remembered as long as any token of the family could still be presented,
+        // and at least until rotations in flight have inserted their records
+        let mut expires_at = get_current_timestamp() + PURGE_INTERVAL;
+        for record in records.records.values_mut() {
+            if record.family_id == family_id {
+                record.revoked = true;
+                expires_at = expires_at.max(record.expires_at);
+            }
+        }
+        let revoked = records
+            .revoked_families
+            .entry(family_id.to_owned())
+            .or_default();
+        *revoked = (*revoked).max(expires_at);
+        Box::pin(ready(Ok(())))
+    }
+
+    fn is_family_revoked<'a>(
+        &'a self,
+        family_id: &'a str,
+    ) -> LocalBoxFuture<'a, Result<bool, RefreshError>> {
+        let revoked = self
+            .records
+            .lock()
+            .unwrap()
+            .revoked_families
+            .contains_key(family_id);
+        Box::pin(ready(Ok(revoked)))
+    }
+}
+
+#[derive(Debug)]
+pub enum RefreshError {
+    InvalidGrant,
+    Reused,
+    UnsupportedGrantType,
+    Issue(IssueError),
+    Store(String),
+}
+
+impl fmt::Display for RefreshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefreshError::InvalidGrant => {
+                f.write_str("refresh token is invalid, expired or revoked")
+            }
+            RefreshError::Reused => {
+                f.write_str("refresh token was already used, the token family has been revoked")
+            }
+            RefreshError::UnsupportedGrantType => f.write_str("grant type must be refresh_token"),
+            RefreshError::Issue(e) => write!(f, "failed to issue tokens: {}", e),
+            RefreshError::Store(e) => write!(f, "refresh token store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RefreshError {}
+
+// error responses follow RFC 6749 section 5.2
+impl ResponseError for RefreshError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RefreshError::InvalidGrant
+            | RefreshError::Reused
+            | RefreshError::UnsupportedGrantType => StatusCode::BAD_REQUEST,
+            RefreshError::Issue(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            RefreshError::Store(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error = match self {
+            RefreshError::InvalidGrant | RefreshError::Reused => serde_json::json!({
+                "error": "invalid_grant",
+                "error_description": self.to_string(),
+            }),
+            RefreshError::UnsupportedGrantType => serde_json::json!({
+                "error": "unsupported_grant_type",
+                "error_description": self.to_string(),
+            }),
+            _ => serde_json::json!({ "error": "server_error" }),
+        };
+        HttpResponse::build(self.status_code())
+            .insert_header(("Cache-Control", "no-store"))
+            .json(error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: u64,
+    pub refresh_token: String,
+    pub refresh_expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshRequest {
+    grant_type: Option<String>,
+    refresh_token: String,
+}
+
+#[allow(clippy::type_complexity)]
+pub struct RefreshTokens<T> {
+    issuer: JwtIssuer<T>,
+    store: Arc<dyn RefreshTokenStore>,
+    lifetime: Duration,
+    reuse_handler: Option<Arc<dyn Fn(&RefreshTokenRecord) + Send + Sync>>,
+    random: Arc<SystemRandom>,
+}
+
+impl<T> Clone for RefreshTokens<T> {
+    fn clone(&self) -> Self {
+        Self {
+            issuer: self.issuer.clone(),
+            store: self.store.clone(),
+            lifetime: self.lifetime,
+            reuse_handler: self.reuse_handler.clone(),
+            random: self.random.clone(),
+        }
+    }
+}
+
+impl<T> RefreshTokens<T> {
+    pub fn new<S>(issuer: JwtIssuer<T>, store: S) -> Self
+    where
+        S: RefreshTokenStore + 'static,
+    {
+        Self {
+            issuer,
+            store: Arc::new(store),
+            lifetime: Duration::from_secs(14 * 24 * 60 * 60),
+            reuse_handler: None,
+            random: Arc::new(SystemRandom::new()),
+        }
+    }
+
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    // called after a reused refresh token revoked its family, e.g. to also revoke the subject's access tokens
+    pub fn reuse_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&RefreshTokenRecord) + Send + Sync + 'static,
+    {
+        self.reuse_handler = Some(Arc::new(f));
+        self
+    }
+
+    pub fn issuer(&self) -> &JwtIssuer<T> {
+        &self.issuer
+    }
+
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, RefreshError> {
+        let record = self
+            .store
+            .mark_used(&hash_token(refresh_token))
+            .await?
+            .ok_or(RefreshError::InvalidGrant)?;
+        if record.revoked
+            || record.expires_at <= get_current_timestamp()
+            || self.store.is_family_revoked(&record.family_id).await?
+        {
+            return Err(RefreshError::InvalidGrant);
+        }
+        if record.used {
+            self.store.revoke_family(&record.family_id).await?;
+            if let Some(reuse_handler) = &self.reuse_handler {
+                (reuse_handler)(&record);
+            }
+            return Err(RefreshError::Reused);
+        }
+        self.issue_pair(record.family_id, record.claims).await
+    }
+
+    // revokes the whole family, e.g. on logout
+    pub async fn revoke(&self, refresh_token: &str) -> Result<(), RefreshError> {
+        match self.store.mark_used(&hash_token(refresh_token)).await? {
+            Some(record) => self.store.revoke_family(&record.family_id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn issue_pair(
+        &self,
+        family_id: String,
+        claims: Map<String, Value>,
+    ) -> Result<TokenPair, RefreshError> {
+        let access_token = self
+            .issuer
+            .issue_payload(claims.clone())
+            .map_err(RefreshError::Issue)?;
+        let refresh_token =
+            random_token(&self.random, 32).ok_or(RefreshError::Issue(IssueError::Random))?;
+        self.store
+            .insert(
+                &hash_token(&refresh_token),
+                RefreshTokenRecord {
+                    family_id,
+                    claims,
+                    expires_at: get_current_timestamp() + self.lifetime.as_secs(),
+                    used: false,
+                    revoked: false,
+                },
+            )
+            .await?;
+        Ok(TokenPair {
+            access_token: access_token.token,
+            token_type: "Bearer",
+            expires_in: self.issuer.lifetime().as_secs(),
+            refresh_token,
+            refresh_expires_in: self.lifetime.as_secs(),
+        })
+    }
+}
+
+impl<T: Serialize> RefreshTokens<T> {
+    // starts a new token family, e.g. after the user logged in
+    pub async fn issue(&self, claims: &T) -> Result<TokenPair, RefreshError> {
+        let Value::Object(mut claims) =
+            serde_json::to_value(claims).map_err(|e| RefreshError::Issue(IssueError::Claims(e)))?
+        else {
+            return Err(RefreshError::Issue(IssueError::ClaimsNotAnObject));
+        };
+        for claim in ["iat", "nbf", "exp", "jti"] {
+            claims.remove(claim);
+        }
+        let family_id =
+            random_token(&self.random, 16).ok_or(RefreshError::Issue(IssueError::Random))?;
+        self.issue_pair(family_id, claims).await
+    }
+}
+
+impl<T: 'static> RefreshTokens<T> {
+    // token endpoint accepting `grant_type=refresh_token` as a form or JSON body
+    pub fn resource(&self, path: &str) -> Resource {
+        let tokens = self.clone();
+        web::resource(path).route(web::post().to(
+            move |body: Either<web::Form<RefreshRequest>, web::Json<RefreshRequest>>| {
+                let tokens = tokens.clone();
+                async move {
+                    let body = match body {
+                        Either::Left(form) => form.into_inner(),
+                        Either::Right(json) => json.into_inner(),
+                    };
+                    if body
+                        .grant_type
+                        .is_some_and(|grant_type| grant_type != "refresh_token")
+                    {
+                        return Err(RefreshError::UnsupportedGrantType);
+                    }
+                    let pair = tokens.refresh(&body.refresh_token).await?;
+                    Ok::<_, RefreshError>(
+                        HttpResponse::Ok()
+                            .insert_header(("Cache-Control", "no-store"))
+                            .json(pair),
+                    )
+                }
+            },
+        ))
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::errors::ErrorKind;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::JwtDecodeErrors;
+
+// registered claims are read leniently, validating their types is left to `jsonwebtoken` and `T`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct RegisteredClaims {
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub iss: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub sub: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub jti: Option<String>,
+    #[serde(default, deserialize_with = "lenient_timestamp")]
+    pub iat: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_timestamp")]
+    pub nbf: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_timestamp")]
+    pub exp: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_confirmation")]
+    pub cnf: Option<Confirmation>,
+}
+
+// confirmation methods binding the token to a key (RFC 7800)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Confirmation {
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub jkt: Option<String>,
+    #[serde(default, rename = "x5t#S256", deserialize_with = "lenient_string")]
+    pub x5t_s256: Option<String>,
+}
+
+impl RegisteredClaims {
+    // reads the payload without verifying the signature, only use after verification
+    // or to select how the token gets verified
+    pub(crate) fn from_token(token: &str) -> Result<Self, JwtDecodeErrors> {
+        let payload = token
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| JwtDecodeErrors::InvalidJWTToken(ErrorKind::InvalidToken.into()))?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|e| JwtDecodeErrors::InvalidJWTToken(ErrorKind::Base64(e).into()))?;
+        serde_json::from_slice(&payload)
+            .map_err(|e| JwtDecodeErrors::InvalidJWTToken(ErrorKind::Json(Arc::new(e)).into()))
+    }
+}
+
+fn lenient_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(value) => Some(value),
+        _ => None,
+    })
+}
+
+fn lenient_timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(value) => value
+            .as_u64()
+            .or_else(|| value.as_f64().filter(|v| *v >= 0.0).map(|v| v as u64)),
+        _ => None,
+    })
+}
+
+fn lenient_confirmation<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Confirmation>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        value @ Value::Object(_) => Confirmation::deserialize(value).ok(),
+        _ => None,
+    })
+}
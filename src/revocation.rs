@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::get_current_timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::RegisteredClaims;
+
+pub trait RevocationStore: Send + Sync {
+    fn is_revoked<'a>(
+        &'a self,
+        claims: &'a RegisteredClaims,
+    ) -> LocalBoxFuture<'a, Result<bool, RevocationError>>;
+}
+
+#[derive(Debug)]
+pub enum RevocationError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Backend(String),
+}
+
+impl fmt::Display for RevocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevocationError::Io(e) => write!(f, "revocation store I/O error: {}", e),
+            RevocationError::Json(e) => write!(f, "revocation store contains invalid data: {}", e),
+            RevocationError::Backend(e) => write!(f, "revocation store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RevocationError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct SubjectRevocation {
+    issued_before: u64,
+    expires_at: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Revocations {
+    #[serde(default)]
+    tokens: HashMap<String, u64>,
+    #[serde(default)]
+    subjects: HashMap<String, SubjectRevocation>,
+}
+
+impl Revocations {
+    fn is_revoked(&self, claims: &RegisteredClaims, now: u64) -> bool {
+        let token_revoked = claims
+            .jti
+            .as_ref()
+            .and_then(|jti| self.tokens.get(jti))
+            .is_some_and(|expires_at| *expires_at > now);
+        let subject_revoked = claims
+            .sub
+            .as_ref()
+            .and_then(|sub| self.subjects.get(sub))
+            .is_some_and(|revocation| {
+                // tokens without `iat` can't prove they were issued after the cutoff
+                revocation.expires_at > now
+                    && claims.iat.is_none_or(|iat| iat < revocation.issued_before)
+            });
+        token_revoked || subject_revoked
+    }
+
+    fn purge_expired(&mut self, now: u64) {
+        self.tokens.retain(|_, expires_at| *expires_at > now);
+        self.subjects
+            .retain(|_, revocation| revocation.expires_at > now);
+    }
+}
+
+// entries only need to outlive the tokens they revoke, so `ttl` should be at least the token lifetime
+#[derive(Clone, Default)]
+pub struct MemoryRevocationStore {
+    revocations: Arc<RwLock<Revocations>>,
+}
+
+impl MemoryRevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn revoke_token(&self, jti: impl Into<String>, ttl: Duration) {
+        let now = get_current_timestamp();
+        let mut revocations = self.revocations.write().unwrap();
+        revocations.purge_expired(now);
+        revocations.tokens.insert(jti.into(), now + ttl.as_secs());
+    }
+
+    pub fn revoke_subject(&self, sub: impl Into<String>, issued_before: u64, ttl: Duration) {
+        let now = get_current_timestamp();
+        let mut revocations = self.revocations.write().unwrap();
+        revocations.purge_expired(now);
+        revocations.subjects.insert(
+            sub.into(),
+            SubjectRevocation {
+                issued_before,
+                expires_at: now + ttl.as_secs(),
+            },
+        );
+    }
+
+    pub fn revoke_subject_now(&self, sub: impl Into<String>, ttl: Duration) {
+        self.revoke_subject(sub, get_current_timestamp(), ttl);
+    }
+
+    pub fn is_revoked_now(&self, claims: &RegisteredClaims) -> bool {
+        self.revocations
+            .read()
+            .unwrap()
+            .is_revoked(claims, get_current_timestamp())
+    }
+
+    fn snapshot(&self) -> Revocations {
+        self.revocations.read().unwrap().clone()
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn is_revoked<'a>(
+        &'a self,
+        claims: &'a RegisteredClaims,
+    ) -> LocalBoxFuture<'a, Result<bool, RevocationError>> {
+        Box::pin(ready(Ok(self.is_revoked_now(claims))))
+    }
+}
+
+#[derive(Clone)]
+pub struct FileRevocationStore {
+    path: PathBuf,
+    memory: MemoryRevocationStore,
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl FileRevocationStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, RevocationError> {
+        let path = path.into();
+        let mut revocations = match fs::read(&path) {
+            Ok(content) => {
+                serde_json::from_slice::<Revocations>(&content).map_err(RevocationError::Json)?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Revocations::default(),
+            Err(e) => return Err(RevocationError::Io(e)),
+        };
+        revocations.purge_expired(get_current_timestamp());
+        Ok(Self {
+            path,
+            memory: MemoryRevocationStore {
+                revocations: Arc::new(RwLock::new(revocations)),
+            },
+            write_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    pub fn revoke_token(
+        &self,
+        jti: impl Into<String>,
+        ttl: Duration,
+    ) -> Result<(), RevocationError> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.memory.revoke_token(jti, ttl);
+        self.persist()
+    }
+
+    pub fn revoke_subject(
+        &self,
+        sub: impl Into<String>,
+        issued_before: u64,
+        ttl: Duration,
+    ) -> Result<(), RevocationError> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.memory.revoke_subject(sub, issued_before, ttl);
+        self.persist()
+    }
+
+    pub fn revoke_subject_now(
+        &self,
+        sub: impl Into<String>,
+        ttl: Duration,
+    ) -> Result<(), RevocationError> {
+        self.revoke_subject(sub, get_current_timestamp(), ttl)
+    }
+
+    // written to a temporary file first so a crash never leaves a truncated store behind
+    fn persist(&self) -> Result<(), RevocationError> {
+        let content =
+            serde_json::to_vec_pretty(&self.memory.snapshot()).map_err(RevocationError::Json)?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, content).map_err(RevocationError::Io)?;
+        fs::rename(&tmp_path, &self.path).map_err(RevocationError::Io)
+    }
+}
+
+impl RevocationStore for FileRevocationStore {
+    fn is_revoked<'a>(
+        &'a self,
+        claims: &'a RegisteredClaims,
+    ) -> LocalBoxFuture<'a, Result<bool, RevocationError>> {
+        self.memory.is_revoked(claims)
+    }
+}
@@ -0,0 +1,343 @@
+use std::{fmt, marker::PhantomData, sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::{
+    get_current_timestamp, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{JwtDecodeErrors, JwtMiddleware, KeyResolver, KeySet, RegisteredClaims, ResolvedKey};
+
+#[derive(Clone)]
+pub struct SigningKey {
+    encoding: Arc<EncodingKey>,
+    decoding: ResolvedKey,
+}
+
+impl SigningKey {
+    pub fn new(encoding: EncodingKey, decoding: DecodingKey, algorithm: Algorithm) -> Self {
+        Self {
+            encoding: Arc::new(encoding),
+            decoding: ResolvedKey::new(decoding).algorithm(algorithm),
+        }
+    }
+
+    pub fn hmac(secret: &[u8], algorithm: Algorithm) -> Self {
+        Self::new(
+            EncodingKey::from_secret(secret),
+            DecodingKey::from_secret(secret),
+            algorithm,
+        )
+    }
+
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.decoding = self.decoding.kid(kid);
+        self
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        // always set by the constructors
+        self.decoding.algorithm.unwrap_or_default()
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.decoding.kid.as_deref()
+    }
+
+    pub fn verification_key(&self) -> ResolvedKey {
+        self.decoding.clone()
+    }
+}
+
+impl KeyResolver for SigningKey {
+    fn resolve<'a>(
+        &'a self,
+        _header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(ready(Ok(self.decoding.clone())))
+    }
+}
+
+// tokens are signed with the active key, the other keys only verify tokens issued before a rotation
+#[derive(Clone)]
+pub struct SigningKeySet {
+    active: SigningKey,
+    keys: Vec<SigningKey>,
+    verification: Arc<KeySet>,
+}
+
+impl SigningKeySet {
+    pub fn new(active: SigningKey) -> Self {
+        Self {
+            verification: Arc::new(KeySet::from_keys([active.verification_key()])),
+            keys: vec![active.clone()],
+            active,
+        }
+    }
+
+    pub fn key(mut self, key: SigningKey) -> Self {
+        self.keys.push(key);
+        self.verification = Arc::new(KeySet::from_keys(
+            self.keys.iter().map(SigningKey::verification_key),
+        ));
+        self
+    }
+
+    pub fn active(&self) -> &SigningKey {
+        &self.active
+    }
+
+    pub fn active_kid(&self) -> Option<&str> {
+        self.active.key_id()
+    }
+}
+
+impl KeyResolver for SigningKeySet {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        Box::pin(ready(
+            self.verification
+                .find(header.kid.as_deref())
+                .ok_or(JwtDecodeErrors::UnknownKey),
+        ))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IssuancePolicy {
+    lifetime: Duration,
+    issuer: Option<String>,
+    audience: Vec<String>,
+    not_before: bool,
+    jti: bool,
+}
+
+impl Default for IssuancePolicy {
+    fn default() -> Self {
+        Self {
+            lifetime: Duration::from_secs(15 * 60),
+            issuer: None,
+            audience: Vec::new(),
+            not_before: false,
+            jti: true,
+        }
+    }
+}
+
+impl IssuancePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    pub fn audience<I, S>(mut self, audience: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.audience = audience.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn not_before(mut self, not_before: bool) -> Self {
+        self.not_before = not_before;
+        self
+    }
+
+    pub fn jti(mut self, jti: bool) -> Self {
+        self.jti = jti;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum IssueError {
+    Claims(serde_json::Error),
+    ClaimsNotAnObject,
+    Random,
+    Signing(jsonwebtoken::errors::Error),
+}
+
+impl fmt::Display for IssueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueError::Claims(e) => write!(f, "failed to serialize claims: {}", e),
+            IssueError::ClaimsNotAnObject => f.write_str("claims must serialize to a JSON object"),
+            IssueError::Random => f.write_str("failed to generate a random token id"),
+            IssueError::Signing(e) => write!(f, "failed to sign token: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IssueError {}
+
+#[derive(Clone, Debug)]
+pub struct IssuedToken {
+    pub token: String,
+    pub claims: RegisteredClaims,
+}
+
+pub struct JwtIssuer<T> {
+    keys: SigningKeySet,
+    policy: IssuancePolicy,
+    random: SystemRandom,
+    _token_data_type: PhantomData<T>,
+}
+
+impl<T> Clone for JwtIssuer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            keys: self.keys.clone(),
+            policy: self.policy.clone(),
+            random: SystemRandom::new(),
+            _token_data_type: PhantomData,
+        }
+    }
+}
+
+impl<T> JwtIssuer<T> {
+    pub fn new(key: SigningKey) -> Self {
+        Self::with_key_set(SigningKeySet::new(key))
+    }
+
+    pub fn with_key_set(keys: SigningKeySet) -> Self {
+        Self {
+            keys,
+            policy: IssuancePolicy::default(),
+            random: SystemRandom::new(),
+            _token_data_type: PhantomData,
+        }
+    }
+
+    pub fn policy(mut self, policy: IssuancePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn key_set(&self) -> &SigningKeySet {
+        &self.keys
+    }
+
+    // validation matching what this issuer stamps into its tokens
+    pub fn validation(&self) -> Validation {
+        let mut validation = Validation::new(self.keys.active.algorithm());
+        for key in &self.keys.keys {
+            if !validation.algorithms.contains(&key.algorithm()) {
+                validation.algorithms.push(key.algorithm());
+            }
+        }
+        let mut required = vec!["exp"];
+        if let Some(issuer) = &self.policy.issuer {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        if self.policy.audience.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.policy.audience);
+            required.push("aud");
+        }
+        validation.set_required_spec_claims(&required);
+        validation
+    }
+
+    pub fn middleware(&self) -> JwtMiddleware<T> {
+        JwtMiddleware::with_key_resolver(self.keys.clone(), self.validation())
+    }
+}
+
+impl<T: Serialize> JwtIssuer<T> {
+    pub fn issue(&self, claims: &T) -> Result<String, IssueError> {
+        self.issue_token(claims).map(|issued| issued.token)
+    }
+
+    // registered claims already present in `claims` are kept, the policy only fills in the missing ones
+    pub fn issue_token(&self, claims: &T) -> Result<IssuedToken, IssueError> {
+        let Value::Object(payload) = serde_json::to_value(claims).map_err(IssueError::Claims)?
+        else {
+            return Err(IssueError::ClaimsNotAnObject);
+        };
+        self.issue_payload(payload)
+    }
+}
+
+impl<T> JwtIssuer<T> {
+    pub(crate) fn issue_payload(
+        &self,
+        mut payload: Map<String, Value>,
+    ) -> Result<IssuedToken, IssueError> {
+        let now = get_current_timestamp();
+        let policy = &self.policy;
+        insert_missing(&mut payload, "iat", now.into());
+        insert_missing(
+            &mut payload,
+            "exp",
+            (now + policy.lifetime.as_secs()).into(),
+        );
+        if policy.not_before {
+            insert_missing(&mut payload, "nbf", now.into());
+        }
+        if let Some(issuer) = &policy.issuer {
+            insert_missing(&mut payload, "iss", issuer.as_str().into());
+        }
+        match policy.audience.as_slice() {
+            [] => {}
+            [audience] => insert_missing(&mut payload, "aud", audience.as_str().into()),
+            audience => insert_missing(&mut payload, "aud", audience.into()),
+        }
+        if policy.jti && !payload.contains_key("jti") {
+            payload.insert("jti".to_owned(), self.random_id()?.into());
+        }
+
+        let payload = Value::Object(payload);
+        let registered = serde_json::from_value(payload.clone()).map_err(IssueError::Claims)?;
+        let active = &self.keys.active;
+        let mut header = Header::new(active.algorithm());
+        header.kid = active.key_id().map(ToOwned::to_owned);
+        let token = jsonwebtoken::encode(&header, &payload, &active.encoding)
+            .map_err(IssueError::Signing)?;
+        Ok(IssuedToken {
+            token,
+            claims: registered,
+        })
+    }
+
+    fn random_id(&self) -> Result<String, IssueError> {
+        random_token(&self.random, 16).ok_or(IssueError::Random)
+    }
+
+    pub(crate) fn lifetime(&self) -> Duration {
+        self.policy.lifetime
+    }
+}
+
+pub(crate) fn random_token(random: &SystemRandom, len: usize) -> Option<String> {
+    let mut bytes = vec![0u8; len];
+    random.fill(&mut bytes).ok()?;
+    Some(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, token.as_bytes()))
+}
+
+fn insert_missing(payload: &mut Map<String, Value>, claim: &str, value: Value) {
+    payload.entry(claim).or_insert(value);
+}
@@ -0,0 +1,133 @@
+// tracing spans and metrics for token verification, every function is a no-op
+// unless the `tracing` or `metrics` feature is enabled
+
+use std::time::Duration;
+
+use crate::JwtDecodeErrors;
+#[cfg(feature = "tracing")]
+use crate::RegisteredClaims;
+
+#[cfg(feature = "tracing")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubjectRedaction {
+    Plain,
+    // SHA-256 of the subject, still correlatable between requests
+    #[default]
+    Hash,
+    Omit,
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn span() -> tracing::Span {
+    use tracing::field::Empty;
+
+    tracing::info_span!(
+        "jwt_verification",
+        issuer = Empty,
+        kid = Empty,
+        alg = Empty,
+        sub = Empty,
+        outcome = Empty,
+    )
+}
+
+// the header is recorded before verification so failed attempts carry `kid` and `alg` too
+pub(crate) fn record_header(token: &str) {
+    #[cfg(not(feature = "tracing"))]
+    let _ = token;
+    #[cfg(feature = "tracing")]
+    if let Ok(header) = jsonwebtoken::decode_header(token) {
+        let span = tracing::Span::current();
+        span.record("alg", tracing::field::debug(header.alg));
+        if let Some(kid) = &header.kid {
+            span.record("kid", kid.as_str());
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn record_claims(registered: &RegisteredClaims, redaction: SubjectRedaction) {
+    let span = tracing::Span::current();
+    if let Some(iss) = &registered.iss {
+        span.record("issuer", iss.as_str());
+    }
+    match (&registered.sub, redaction) {
+        (Some(sub), SubjectRedaction::Plain) => {
+            span.record("sub", sub.as_str());
+        }
+        (Some(sub), SubjectRedaction::Hash) => {
+            span.record("sub", crate::signing::hash_token(sub).as_str());
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn success() {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::Span::current().record("outcome", "success");
+        tracing::debug!("token verified");
+    }
+    #[cfg(feature = "metrics")]
+    metrics::counter!("jwt_verifications_total", "outcome" => "success").increment(1);
+}
+
+pub(crate) fn failure(e: &JwtDecodeErrors) {
+    #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+    let _ = e;
+    #[cfg(feature = "tracing")]
+    {
+        tracing::Span::current().record("outcome", e.kind());
+        tracing::info!(error = %e.to_error_string(), "token rejected");
+    }
+    #[cfg(feature = "metrics")]
+    metrics::counter!("jwt_verifications_total", "outcome" => e.kind()).increment(1);
+}
+
+pub(crate) fn throttled() {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::Span::current().record("outcome", "throttled");
+        tracing::info!("client throttled after repeated failures");
+    }
+    #[cfg(feature = "metrics")]
+    metrics::counter!("jwt_verifications_total", "outcome" => "throttled").increment(1);
+}
+
+pub(crate) fn key_resolution(elapsed: Duration) {
+    #[cfg(not(feature = "metrics"))]
+    let _ = elapsed;
+    #[cfg(feature = "metrics")]
+    metrics::histogram!("jwt_key_resolution_seconds").record(elapsed.as_secs_f64());
+}
+
+pub(crate) fn key_reload(success: bool) {
+    #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+    let _ = success;
+    #[cfg(feature = "tracing")]
+    if success {
+        tracing::info!("key files reloaded");
+    } else {
+        tracing::warn!("key file reload failed, keeping the previous keys");
+    }
+    #[cfg(feature = "metrics")]
+    metrics::counter!(
+        "jwt_key_reloads_total",
+        "result" => if success { "success" } else { "failure" },
+    )
+    .increment(1);
+}
+
+pub(crate) fn cache_lookup(cache: &'static str, hit: bool) {
+    #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+    let _ = (cache, hit);
+    #[cfg(feature = "tracing")]
+    tracing::trace!(cache, hit, "cache lookup");
+    #[cfg(feature = "metrics")]
+    metrics::counter!(
+        "jwt_cache_lookups_total",
+        "cache" => cache,
+        "result" => if hit { "hit" } else { "miss" },
+    )
+    .increment(1);
+}
@@ -0,0 +1,165 @@
+use actix_web::{http::header, test::TestRequest};
+use jsonwebtoken::{get_current_timestamp, Algorithm, DecodingKey, EncodingKey};
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{IssuancePolicy, JwtIssuer, JwtMiddleware, SigningKey};
+
+pub const TEST_ISSUER: &str = "https://issuer.test";
+pub const TEST_AUDIENCE: &str = "test-audience";
+const TEST_KID: &str = "test-key";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TestKeyKind {
+    Hs256,
+    #[default]
+    Ed25519,
+}
+
+// ephemeral keys are generated on creation, so tokens of one authority never verify with another;
+// everything panics on failure since it's meant for tests only
+pub struct TestAuthority<T> {
+    kind: TestKeyKind,
+    issuer: JwtIssuer<T>,
+    // same kid and algorithm but a different key
+    forger: JwtIssuer<T>,
+}
+
+impl<T> Default for TestAuthority<T> {
+    fn default() -> Self {
+        Self::new(TestKeyKind::default())
+    }
+}
+
+impl<T> TestAuthority<T> {
+    pub fn new(kind: TestKeyKind) -> Self {
+        let policy = IssuancePolicy::new()
+            .issuer(TEST_ISSUER)
+            .audience([TEST_AUDIENCE]);
+        Self {
+            kind,
+            issuer: JwtIssuer::new(generate_key(kind)).policy(policy.clone()),
+            forger: JwtIssuer::new(generate_key(kind)).policy(policy),
+        }
+    }
+
+    pub fn hs256() -> Self {
+        Self::new(TestKeyKind::Hs256)
+    }
+
+    pub fn ed25519() -> Self {
+        Self::new(TestKeyKind::Ed25519)
+    }
+
+    // replaces the default issuer, audience and lifetime stamped into tokens and checked by the middleware
+    pub fn policy(mut self, policy: IssuancePolicy) -> Self {
+        self.issuer = self.issuer.policy(policy.clone());
+        self.forger = self.forger.policy(policy);
+        self
+    }
+
+    pub fn key_kind(&self) -> TestKeyKind {
+        self.kind
+    }
+
+    pub fn issuer(&self) -> &JwtIssuer<T> {
+        &self.issuer
+    }
+
+    pub fn middleware(&self) -> JwtMiddleware<T> {
+        self.issuer.middleware()
+    }
+}
+
+impl<T: Serialize> TestAuthority<T> {
+    pub fn token(&self, claims: &T) -> String {
+        self.issue(&self.issuer, claims, |_| {})
+    }
+
+    pub fn expired_token(&self, claims: &T) -> String {
+        // well past the default validation leeway
+        let now = get_current_timestamp();
+        self.issue(&self.issuer, claims, |payload| {
+            payload.insert("iat".into(), (now - 2 * 3600).into());
+            payload.insert("exp".into(), (now - 3600).into());
+        })
+    }
+
+    pub fn wrong_audience_token(&self, claims: &T) -> String {
+        self.issue(&self.issuer, claims, |payload| {
+            payload.insert("aud".into(), "wrong-audience".into());
+        })
+    }
+
+    pub fn wrong_issuer_token(&self, claims: &T) -> String {
+        self.issue(&self.issuer, claims, |payload| {
+            payload.insert("iss".into(), "https://wrong-issuer.test".into());
+        })
+    }
+
+    pub fn wrong_signature_token(&self, claims: &T) -> String {
+        self.issue(&self.forger, claims, |_| {})
+    }
+
+    // valid signature over arbitrary registered claims, e.g. to test custom `jti` or `sub` handling
+    pub fn token_with(&self, claims: &T, overrides: Map<String, Value>) -> String {
+        self.issue(&self.issuer, claims, |payload| payload.extend(overrides))
+    }
+
+    fn issue(
+        &self,
+        issuer: &JwtIssuer<T>,
+        claims: &T,
+        customize: impl FnOnce(&mut Map<String, Value>),
+    ) -> String {
+        let Value::Object(mut payload) =
+            serde_json::to_value(claims).expect("test claims must serialize")
+        else {
+            panic!("test claims must serialize to a JSON object");
+        };
+        customize(&mut payload);
+        issuer
+            .issue_payload(payload)
+            .expect("failed to issue test token")
+            .token
+    }
+}
+
+fn generate_key(kind: TestKeyKind) -> SigningKey {
+    let random = SystemRandom::new();
+    let key = match kind {
+        TestKeyKind::Hs256 => {
+            let mut secret = [0u8; 32];
+            random
+                .fill(&mut secret)
+                .expect("failed to generate test secret");
+            SigningKey::hmac(&secret, Algorithm::HS256)
+        }
+        TestKeyKind::Ed25519 => {
+            let pkcs8 =
+                Ed25519KeyPair::generate_pkcs8(&random).expect("failed to generate test key");
+            let key_pair =
+                Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("failed to parse test key");
+            SigningKey::new(
+                EncodingKey::from_ed_der(pkcs8.as_ref()),
+                DecodingKey::from_ed_der(key_pair.public_key().as_ref()),
+                Algorithm::EdDSA,
+            )
+        }
+    };
+    key.kid(TEST_KID)
+}
+
+pub trait TestRequestExt {
+    fn bearer_token(self, token: impl AsRef<str>) -> Self;
+}
+
+impl TestRequestExt for TestRequest {
+    fn bearer_token(self, token: impl AsRef<str>) -> Self {
+        self.insert_header((header::AUTHORIZATION, format!("Bearer {}", token.as_ref())))
+    }
+}
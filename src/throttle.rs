@@ -0,0 +1,252 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::dev::ServiceRequest;
+use jsonwebtoken::errors::ErrorKind;
+
+use crate::{signing::hash_token, JwtDecodeErrors, JwtVerifier};
+
+struct RejectedToken {
+    error: JwtDecodeErrors,
+    expires_at: Instant,
+    // a token rejected by a previous configuration, e.g. before a key was added, is verified again
+    verifier: Arc<JwtVerifier>,
+    generation: u64,
+}
+
+struct RejectedTokens {
+    entries: HashMap<String, RejectedToken>,
+    // ordered by expiry, the oldest entry is replaced when the cache is full
+    expiries: BTreeSet<(Instant, String)>,
+}
+
+// recently rejected tokens keyed by the SHA-256 of the raw token, repeated attempts are answered
+// with the cached error without decoding or verifying the token again
+#[derive(Clone)]
+pub struct RejectedTokenCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Arc<Mutex<RejectedTokens>>,
+}
+
+impl RejectedTokenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Arc::new(Mutex::new(RejectedTokens {
+                entries: HashMap::new(),
+                expiries: BTreeSet::new(),
+            })),
+        }
+    }
+
+    pub(crate) fn get(
+        &self,
+        token: &str,
+        verifier: &Arc<JwtVerifier>,
+        generation: u64,
+    ) -> Option<JwtDecodeErrors> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .entries
+            .get(&hash_token(token))
+            .filter(|rejected| {
+                rejected.expires_at > Instant::now()
+                    && Arc::ptr_eq(&rejected.verifier, verifier)
+                    && rejected.generation == generation
+            })
+            .map(|rejected| rejected.error.clone())
+    }
+
+    pub(crate) fn insert(
+        &self,
+        token: &str,
+        error: &JwtDecodeErrors,
+        verifier: &Arc<JwtVerifier>,
+        generation: u64,
+    ) {
+        if !is_permanent(error) || self.capacity == 0 {
+            return;
+        }
+        let key = hash_token(token);
+        let expires_at = Instant::now() + self.ttl;
+        let mut rejected = self.entries.lock().unwrap();
+        if let Some(previous) = rejected.entries.remove(&key) {
+            rejected
+                .expiries
+                .remove(&(previous.expires_at, key.clone()));
+        }
+        while rejected.entries.len() >= self.capacity {
+            let Some((_, oldest)) = rejected.expiries.pop_first() else {
+                break;
+            };
+            rejected.entries.remove(&oldest);
+        }
+        rejected.expiries.insert((expires_at, key.clone()));
+        rejected.entries.insert(
+            key,
+            RejectedToken {
+                error: error.clone(),
+                expires_at,
+                verifier: verifier.clone(),
+                generation,
+            },
+        );
+    }
+}
+
+// errors that can't go away by retrying the same token, unlike unavailable backends,
+// unknown keys that might show up after a JWKS refresh or tokens that aren't valid yet
+fn is_permanent(error: &JwtDecodeErrors) -> bool {
+    match error {
+        JwtDecodeErrors::InvalidJWTToken(e) => !matches!(e.kind(), ErrorKind::ImmatureSignature),
+        JwtDecodeErrors::InvalidJWTHeader
+        | JwtDecodeErrors::UnknownIssuer
+        | JwtDecodeErrors::Revoked
+        | JwtDecodeErrors::InactiveToken
+        | JwtDecodeErrors::InvalidJWE
+        | JwtDecodeErrors::UnsupportedJWEAlgorithm
+        | JwtDecodeErrors::JWEDecryptionFailed => true,
+        _ => false,
+    }
+}
+
+struct FailureWindow {
+    failures: u32,
+    started_at: Instant,
+}
+
+struct FailureWindows {
+    clients: HashMap<IpAddr, FailureWindow>,
+    // ordered by window start, the oldest window is dropped when the table is full
+    started: BTreeSet<(Instant, IpAddr)>,
+}
+
+// clients exceeding `max_failures` within `window` get 429 responses until the window is over
+#[derive(Clone)]
+pub struct FailureThrottle {
+    max_failures: u32,
+    window: Duration,
+    max_clients: usize,
+    real_ip: bool,
+    ipv6_prefix: u8,
+    clients: Arc<Mutex<FailureWindows>>,
+}
+
+impl FailureThrottle {
+    pub fn new(max_failures: u32, window: Duration) -> Self {
+        Self {
+            max_failures,
+            window,
+            max_clients: 100_000,
+            real_ip: false,
+            ipv6_prefix: 64,
+            clients: Arc::new(Mutex::new(FailureWindows {
+                clients: HashMap::new(),
+                started: BTreeSet::new(),
+            })),
+        }
+    }
+
+    // upper bound on tracked addresses, the client with the oldest window is dropped once it's reached
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    // use the client address from `Forwarded` / `X-Forwarded-For`, only safe behind a proxy
+    // that overwrites these headers, otherwise clients can pick their own address
+    pub fn real_ip(mut self, real_ip: bool) -> Self {
+        self.real_ip = real_ip;
+        self
+    }
+
+    // IPv6 clients are tracked by network, a single host usually gets a whole /64
+    pub fn ipv6_prefix(mut self, ipv6_prefix: u8) -> Self {
+        self.ipv6_prefix = ipv6_prefix.min(128);
+        self
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        let ip = match self.peer_ip(req)?.to_canonical() {
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.ipv6_prefix))
+                    .unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+            ip => ip,
+        };
+        Some(ip)
+    }
+
+    fn peer_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        if self.real_ip {
+            let info = req.connection_info();
+            let addr = info.realip_remote_addr()?;
+            // the peer address includes the port, forwarded ones usually don't
+            addr.parse::<IpAddr>()
+                .ok()
+                .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        } else {
+            req.peer_addr().map(|addr| addr.ip())
+        }
+    }
+
+    // remaining time of the window when the client is over the limit
+    pub(crate) fn retry_after(&self, req: &ServiceRequest) -> Option<Duration> {
+        let ip = self.client_ip(req)?;
+        let clients = self.clients.lock().unwrap();
+        let client = clients.clients.get(&ip)?;
+        let elapsed = client.started_at.elapsed();
+        if client.failures < self.max_failures || elapsed >= self.window {
+            return None;
+        }
+        Some(self.window - elapsed)
+    }
+
+    pub(crate) fn record_failure(&self, req: &ServiceRequest, error: &JwtDecodeErrors) {
+        // server side outages aren't the client's fault, and a nonce challenge is part of a
+        // normal DPoP exchange
+        if matches!(
+            error,
+            JwtDecodeErrors::KeysUnavailable
+                | JwtDecodeErrors::RevocationUnavailable
+                | JwtDecodeErrors::IntrospectionUnavailable
+                | JwtDecodeErrors::UseDPoPNonce(_)
+        ) {
+            return;
+        }
+        let Some(ip) = self.client_ip(req) else {
+            return;
+        };
+        let now = Instant::now();
+        let mut windows = self.clients.lock().unwrap();
+        let windows = &mut *windows;
+        if !windows.clients.contains_key(&ip) {
+            while windows.clients.len() >= self.max_clients.max(1) {
+                let Some((_, oldest)) = windows.started.pop_first() else {
+                    break;
+                };
+                windows.clients.remove(&oldest);
+            }
+            windows.started.insert((now, ip));
+        }
+        let client = windows.clients.entry(ip).or_insert(FailureWindow {
+            failures: 0,
+            started_at: now,
+        });
+        if now - client.started_at >= self.window {
+            windows.started.remove(&(client.started_at, ip));
+            windows.started.insert((now, ip));
+            client.failures = 0;
+            client.started_at = now;
+        }
+        client.failures = client.failures.saturating_add(1);
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use jsonwebtoken::get_current_timestamp;
+
+use crate::{jwt::DecodedJwt, signing::hash_token, telemetry, JwtVerifier};
+
+struct CacheEntry<T> {
+    decoded: DecodedJwt<T>,
+    expires_at: u64,
+    // kept alive so a new configuration can't end up at the same address
+    verifier: Arc<JwtVerifier>,
+    // reloaded key files or a refreshed JWKS don't replace the verifier
+    generation: u64,
+}
+
+struct CacheEntries<T> {
+    entries: HashMap<String, CacheEntry<T>>,
+    // ordered by expiry, so expired entries and the one expiring soonest are found without a scan
+    expiries: BTreeSet<(u64, String)>,
+}
+
+impl<T> CacheEntries<T> {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.expiries.remove(&(entry.expires_at, key.to_owned()));
+        }
+    }
+
+    fn purge(&mut self, now: u64) {
+        while let Some((expires_at, key)) = self.expiries.first() {
+            if *expires_at > now {
+                break;
+            }
+            self.entries.remove(key);
+            self.expiries.pop_first();
+        }
+    }
+
+    fn evict_soonest(&mut self) {
+        if let Some((_, key)) = self.expiries.pop_first() {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+struct CacheInner<T> {
+    capacity: usize,
+    max_ttl: Duration,
+    entries: Mutex<CacheEntries<T>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    clone_claims: fn(&T) -> T,
+}
+
+impl<T> CacheInner<T> {
+    fn clone_decoded(&self, decoded: &DecodedJwt<T>) -> DecodedJwt<T> {
+        DecodedJwt {
+            claims: (self.clone_claims)(&decoded.claims),
+            registered: decoded.registered.clone(),
+            header: decoded.header.clone(),
+            key_id: decoded.key_id.clone(),
+            issuer: decoded.issuer.clone(),
+            verified_at: decoded.verified_at,
+        }
+    }
+}
+
+// verified tokens keyed by the SHA-256 of the raw token, kept until the earlier of `exp` and `max_ttl`;
+// clones share the entries, so one cache can be used by the middleware of every worker
+pub struct VerifiedTokenCache<T> {
+    inner: Arc<CacheInner<T>>,
+}
+
+impl<T> Clone for VerifiedTokenCache<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Clone> VerifiedTokenCache<T> {
+    pub fn new(capacity: usize, max_ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(CacheInner {
+                capacity,
+                max_ttl,
+                entries: Mutex::new(CacheEntries {
+                    entries: HashMap::new(),
+                    expiries: BTreeSet::new(),
+                }),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                clone_claims: T::clone,
+            }),
+        }
+    }
+}
+
+impl<T> VerifiedTokenCache<T> {
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.inner.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.entries.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.inner.entries.lock().unwrap();
+        entries.entries.clear();
+        entries.expiries.clear();
+    }
+
+    pub(crate) fn get(
+        &self,
+        token: &str,
+        verifier: &Arc<JwtVerifier>,
+        generation: u64,
+    ) -> Option<DecodedJwt<T>> {
+        let inner = &self.inner;
+        let now = get_current_timestamp();
+        let decoded = inner
+            .entries
+            .lock()
+            .unwrap()
+            .entries
+            .get(&hash_token(token))
+            .filter(|entry| {
+                entry.expires_at > now
+                    && Arc::ptr_eq(&entry.verifier, verifier)
+                    && entry.generation == generation
+            })
+            .map(|entry| inner.clone_decoded(&entry.decoded));
+        let counter = match decoded {
+            Some(_) => &inner.hits,
+            None => &inner.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        telemetry::cache_lookup("verified_token", decoded.is_some());
+        decoded
+    }
+
+    pub(crate) fn insert(
+        &self,
+        token: &str,
+        decoded: &DecodedJwt<T>,
+        verifier: &Arc<JwtVerifier>,
+        generation: u64,
+    ) {
+        let inner = &self.inner;
+        let now = get_current_timestamp();
+        let max_expires_at = now + inner.max_ttl.as_secs();
+        let expires_at = decoded
+            .registered
+            .exp
+            .map_or(max_expires_at, |exp| exp.min(max_expires_at));
+        if expires_at <= now || inner.capacity == 0 {
+            return;
+        }
+        let key = hash_token(token);
+        let mut entries = inner.entries.lock().unwrap();
+        entries.remove(&key);
+        if entries.entries.len() >= inner.capacity {
+            entries.purge(now);
+        }
+        if entries.entries.len() >= inner.capacity {
+            entries.evict_soonest();
+        }
+        entries.expiries.insert((expires_at, key.clone()));
+        entries.entries.insert(
+            key,
+            CacheEntry {
+                decoded: inner.clone_decoded(decoded),
+                expires_at,
+                verifier: verifier.clone(),
+                generation,
+            },
+        );
+    }
+}
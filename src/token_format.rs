@@ -0,0 +1,21 @@
+use futures::future::LocalBoxFuture;
+use serde_json::Value;
+
+use crate::{JwtDecodeErrors, RegisteredClaims};
+
+pub struct DecodedToken {
+    pub claims: Value,
+    // used for revocation checks, formats without numeric timestamps have to convert them
+    pub registered: RegisteredClaims,
+    pub key_id: Option<String>,
+}
+
+// verifies tokens that are not JWS, e.g. PASETO, while JWTs keep going through `jsonwebtoken`
+pub trait TokenFormat: Send + Sync {
+    fn accepts(&self, token: &str) -> bool;
+
+    fn decode<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> LocalBoxFuture<'a, Result<DecodedToken, JwtDecodeErrors>>;
+}
@@ -0,0 +1,251 @@
+use actix_web::{
+    dev::{Payload, ServiceRequest},
+    http::{
+        header::{self, HeaderName},
+        Method,
+    },
+    web::BytesMut,
+    HttpMessage,
+};
+use futures::{future::LocalBoxFuture, StreamExt};
+
+use crate::JwtDecodeErrors;
+
+pub trait TokenSource: Send + Sync {
+    fn extract<'a>(
+        &'a self,
+        req: &'a mut ServiceRequest,
+    ) -> LocalBoxFuture<'a, Option<Result<String, JwtDecodeErrors>>>;
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TokenScheme {
+    #[default]
+    Bearer,
+    None,
+    Custom(String),
+}
+
+impl TokenScheme {
+    // authentication schemes are case-insensitive (RFC 7235 section 2.1)
+    pub(crate) fn strip<'a>(&self, value: &'a str) -> Option<&'a str> {
+        let scheme = match self {
+            TokenScheme::Bearer => "Bearer",
+            TokenScheme::None => return Some(value),
+            TokenScheme::Custom(scheme) => scheme,
+        };
+        let prefix = value.get(..scheme.len())?;
+        if !prefix.eq_ignore_ascii_case(scheme) || !value[scheme.len()..].starts_with(' ') {
+            return None;
+        }
+        Some(value[scheme.len() + 1..].trim_start())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HeaderSource {
+    name: HeaderName,
+    schemes: Vec<TokenScheme>,
+}
+
+impl HeaderSource {
+    pub fn new(name: HeaderName) -> Self {
+        Self {
+            name,
+            schemes: vec![TokenScheme::None],
+        }
+    }
+
+    pub fn authorization() -> Self {
+        Self::new(header::AUTHORIZATION).scheme(TokenScheme::Bearer)
+    }
+
+    pub fn scheme(mut self, scheme: TokenScheme) -> Self {
+        self.schemes = vec![scheme];
+        self
+    }
+
+    // accepts another scheme in addition to the ones already configured
+    pub fn or_scheme(mut self, scheme: TokenScheme) -> Self {
+        self.schemes.push(scheme);
+        self
+    }
+}
+
+impl TokenSource for HeaderSource {
+    fn extract<'a>(
+        &'a self,
+        req: &'a mut ServiceRequest,
+    ) -> LocalBoxFuture<'a, Option<Result<String, JwtDecodeErrors>>> {
+        let token = req.headers().get(&self.name).map(|value| {
+            let Ok(value) = value.to_str() else {
+                return Err(JwtDecodeErrors::InvalidAuthHeader);
+            };
+            match self.schemes.iter().find_map(|scheme| scheme.strip(value)) {
+                Some(token) if !token.is_empty() => Ok(token.to_owned()),
+                _ => Err(JwtDecodeErrors::InvalidJWTHeader),
+            }
+        });
+        Box::pin(async move { token })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CookieSource {
+    name: String,
+}
+
+impl CookieSource {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+impl TokenSource for CookieSource {
+    fn extract<'a>(
+        &'a self,
+        req: &'a mut ServiceRequest,
+    ) -> LocalBoxFuture<'a, Option<Result<String, JwtDecodeErrors>>> {
+        let token = req
+            .cookie(&self.name)
+            .map(|cookie| cookie.value().to_owned())
+            .filter(|token| !token.is_empty())
+            .map(Ok);
+        Box::pin(async move { token })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct QuerySource {
+    name: String,
+}
+
+impl QuerySource {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+
+    pub fn access_token() -> Self {
+        Self::new("access_token")
+    }
+}
+
+impl TokenSource for QuerySource {
+    fn extract<'a>(
+        &'a self,
+        req: &'a mut ServiceRequest,
+    ) -> LocalBoxFuture<'a, Option<Result<String, JwtDecodeErrors>>> {
+        let token = find_param(req.query_string().as_bytes(), &self.name);
+        Box::pin(async move { token })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FormSource {
+    name: String,
+    limit: usize,
+}
+
+impl FormSource {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            limit: 16 * 1024,
+        }
+    }
+
+    pub fn access_token() -> Self {
+        Self::new("access_token")
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl TokenSource for FormSource {
+    fn extract<'a>(
+        &'a self,
+        req: &'a mut ServiceRequest,
+    ) -> LocalBoxFuture<'a, Option<Result<String, JwtDecodeErrors>>> {
+        Box::pin(async move {
+            if req.method() == Method::GET
+                || req.content_type() != "application/x-www-form-urlencoded"
+            {
+                return None;
+            }
+            // the body is buffered and put back for the handler, so only read bodies of known size
+            let length = req
+                .headers()
+                .get(header::CONTENT_LENGTH)?
+                .to_str()
+                .ok()?
+                .parse::<usize>()
+                .ok()?;
+            if length > self.limit {
+                return None;
+            }
+
+            let mut payload = req.take_payload();
+            let mut body = BytesMut::with_capacity(length);
+            let mut failed = false;
+            while let Some(chunk) = payload.next().await {
+                match chunk {
+                    Ok(chunk) => body.extend_from_slice(&chunk),
+                    Err(_) => {
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+            let body = body.freeze();
+            let token = (!failed).then(|| find_param(&body, &self.name)).flatten();
+            req.set_payload(Payload::from(body));
+            token
+        })
+    }
+}
+
+fn find_param(input: &[u8], name: &str) -> Option<Result<String, JwtDecodeErrors>> {
+    form_urlencoded::parse(input)
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .filter(|token| !token.is_empty())
+        .map(Ok)
+}
+
+#[derive(Default)]
+pub struct TokenSourceChain {
+    sources: Vec<Box<dyn TokenSource>>,
+}
+
+impl TokenSourceChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn source<S>(mut self, source: S) -> Self
+    where
+        S: TokenSource + 'static,
+    {
+        self.sources.push(Box::new(source));
+        self
+    }
+}
+
+impl TokenSource for TokenSourceChain {
+    fn extract<'a>(
+        &'a self,
+        req: &'a mut ServiceRequest,
+    ) -> LocalBoxFuture<'a, Option<Result<String, JwtDecodeErrors>>> {
+        Box::pin(async move {
+            for source in &self.sources {
+                if let Some(token) = source.extract(req).await {
+                    return Some(token);
+                }
+            }
+            None
+        })
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    fmt,
+    future::{ready, Ready},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use actix_web::{dev::Payload, Error, FromRequest, HttpMessage, HttpRequest};
+use jsonwebtoken::Header;
+
+use crate::{ClaimsConfig, VerifiedIssuer};
+
+struct VerifiedTokenInner<T> {
+    // `None` for tokens that were not JWTs, e.g. introspected reference tokens
+    header: Option<Header>,
+    claims: Arc<T>,
+    raw_token: String,
+    key_id: Option<String>,
+    issuer: Option<VerifiedIssuer>,
+    verified_at: SystemTime,
+}
+
+pub struct VerifiedToken<T>(Arc<VerifiedTokenInner<T>>);
+
+impl<T> VerifiedToken<T> {
+    pub(crate) fn new(
+        header: Option<Header>,
+        claims: Arc<T>,
+        raw_token: String,
+        key_id: Option<String>,
+        issuer: Option<VerifiedIssuer>,
+        verified_at: SystemTime,
+    ) -> Self {
+        Self(Arc::new(VerifiedTokenInner {
+            header,
+            claims,
+            raw_token,
+            key_id,
+            issuer,
+            verified_at,
+        }))
+    }
+
+    pub fn header(&self) -> Option<&Header> {
+        self.0.header.as_ref()
+    }
+
+    pub fn claims(&self) -> &T {
+        &self.0.claims
+    }
+
+    pub fn raw_token(&self) -> &str {
+        &self.0.raw_token
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.0.key_id.as_deref()
+    }
+
+    pub fn issuer(&self) -> Option<&VerifiedIssuer> {
+        self.0.issuer.as_ref()
+    }
+
+    pub fn verified_at(&self) -> SystemTime {
+        self.0.verified_at
+    }
+}
+
+impl<T> Clone for VerifiedToken<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for VerifiedToken<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the raw token is a bearer credential, keep it out of logs
+        f.debug_struct("VerifiedToken")
+            .field("header", &self.0.header)
+            .field("claims", &self.0.claims)
+            .field("key_id", &self.0.key_id)
+            .field("issuer", &self.0.issuer)
+            .field("verified_at", &self.0.verified_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: 'static> FromRequest for VerifiedToken<T> {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<VerifiedToken<T>>()
+                .cloned()
+                .ok_or_else(|| ClaimsConfig::from_req(req).error(req)),
+        )
+    }
+}
@@ -0,0 +1,252 @@
+mod common;
+
+use std::{
+    fs,
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use actix_jwt_middleware::{
+    AuditEvent, AuditOutcome, AuditPolicy, AuditSink, FailureThrottle, FieldPolicy,
+    JsonLinesAuditSink, JwtMiddleware, NonBlockingAuditSink, SuccessAction,
+};
+use actix_web::{error::ErrorForbidden, test, web, App, HttpResponse};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{bearer, claims, middleware, token, TestClaims};
+use jsonwebtoken::get_current_timestamp;
+use ring::{digest, hmac};
+use serde_json::{json, Value};
+
+#[derive(Clone, Default)]
+struct Events(Arc<Mutex<Vec<AuditEvent>>>);
+
+impl AuditSink for Events {
+    fn record(&self, event: AuditEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+fn issued_token() -> String {
+    token(&json!({
+        "sub": "alice",
+        "jti": "token-1",
+        "iss": "https://issuer.example",
+        "exp": get_current_timestamp() + 600,
+    }))
+}
+
+async fn call_all(jwt: JwtMiddleware<TestClaims>, tokens: &[&str]) {
+    let app = test::init_service(App::new().wrap(jwt).route(
+        "/orders",
+        web::get().to(|| async { HttpResponse::Ok().finish() }),
+    ))
+    .await;
+    for token in tokens {
+        let req = test::TestRequest::get()
+            .uri("/orders")
+            .peer_addr("192.0.2.1:1234".parse().unwrap())
+            .insert_header(bearer(token))
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+}
+
+#[actix_web::test]
+async fn records_successful_and_failed_attempts() {
+    let events = Events::default();
+    let valid = issued_token();
+    let tampered = format!("{}x", valid);
+    call_all(
+        middleware().audit(events.clone(), AuditPolicy::new()),
+        &[&valid, &tampered],
+    )
+    .await;
+    let events = events.0.lock().unwrap();
+    assert_eq!(events.len(), 2);
+
+    let success = &events[0];
+    assert_eq!(success.outcome, AuditOutcome::Success);
+    assert_eq!(success.client_ip.as_deref(), Some("192.0.2.1"));
+    assert_eq!(success.method, "GET");
+    assert_eq!(success.path, "/orders");
+    assert_eq!(success.subject.as_deref(), Some("alice"));
+    assert_eq!(success.jti.as_deref(), Some("token-1"));
+    assert_eq!(success.issuer.as_deref(), Some("https://issuer.example"));
+    assert_eq!(success.error, None);
+
+    // the claims of rejected tokens aren't trusted
+    let failure = &events[1];
+    assert_eq!(failure.outcome, AuditOutcome::Failure);
+    assert_eq!(failure.error, Some("invalid_signature"));
+    assert_eq!(failure.subject, None);
+    assert_eq!(failure.jti, None);
+}
+
+#[actix_web::test]
+async fn applies_the_field_policy() {
+    let events = Events::default();
+    let policy = AuditPolicy::new()
+        .client_ip(FieldPolicy::Hash)
+        .subject(FieldPolicy::Hash)
+        .jti(FieldPolicy::Omit)
+        .hash_key(b"audit-key");
+    call_all(
+        middleware().audit(events.clone(), policy),
+        &[&issued_token()],
+    )
+    .await;
+    let recorded = events.0.lock().unwrap().clone();
+    let key = hmac::Key::new(hmac::HMAC_SHA256, b"audit-key");
+    let hmac = |value: &str| URL_SAFE_NO_PAD.encode(hmac::sign(&key, value.as_bytes()));
+    assert_eq!(recorded[0].client_ip, Some(hmac("192.0.2.1")));
+    assert_eq!(recorded[0].subject, Some(hmac("alice")));
+    assert_eq!(recorded[0].jti, None);
+    assert_eq!(
+        recorded[0].issuer.as_deref(),
+        Some("https://issuer.example")
+    );
+
+    let events = Events::default();
+    let policy = AuditPolicy::new().subject(FieldPolicy::Hash);
+    call_all(
+        middleware().audit(events.clone(), policy),
+        &[&issued_token()],
+    )
+    .await;
+    let sha256 = URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, b"alice"));
+    assert_eq!(events.0.lock().unwrap()[0].subject, Some(sha256));
+}
+
+#[actix_web::test]
+async fn writes_json_lines() {
+    let path = std::env::temp_dir().join(format!("audit-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let sink = JsonLinesAuditSink::open(&path).unwrap();
+    let valid = issued_token();
+    call_all(
+        middleware().audit(sink, AuditPolicy::new()),
+        &[&valid, "not-a-token"],
+    )
+    .await;
+    let lines: Vec<Value> = fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["outcome"], "success");
+    assert_eq!(lines[0]["subject"], "alice");
+    assert_eq!(lines[1]["outcome"], "failure");
+    assert_eq!(lines[1]["error"], "invalid_token");
+    assert!(lines[1].get("subject").is_none());
+}
+
+#[actix_web::test]
+async fn records_success_after_the_success_handler() {
+    for subject in ["alice", "bob", "carol"] {
+        let events = Events::default();
+        let seen = events.clone();
+        let jwt = middleware::<TestClaims>()
+            .audit(events.clone(), AuditPolicy::new())
+            .async_success_handler(move |_, claims: TestClaims| {
+                let recorded = seen.0.lock().unwrap().len();
+                async move {
+                    assert_eq!(recorded, 0);
+                    match claims.sub.as_str() {
+                        "alice" => Ok(SuccessAction::Continue),
+                        "bob" => Ok(SuccessAction::Respond(HttpResponse::Forbidden().finish())),
+                        _ => Err(ErrorForbidden("suspended")),
+                    }
+                }
+            });
+        call_all(jwt, &[&token(&claims(subject))]).await;
+        let events = events.0.lock().unwrap().clone();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].subject.as_deref(), Some(subject));
+        if subject == "alice" {
+            assert_eq!(events[0].outcome, AuditOutcome::Success);
+            assert_eq!(events[0].error, None);
+        } else {
+            // rejected by the success handler after the token was verified
+            assert_eq!(events[0].outcome, AuditOutcome::Failure);
+            assert_eq!(events[0].error, Some("denied"));
+        }
+    }
+}
+
+#[actix_web::test]
+async fn records_throttled_requests() {
+    let events = Events::default();
+    let tampered = format!("{}x", issued_token());
+    let jwt = middleware()
+        .audit(events.clone(), AuditPolicy::new())
+        .failure_throttle(FailureThrottle::new(1, Duration::from_secs(60)));
+    call_all(jwt, &[&tampered, &issued_token()]).await;
+    let events = events.0.lock().unwrap().clone();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].outcome, AuditOutcome::Failure);
+    assert_eq!(events[1].error, Some("throttled"));
+    assert_eq!(events[1].subject, None);
+    assert_eq!(events[1].client_ip.as_deref(), Some("192.0.2.1"));
+}
+
+// blocks in `record` until the test lets it continue
+struct BlockingSink {
+    started: mpsc::Sender<()>,
+    release: Mutex<mpsc::Receiver<()>>,
+    events: Events,
+}
+
+impl AuditSink for BlockingSink {
+    fn record(&self, event: AuditEvent) {
+        self.started.send(()).unwrap();
+        self.release.lock().unwrap().recv().unwrap();
+        self.events.record(event);
+    }
+}
+
+#[actix_web::test]
+async fn non_blocking_sink_drops_events_when_full() {
+    let (started, started_rx) = mpsc::channel();
+    let (release, release_rx) = mpsc::channel();
+    let events = Events::default();
+    let sink = NonBlockingAuditSink::new(
+        BlockingSink {
+            started,
+            release: Mutex::new(release_rx),
+            events: events.clone(),
+        },
+        1,
+    )
+    .unwrap();
+    let event = AuditEvent {
+        timestamp: get_current_timestamp(),
+        client_ip: None,
+        method: "GET".into(),
+        path: "/".into(),
+        subject: None,
+        jti: None,
+        issuer: None,
+        outcome: AuditOutcome::Success,
+        error: None,
+    };
+
+    // the first event is being written, the second one waits in the channel
+    sink.record(event.clone());
+    started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    sink.record(event.clone());
+    sink.record(event.clone());
+    assert_eq!(sink.dropped(), 1);
+
+    release.send(()).unwrap();
+    started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    release.send(()).unwrap();
+    for _ in 0..50 {
+        if events.0.lock().unwrap().len() == 2 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(events.0.lock().unwrap().len(), 2);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use actix_jwt_middleware::{AuthRequirement, JwtDecodeErrors};
+use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+use common::{bearer, claims, middleware, token, TestClaims};
+
+async fn ok() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+#[actix_web::test]
+async fn required_rejects_requests_without_a_token() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<TestClaims>().auth_requirement(AuthRequirement::Required))
+            .route("/", web::get().to(ok)),
+    )
+    .await;
+    let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body = test::read_body(res).await;
+    assert_eq!(body, JwtDecodeErrors::MissingToken.to_error_string());
+}
+
+#[actix_web::test]
+async fn required_accepts_valid_tokens() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<TestClaims>().required())
+            .route("/", web::get().to(ok)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token(&claims("alice"))))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn optional_passes_requests_without_a_token() {
+    let required = middleware::<TestClaims>().required();
+    let app = test::init_service(
+        App::new()
+            .wrap(required.optional())
+            .route("/", web::get().to(ok)),
+    )
+    .await;
+    let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn optional_still_rejects_invalid_tokens() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<TestClaims>())
+            .route("/", web::get().to(ok)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer("not-a-token"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use actix_jwt_middleware::{RequirePermissions, RequireRoles, RequireScopes, StandardAuthorities};
+use actix_web::{
+    http::{header, StatusCode},
+    test, web, App, HttpResponse,
+};
+use common::{bearer, middleware, token};
+use jsonwebtoken::get_current_timestamp;
+use serde_json::{json, Value};
+
+async fn ok() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+fn token_with(authorities: Value) -> String {
+    let mut claims = json!({ "sub": "alice", "exp": get_current_timestamp() + 600 });
+    claims
+        .as_object_mut()
+        .unwrap()
+        .extend(authorities.as_object().unwrap().clone());
+    token(&claims)
+}
+
+#[actix_web::test]
+async fn requires_scopes() {
+    let app = test::init_service(
+        App::new().wrap(middleware::<Value>()).service(
+            web::resource("/")
+                .wrap(RequireScopes::<Value>::all(["read", "write"]))
+                .to(ok),
+        ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token_with(json!({ "scope": "read write" }))))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token_with(json!({ "scp": ["read"] }))))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let challenge = res.headers().get(header::WWW_AUTHENTICATE).unwrap();
+    assert!(challenge
+        .to_str()
+        .unwrap()
+        .starts_with("Bearer error=\"insufficient_scope\""));
+    assert!(challenge
+        .to_str()
+        .unwrap()
+        .ends_with("scope=\"read write\""));
+}
+
+#[actix_web::test]
+async fn rejects_unauthenticated_requests() {
+    let app = test::init_service(
+        App::new().wrap(middleware::<Value>()).service(
+            web::resource("/")
+                .wrap(RequireScopes::<Value>::any(["read"]))
+                .to(ok),
+        ),
+    )
+    .await;
+    let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn reads_roles_of_typed_claims() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<StandardAuthorities>())
+            .service(
+                web::resource("/")
+                    .wrap(RequireRoles::<StandardAuthorities>::any(["admin", "owner"]))
+                    .to(ok),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token_with(
+            json!({ "realm_access": { "roles": ["owner"] } }),
+        )))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token_with(json!({ "roles": "user" }))))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[actix_web::test]
+async fn guards_routes_by_permissions() {
+    let app = test::init_service(
+        App::new().wrap(middleware::<Value>()).service(
+            web::resource("/")
+                .guard(RequirePermissions::<Value>::all(["invoices:read"]))
+                .to(ok),
+        ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token_with(
+            json!({ "permissions": ["invoices:read"] }),
+        )))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token_with(json!({ "permissions": [] }))))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use actix_jwt_middleware::{Claims, ClaimsConfig};
+use actix_web::{
+    error::ErrorForbidden, http::StatusCode, test, web, App, HttpMessage, HttpRequest, HttpResponse,
+};
+use common::{bearer, claims, middleware, token, TestClaims};
+
+async fn subject(claims: Claims<TestClaims>) -> String {
+    claims.sub.clone()
+}
+
+#[actix_web::test]
+async fn extracts_claims_of_a_valid_token() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<TestClaims>())
+            .route("/", web::get().to(subject)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token(&claims("alice"))))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, "alice");
+}
+
+#[actix_web::test]
+async fn rejects_requests_without_claims() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<TestClaims>())
+            .route("/", web::get().to(subject)),
+    )
+    .await;
+    let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn uses_the_configured_error_handler() {
+    let app = test::init_service(
+        App::new()
+            .app_data(ClaimsConfig::default().error_handler(|_| ErrorForbidden("no claims")))
+            .wrap(middleware::<TestClaims>())
+            .route("/", web::get().to(subject)),
+    )
+    .await;
+    let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn leaves_plain_claims_in_extensions() {
+    let app = test::init_service(App::new().wrap(middleware::<TestClaims>()).route(
+        "/",
+        web::get().to(|req: HttpRequest, claims: Claims<TestClaims>| async move {
+            let plain = req.extensions().get::<TestClaims>().cloned();
+            let again = Claims::<TestClaims>::from_request_extensions(&req).unwrap();
+            assert_eq!(plain.as_ref(), Some(&*claims));
+            assert!(std::sync::Arc::ptr_eq(
+                &claims.into_inner(),
+                &again.into_inner()
+            ));
+            HttpResponse::Ok().finish()
+        }),
+    ))
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token(&claims("alice"))))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn optional_claims_are_none_without_a_token() {
+    let app = test::init_service(App::new().wrap(middleware::<TestClaims>()).route(
+        "/",
+        web::get().to(|claims: Option<Claims<TestClaims>>| async move {
+            claims.map_or("anonymous".to_owned(), |claims| claims.sub.clone())
+        }),
+    ))
+    .await;
+    let body = test::call_and_read_body(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(body, "anonymous");
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token(&claims("alice"))))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, "alice");
+}
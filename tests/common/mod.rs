@@ -0,0 +1,137 @@
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use actix_jwt_middleware::JwtMiddleware;
+use jsonwebtoken::{
+    encode, get_current_timestamp, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const SECRET: &[u8] = b"integration-test-secret";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TestClaims {
+    pub sub: String,
+    pub exp: u64,
+}
+
+impl TestClaims {
+    pub fn new(sub: &str) -> Self {
+        Self {
+            sub: sub.into(),
+            exp: get_current_timestamp() + 600,
+        }
+    }
+}
+
+pub fn claims(sub: &str) -> Value {
+    json!({ "sub": sub, "exp": get_current_timestamp() + 600 })
+}
+
+pub fn token(claims: &Value) -> String {
+    token_with_header(&Header::new(Algorithm::HS256), claims)
+}
+
+pub fn token_with_header(header: &Header, claims: &Value) -> String {
+    encode(header, claims, &EncodingKey::from_secret(SECRET)).unwrap()
+}
+
+pub fn validation() -> Validation {
+    Validation::new(Algorithm::HS256)
+}
+
+pub fn middleware<T>() -> JwtMiddleware<T> {
+    JwtMiddleware::new(DecodingKey::from_secret(SECRET), validation())
+}
+
+pub fn bearer(token: &str) -> (&'static str, String) {
+    ("Authorization", format!("Bearer {}", token))
+}
+
+// answers every request with the current response and records the raw requests
+pub struct HttpStub {
+    pub url: String,
+    state: Arc<Mutex<StubState>>,
+}
+
+struct StubState {
+    status: u16,
+    body: String,
+    requests: Vec<String>,
+}
+
+impl HttpStub {
+    pub fn start(status: u16, body: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(StubState {
+            status,
+            body: body.into(),
+            requests: Vec::new(),
+        }));
+        let shared = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let request = read_request(&mut stream);
+                let (status, body) = {
+                    let mut state = shared.lock().unwrap();
+                    state.requests.push(request);
+                    (state.status, state.body.clone())
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        Self { url, state }
+    }
+
+    pub fn respond(&self, status: u16, body: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.status = status;
+        state.body = body.into();
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> String {
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        request.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let mut body = vec![0; content_length];
+    let _ = reader.read_exact(&mut body);
+    request.push_str(&String::from_utf8_lossy(&body));
+    request
+}
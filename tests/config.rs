@@ -0,0 +1,374 @@
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use actix_jwt_middleware::{JwtConfig, JwtConfigError, JwtMiddleware};
+use actix_web::{cookie::Cookie, http::header, test, web, App, HttpResponse};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{bearer, claims, TestClaims};
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde_json::{json, Value};
+
+fn directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("config-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn sign(key: &EncodingKey, alg: Algorithm, kid: Option<&str>, claims: &Value) -> String {
+    let mut header = Header::new(alg);
+    header.kid = kid.map(str::to_owned);
+    encode(&header, claims, key).unwrap()
+}
+
+fn config(config: Value) -> JwtConfig {
+    JwtConfig::from_json_str(&config.to_string()).unwrap()
+}
+
+fn config_error(config: Value) -> JwtConfigError {
+    let config = JwtConfig::from_json_str(&config.to_string()).unwrap();
+    match JwtMiddleware::<TestClaims>::from_config(&config) {
+        Ok(_) => panic!("config was accepted"),
+        Err(e) => e,
+    }
+}
+
+// status and body of the response
+async fn call(jwt: JwtMiddleware<TestClaims>, req: test::TestRequest) -> (u16, String) {
+    let app = test::init_service(App::new().wrap(jwt).route(
+        "/",
+        web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+    ))
+    .await;
+    let res = test::call_service(&app, req.to_request()).await;
+    let status = res.status().as_u16();
+    let body = test::read_body(res).await;
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+fn with_token(token: &str) -> test::TestRequest {
+    test::TestRequest::get().insert_header(bearer(token))
+}
+
+#[actix_web::test]
+async fn builds_the_middleware_from_json() {
+    let config = config(json!({
+        "keys": [{ "type": "secret", "secret": "config-secret" }],
+        "algorithms": ["HS256"],
+        "issuers": ["https://issuer.example"],
+        "audiences": ["api"],
+        "token_sources": [{ "type": "cookie", "name": "session" }, { "type": "header" }],
+        "error_style": { "type": "rfc6750", "realm": "api" },
+    }));
+    let jwt = JwtMiddleware::<TestClaims>::from_config(&config).unwrap();
+    let key = EncodingKey::from_secret(b"config-secret");
+    let exp = get_current_timestamp() + 600;
+    let valid = sign(
+        &key,
+        Algorithm::HS256,
+        None,
+        &json!({ "sub": "alice", "iss": "https://issuer.example", "aud": "api", "exp": exp }),
+    );
+    let cookie = test::TestRequest::get().cookie(Cookie::new("session", valid.clone()));
+    assert_eq!(call(jwt.clone(), cookie).await.0, 200);
+    assert_eq!(call(jwt.clone(), with_token(&valid)).await.0, 200);
+
+    let wrong_audience = sign(
+        &key,
+        Algorithm::HS256,
+        None,
+        &json!({ "sub": "alice", "iss": "https://issuer.example", "aud": "other", "exp": exp }),
+    );
+    let app = test::init_service(
+        App::new()
+            .wrap(jwt)
+            .route("/", web::get().to(|| async { HttpResponse::Ok().finish() })),
+    )
+    .await;
+    let res = test::call_service(&app, with_token(&wrong_audience).to_request()).await;
+    assert_eq!(res.status().as_u16(), 401);
+    let challenge = res.headers().get(header::WWW_AUTHENTICATE).unwrap();
+    let challenge = challenge.to_str().unwrap();
+    assert!(challenge.starts_with("Bearer realm=\"api\""));
+    assert!(challenge.contains("error=\"invalid_token\""));
+}
+
+#[actix_web::test]
+async fn required_claims_default_to_the_configured_checks() {
+    let jwt = JwtMiddleware::<TestClaims>::from_config(&config(json!({
+        "keys": [{ "type": "secret", "secret": "config-secret" }],
+        "algorithms": ["HS256"],
+        "issuers": ["https://issuer.example"],
+    })))
+    .unwrap();
+    let without_issuer = sign(
+        &EncodingKey::from_secret(b"config-secret"),
+        Algorithm::HS256,
+        None,
+        &claims("alice"),
+    );
+    let (status, body) = call(jwt, with_token(&without_issuer)).await;
+    assert_eq!(status, 400);
+    assert!(body.contains("iss"), "{}", body);
+}
+
+#[actix_web::test]
+async fn lone_pem_key_without_kid_accepts_tokens_with_a_kid() {
+    let directory = directory("pem");
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    // SubjectPublicKeyInfo of an Ed25519 key is a fixed prefix followed by the raw key
+    let mut spki = vec![
+        0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+    ];
+    spki.extend_from_slice(key_pair.public_key().as_ref());
+    let path = directory.join("public.pem");
+    fs::write(
+        &path,
+        format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            STANDARD.encode(&spki)
+        ),
+    )
+    .unwrap();
+    let jwt = JwtMiddleware::<TestClaims>::from_config(&config(json!({
+        "keys": [
+            { "type": "pem", "path": path },
+            { "type": "secret", "secret": "config-secret", "kid": "hmac" },
+        ],
+        "algorithms": ["EdDSA", "HS256"],
+    })))
+    .unwrap();
+    let key = EncodingKey::from_ed_der(pkcs8.as_ref());
+    let with_kid = sign(&key, Algorithm::EdDSA, Some("issuer-key"), &claims("alice"));
+    let without_kid = sign(&key, Algorithm::EdDSA, None, &claims("alice"));
+    let hmac = sign(
+        &EncodingKey::from_secret(b"config-secret"),
+        Algorithm::HS256,
+        Some("hmac"),
+        &claims("alice"),
+    );
+    assert_eq!(call(jwt.clone(), with_token(&with_kid)).await.0, 200);
+    assert_eq!(call(jwt.clone(), with_token(&without_kid)).await.0, 200);
+    assert_eq!(call(jwt, with_token(&hmac)).await.0, 200);
+}
+
+#[actix_web::test]
+async fn secret_files_use_the_only_hmac_algorithm() {
+    let directory = directory("secret-file");
+    let path = directory.join("secret");
+    fs::write(&path, "file-secret\n").unwrap();
+    let config_path = directory.join("jwt.json");
+    fs::write(
+        &config_path,
+        json!({
+            "keys": [{ "type": "secret_file", "path": path }],
+            "algorithms": ["HS384"],
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let config = JwtConfig::from_file(&config_path).unwrap();
+    let jwt = JwtMiddleware::<TestClaims>::from_config(&config).unwrap();
+    let token = sign(
+        &EncodingKey::from_secret(b"file-secret"),
+        Algorithm::HS384,
+        None,
+        &claims("alice"),
+    );
+    assert_eq!(call(jwt, with_token(&token)).await.0, 200);
+}
+
+#[actix_web::test]
+async fn reads_environment_variables() {
+    let prefix = format!("JWT_CONFIG_TEST_{}", std::process::id());
+    let vars = [
+        ("SECRET", "env-secret"),
+        ("ALGORITHMS", "HS256, HS512"),
+        ("AUDIENCES", "api,admin"),
+        ("LEEWAY", "5"),
+        ("TOKEN_SOURCES", "query:token,header:x-api-token"),
+    ];
+    for (name, value) in vars {
+        std::env::set_var(format!("{}_{}", prefix, name), value);
+    }
+    let config = JwtConfig::from_env(&prefix).unwrap();
+    assert_eq!(config.algorithms, [Algorithm::HS256, Algorithm::HS512]);
+    assert_eq!(config.audiences, ["api", "admin"]);
+    assert_eq!(config.leeway, 5);
+    let jwt = JwtMiddleware::<TestClaims>::from_config(&config).unwrap();
+    let token = sign(
+        &EncodingKey::from_secret(b"env-secret"),
+        Algorithm::HS512,
+        None,
+        &json!({ "sub": "alice", "aud": "admin", "exp": get_current_timestamp() + 600 }),
+    );
+    let query = test::TestRequest::get().uri(&format!("/?token={}", token));
+    assert_eq!(call(jwt.clone(), query).await.0, 200);
+    let custom = test::TestRequest::get().insert_header(("x-api-token", token.as_str()));
+    assert_eq!(call(jwt, custom).await.0, 200);
+
+    std::env::set_var(format!("{}_LEEWAY", prefix), "soon");
+    let e = JwtConfig::from_env(&prefix).unwrap_err();
+    assert!(matches!(&e, JwtConfigError::Env(name, _) if name.ends_with("_LEEWAY")));
+    for (name, _) in vars {
+        std::env::remove_var(format!("{}_{}", prefix, name));
+    }
+}
+
+#[actix_web::test]
+async fn debug_output_leaves_out_secrets() {
+    let config = config(json!({
+        "keys": [{ "type": "secret", "secret": "config-secret", "kid": "primary" }],
+        "algorithms": ["HS256"],
+    }));
+    let debug = format!("{:?}", config);
+    assert!(!debug.contains("config-secret"), "{}", debug);
+    assert!(debug.contains("primary"), "{}", debug);
+}
+
+#[actix_web::test]
+async fn reports_invalid_configs() {
+    let secret = json!({ "type": "secret", "secret": "config-secret" });
+    assert!(matches!(
+        config_error(json!({ "keys": [], "algorithms": ["HS256"] })),
+        JwtConfigError::NoKeys
+    ));
+    assert!(matches!(
+        config_error(json!({ "keys": [secret], "algorithms": [] })),
+        JwtConfigError::NoAlgorithms
+    ));
+    assert!(matches!(
+        config_error(json!({ "keys": [secret], "algorithms": ["RS256"] })),
+        JwtConfigError::InvalidKey(0, _)
+    ));
+    let e = config_error(json!({
+        "keys": [secret, { "type": "secret", "secret": "x", "algorithm": "HS512" }],
+        "algorithms": ["HS256"],
+    }));
+    assert_eq!(
+        e.to_string(),
+        "keys[1]: algorithm HS512 is not listed in `algorithms`"
+    );
+    assert!(matches!(
+        config_error(json!({
+            "keys": [secret],
+            "algorithms": ["HS256"],
+            "required_claims": ["exp", "jti"],
+        })),
+        JwtConfigError::UnknownRequiredClaim(claim) if claim == "jti"
+    ));
+    assert!(matches!(
+        config_error(json!({
+            "keys": [secret],
+            "algorithms": ["HS256"],
+            "token_sources": [{ "type": "header" }, { "type": "cookie", "name": "" }],
+        })),
+        JwtConfigError::InvalidTokenSource(1, _)
+    ));
+    // several keys without `kid` in the same family would shadow each other
+    let e = config_error(json!({
+        "keys": [secret, { "type": "secret", "secret": "other-secret" }],
+        "algorithms": ["HS256"],
+    }));
+    assert_eq!(
+        e.to_string(),
+        "keys[1]: only one key of each family can be configured without `kid`"
+    );
+    let directory = directory("unnamed");
+    fs::write(directory.join("secret"), "file-secret").unwrap();
+    assert!(matches!(
+        config_error(json!({
+            "keys": [{ "type": "secret_file", "path": directory.join("secret") }, secret],
+            "algorithms": ["HS256"],
+        })),
+        JwtConfigError::InvalidKey(1, _)
+    ));
+    assert!(matches!(
+        config_error(json!({
+            "keys": [{ "type": "pem", "path": "/does/not/exist.pem" }],
+            "algorithms": ["RS256"],
+        })),
+        JwtConfigError::KeyFile(_)
+    ));
+}
+
+#[actix_web::test]
+async fn rejects_unknown_fields_and_formats() {
+    let e =
+        JwtConfig::from_json_str(r#"{ "keys": [], "algorithms": ["HS256"], "audience": ["api"] }"#)
+            .unwrap_err();
+    assert!(matches!(&e, JwtConfigError::Parse("JSON", message) if message.contains("audience")));
+    assert!(matches!(
+        JwtConfig::from_file("jwt.ini"),
+        Err(JwtConfigError::UnsupportedFormat(_))
+    ));
+    #[cfg(not(feature = "toml"))]
+    assert!(matches!(
+        JwtConfig::from_file("jwt.toml"),
+        Err(JwtConfigError::FeatureDisabled("toml"))
+    ));
+    #[cfg(not(feature = "yaml"))]
+    assert!(matches!(
+        JwtConfig::from_file("jwt.yaml"),
+        Err(JwtConfigError::FeatureDisabled("yaml"))
+    ));
+    #[cfg(not(feature = "http"))]
+    assert!(matches!(
+        config_error(json!({
+            "keys": [{ "type": "jwks_url", "url": "https://issuer.example/jwks" }],
+            "algorithms": ["RS256"],
+        })),
+        JwtConfigError::FeatureDisabled("http")
+    ));
+}
+
+#[cfg(feature = "toml")]
+#[actix_web::test]
+async fn parses_toml() {
+    let config = JwtConfig::from_toml_str(
+        r#"
+        algorithms = ["HS256"]
+        audiences = ["api"]
+        leeway = 10
+
+        [[keys]]
+        type = "secret"
+        secret = "Y29uZmlnLXNlY3JldA=="
+        base64 = true
+
+        [error_style]
+        type = "rfc6750"
+        json_body = true
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.leeway, 10);
+    assert!(JwtMiddleware::<TestClaims>::from_config(&config).is_ok());
+}
+
+#[cfg(feature = "yaml")]
+#[actix_web::test]
+async fn parses_yaml() {
+    let config = JwtConfig::from_yaml_str(
+        r#"
+        algorithms: [HS256]
+        issuers: [https://issuer.example]
+        keys:
+          - type: secret
+            secret: config-secret
+            kid: primary
+        token_sources:
+          - type: header
+            name: authorization
+            schemes: [Bearer, Token]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.issuers, ["https://issuer.example"]);
+    assert!(JwtMiddleware::<TestClaims>::from_config(&config).is_ok());
+}
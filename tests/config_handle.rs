@@ -0,0 +1,181 @@
+mod common;
+
+use std::time::Duration;
+
+use actix_jwt_middleware::{
+    ConfigUpdateError, IssuerRegistry, JwtMiddleware, ResolvedKey, TrustedIssuer,
+    VerifiedTokenCache,
+};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    error::ErrorUnauthorized,
+    test, web, App, Error, HttpResponse,
+};
+use common::{claims, middleware, token, validation, TestClaims, SECRET};
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, DecodingKey, EncodingKey, Header};
+use serde_json::{json, Value};
+
+fn sign(secret: &[u8], kid: Option<&str>, claims: &Value) -> String {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = kid.map(str::to_owned);
+    encode(&header, claims, &EncodingKey::from_secret(secret)).unwrap()
+}
+
+fn hmac_key(secret: &[u8]) -> ResolvedKey {
+    ResolvedKey::new(DecodingKey::from_secret(secret))
+}
+
+// the services are created once, updates have to reach the ones that already exist
+fn app(
+    jwt: JwtMiddleware<TestClaims>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .wrap(jwt.error_handler(|e| ErrorUnauthorized(e.kind())))
+        .route(
+            "/",
+            web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+        )
+}
+
+// the body is "ok", or the error kind
+macro_rules! call {
+    ($app:expr, $token:expr) => {{
+        let req = test::TestRequest::get()
+            .insert_header(common::bearer($token))
+            .to_request();
+        let body = test::call_and_read_body($app, req).await;
+        String::from_utf8(body.to_vec()).unwrap()
+    }};
+}
+
+#[actix_web::test]
+async fn set_key_updates_existing_services() {
+    let cache = VerifiedTokenCache::new(16, Duration::from_secs(600));
+    let jwt = middleware().verified_token_cache(cache);
+    let handle = jwt.config_handle();
+    let app = test::init_service(app(jwt)).await;
+    let old = token(&claims("alice"));
+    let new = sign(b"new-secret", None, &claims("alice"));
+    assert_eq!(call!(&app, &old), "ok");
+
+    handle
+        .set_key(DecodingKey::from_secret(b"new-secret"))
+        .unwrap();
+    // the cached result of the old configuration isn't used
+    assert_eq!(call!(&app, &old), "invalid_signature");
+    assert_eq!(call!(&app, &new), "ok");
+}
+
+#[actix_web::test]
+async fn set_keys_matches_tokens_by_kid() {
+    let jwt = middleware();
+    let handle = jwt.config_handle();
+    let app = test::init_service(app(jwt)).await;
+    handle
+        .set_keys([
+            hmac_key(b"secret-one").kid("one"),
+            hmac_key(b"secret-two").kid("two"),
+        ])
+        .unwrap();
+    let claims = claims("alice");
+    assert_eq!(
+        call!(&app, &sign(b"secret-one", Some("one"), &claims)),
+        "ok"
+    );
+    assert_eq!(
+        call!(&app, &sign(b"secret-two", Some("two"), &claims)),
+        "ok"
+    );
+    assert_eq!(
+        call!(&app, &sign(b"secret-two", Some("three"), &claims)),
+        "unknown_key"
+    );
+    assert_eq!(call!(&app, &token(&claims)), "unknown_key");
+}
+
+#[actix_web::test]
+async fn add_key_accepts_an_emergency_key_next_to_the_current_ones() {
+    let jwt = middleware();
+    let handle = jwt.config_handle();
+    let app = test::init_service(app(jwt)).await;
+    assert!(matches!(
+        handle.add_key(hmac_key(b"emergency")),
+        Err(ConfigUpdateError::MissingKeyId)
+    ));
+    handle
+        .add_key(hmac_key(b"emergency").kid("emergency"))
+        .unwrap();
+    let claims = claims("alice");
+    assert_eq!(
+        call!(&app, &sign(b"emergency", Some("emergency"), &claims)),
+        "ok"
+    );
+    assert_eq!(call!(&app, &token(&claims)), "ok");
+    assert_eq!(
+        call!(&app, &sign(b"emergency", Some("other"), &claims)),
+        "invalid_signature"
+    );
+}
+
+#[actix_web::test]
+async fn set_validation_changes_the_rules() {
+    let jwt = middleware();
+    let handle = jwt.config_handle();
+    let app = test::init_service(app(jwt)).await;
+    let for_api = token(&json!({
+        "sub": "alice",
+        "aud": "api",
+        "exp": get_current_timestamp() + 600,
+    }));
+    let mut validation = handle.validation().unwrap();
+    validation.set_audience(&["other"]);
+    handle.set_validation(validation).unwrap();
+    assert_eq!(call!(&app, &for_api), "invalid_audience");
+
+    let mut validation = handle.validation().unwrap();
+    validation.set_audience(&["api", "other"]);
+    handle.set_validation(validation).unwrap();
+    assert_eq!(call!(&app, &for_api), "ok");
+}
+
+#[actix_web::test]
+async fn issuer_registries_are_replaced_as_a_whole() {
+    let issuer = "https://issuer.example";
+    let registry = || {
+        IssuerRegistry::new().issuer(TrustedIssuer::new(
+            issuer,
+            hmac_key(SECRET),
+            &[Algorithm::HS256],
+        ))
+    };
+    let jwt = JwtMiddleware::<TestClaims>::with_issuers(registry());
+    let handle = jwt.config_handle();
+    let app = test::init_service(app(jwt)).await;
+    assert!(handle.validation().is_none());
+    assert!(matches!(
+        handle.set_key(DecodingKey::from_secret(b"new-secret")),
+        Err(ConfigUpdateError::NotSingleKey)
+    ));
+    assert!(matches!(
+        handle.set_validation(validation()),
+        Err(ConfigUpdateError::NotSingleKey)
+    ));
+
+    let issued = json!({ "sub": "alice", "iss": issuer, "exp": get_current_timestamp() + 600 });
+    assert_eq!(call!(&app, &token(&issued)), "ok");
+    assert_eq!(call!(&app, &token(&claims("alice"))), "unknown_issuer");
+
+    handle.replace(hmac_key(SECRET), validation());
+    assert_eq!(call!(&app, &token(&claims("alice"))), "ok");
+    handle.set_issuers(registry());
+    assert_eq!(call!(&app, &token(&claims("alice"))), "unknown_issuer");
+}
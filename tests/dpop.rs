@@ -0,0 +1,302 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use actix_jwt_middleware::{
+    CookieSource, Dpop, DpopMode, FailureThrottle, JwtMiddleware, Rfc6750, RotatingDpopNonce,
+};
+use actix_web::{
+    body::MessageBody, cookie::Cookie, dev::ServiceResponse, error::ErrorUnauthorized,
+    http::StatusCode, test, web, App, HttpResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{middleware, token, TestClaims};
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+use ring::{
+    digest,
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde_json::{json, Value};
+
+const HOST: &str = "api.example";
+
+struct Client {
+    pkcs8: Vec<u8>,
+    x: String,
+}
+
+impl Client {
+    fn new() -> Self {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        Self {
+            x: URL_SAFE_NO_PAD.encode(key_pair.public_key()),
+            pkcs8: pkcs8.as_ref().to_vec(),
+        }
+    }
+
+    fn thumbprint(&self) -> String {
+        let canonical = format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, self.x);
+        URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, canonical.as_bytes()))
+    }
+
+    // access token bound to this client's key
+    fn bound_token(&self) -> String {
+        token(&json!({
+            "sub": "alice",
+            "exp": get_current_timestamp() + 600,
+            "cnf": { "jkt": self.thumbprint() },
+        }))
+    }
+
+    fn proof(&self, htm: &str, htu: &str, token: &str, nonce: Option<&str>) -> String {
+        let mut claims = json!({
+            "jti": URL_SAFE_NO_PAD.encode(random_bytes()),
+            "htm": htm,
+            "htu": htu,
+            "iat": get_current_timestamp(),
+            "ath": URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, token.as_bytes())),
+        });
+        if let Some(nonce) = nonce {
+            claims["nonce"] = Value::from(nonce);
+        }
+        self.sign(&claims)
+    }
+
+    fn sign(&self, claims: &Value) -> String {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.typ = Some("dpop+jwt".into());
+        header.jwk = Some(
+            serde_json::from_value(json!({ "kty": "OKP", "crv": "Ed25519", "x": self.x })).unwrap(),
+        );
+        encode(&header, claims, &EncodingKey::from_ed_der(&self.pkcs8)).unwrap()
+    }
+}
+
+fn random_bytes() -> [u8; 16] {
+    let mut bytes = [0; 16];
+    ring::rand::SecureRandom::fill(&SystemRandom::new(), &mut bytes).unwrap();
+    bytes
+}
+
+fn request(scheme: &str, token: &str, proof: Option<&str>) -> test::TestRequest {
+    let mut req = test::TestRequest::get()
+        .uri("/resource")
+        .insert_header(("Host", HOST))
+        .insert_header(("Authorization", format!("{} {}", scheme, token)));
+    if let Some(proof) = proof {
+        req = req.insert_header(("DPoP", proof));
+    }
+    req
+}
+
+// the body is "ok" or the error kind
+async fn call(jwt: JwtMiddleware<TestClaims>, req: test::TestRequest) -> String {
+    let res = respond(jwt.error_handler(|e| ErrorUnauthorized(e.kind())), req).await;
+    let body = test::read_body(res).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+async fn respond(
+    jwt: JwtMiddleware<TestClaims>,
+    req: test::TestRequest,
+) -> ServiceResponse<impl MessageBody> {
+    let app = test::init_service(App::new().wrap(jwt).route(
+        "/resource",
+        web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+    ))
+    .await;
+    test::call_service(&app, req.to_request()).await
+}
+
+fn header(res: &ServiceResponse<impl MessageBody>, name: &str) -> Option<String> {
+    res.headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap().to_owned())
+}
+
+#[actix_web::test]
+async fn accepts_proofs_bound_to_the_token() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let proof = client.proof("GET", "http://api.example/resource", &token, None);
+    let jwt = middleware().dpop(Dpop::new());
+    assert_eq!(call(jwt, request("DPoP", &token, Some(&proof))).await, "ok");
+}
+
+#[actix_web::test]
+async fn rejects_bound_tokens_used_as_bearer_tokens() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let proof = client.proof("GET", "http://api.example/resource", &token, None);
+    let jwt = middleware().dpop(Dpop::new());
+    assert_eq!(
+        call(jwt, request("Bearer", &token, Some(&proof))).await,
+        "invalid_dpop_proof"
+    );
+}
+
+#[actix_web::test]
+async fn rejects_proofs_of_other_keys_methods_and_urls() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let jwt = middleware().dpop(Dpop::new());
+    let other = Client::new().proof("GET", "http://api.example/resource", &token, None);
+    let post = client.proof("POST", "http://api.example/resource", &token, None);
+    let url = client.proof("GET", "http://api.example/other", &token, None);
+    for proof in [other, post, url] {
+        assert_eq!(
+            call(jwt.clone(), request("DPoP", &token, Some(&proof))).await,
+            "invalid_dpop_proof"
+        );
+    }
+}
+
+#[actix_web::test]
+async fn rejects_replayed_proofs() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let proof = client.proof("GET", "http://api.example/resource", &token, None);
+    let jwt = middleware().dpop(Dpop::new());
+    assert_eq!(
+        call(jwt.clone(), request("DPoP", &token, Some(&proof))).await,
+        "ok"
+    );
+    assert_eq!(
+        call(jwt, request("DPoP", &token, Some(&proof))).await,
+        "invalid_dpop_proof"
+    );
+}
+
+#[actix_web::test]
+async fn ignores_forwarded_headers_unless_enabled() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let forwarded = |proof: &str| {
+        request("DPoP", &token, Some(proof))
+            .insert_header(("X-Forwarded-Proto", "https"))
+            .insert_header(("X-Forwarded-Host", "attacker.example"))
+    };
+    let proof = client.proof("GET", "https://attacker.example/resource", &token, None);
+    let jwt = middleware().dpop(Dpop::new());
+    assert_eq!(call(jwt, forwarded(&proof)).await, "invalid_dpop_proof");
+
+    let proof = client.proof("GET", "https://attacker.example/resource", &token, None);
+    let jwt = middleware().dpop(Dpop::new().forwarded_origin(true));
+    assert_eq!(call(jwt, forwarded(&proof)).await, "ok");
+
+    let proof = client.proof("GET", "https://public.example/resource", &token, None);
+    let jwt = middleware().dpop(Dpop::new().origin("https://public.example"));
+    assert_eq!(call(jwt, forwarded(&proof)).await, "ok");
+}
+
+#[actix_web::test]
+async fn keeps_the_configured_token_source() {
+    let client = Client::new();
+    let unbound = token(&common::claims("alice"));
+    let jwt = middleware()
+        .token_source(CookieSource::new("session"))
+        .dpop(Dpop::new());
+    let req = test::TestRequest::get()
+        .uri("/resource")
+        .cookie(Cookie::new("session", unbound));
+    assert_eq!(call(jwt.clone(), req).await, "ok");
+
+    let token = client.bound_token();
+    let proof = client.proof("GET", "http://api.example/resource", &token, None);
+    assert_eq!(call(jwt, request("DPoP", &token, Some(&proof))).await, "ok");
+}
+
+#[actix_web::test]
+async fn required_mode_rejects_bearer_tokens() {
+    let unbound = token(&common::claims("alice"));
+    let jwt = middleware().dpop(Dpop::new().mode(DpopMode::Required));
+    assert_eq!(
+        call(jwt, request("Bearer", &unbound, None)).await,
+        "invalid_dpop_proof"
+    );
+}
+
+#[actix_web::test]
+async fn rejects_proofs_with_overflowing_iat() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let proof = client.sign(&json!({
+        "jti": URL_SAFE_NO_PAD.encode(random_bytes()),
+        "htm": "GET",
+        "htu": "http://api.example/resource",
+        "iat": u64::MAX,
+        "ath": URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, token.as_bytes())),
+    }));
+    let jwt = middleware().dpop(Dpop::new());
+    assert_eq!(
+        call(jwt, request("DPoP", &token, Some(&proof))).await,
+        "invalid_dpop_proof"
+    );
+}
+
+#[actix_web::test]
+async fn asks_for_a_server_nonce() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let jwt =
+        middleware().dpop(Dpop::new().nonce(RotatingDpopNonce::new(Duration::from_secs(300))));
+    let proof = client.proof("GET", "http://api.example/resource", &token, None);
+    let res = respond(jwt.clone(), request("DPoP", &token, Some(&proof))).await;
+    let nonce = header(&res, "DPoP-Nonce").unwrap();
+    assert_eq!(
+        header(&res, "WWW-Authenticate").unwrap(),
+        "DPoP error=\"use_dpop_nonce\", error_description=\"Resource server requires nonce in DPoP proof\""
+    );
+    let proof = client.proof("GET", "http://api.example/resource", &token, Some(&nonce));
+    assert_eq!(call(jwt, request("DPoP", &token, Some(&proof))).await, "ok");
+}
+
+#[actix_web::test]
+async fn keeps_the_challenge_of_custom_error_handlers() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let jwt = middleware()
+        .dpop(Dpop::new().nonce(RotatingDpopNonce::new(Duration::from_secs(300))))
+        .rfc6750_errors(Rfc6750::new().realm("api"));
+    let proof = client.proof("GET", "http://api.example/resource", &token, None);
+    let res = respond(jwt, request("DPoP", &token, Some(&proof))).await;
+    assert!(header(&res, "DPoP-Nonce").is_some());
+    assert!(header(&res, "WWW-Authenticate")
+        .unwrap()
+        .starts_with("DPoP realm=\"api\", error=\"use_dpop_nonce\""));
+
+    let jwt = middleware()
+        .dpop(Dpop::new().nonce(RotatingDpopNonce::new(Duration::from_secs(300))))
+        .error_handler(|e| ErrorUnauthorized(e.kind()));
+    let proof = client.proof("GET", "http://api.example/resource", &token, None);
+    let res = respond(jwt, request("DPoP", &token, Some(&proof))).await;
+    assert!(header(&res, "DPoP-Nonce").is_some());
+}
+
+#[actix_web::test]
+async fn nonce_challenges_are_not_throttled() {
+    let client = Client::new();
+    let token = client.bound_token();
+    let jwt = middleware()
+        .dpop(Dpop::new().nonce(RotatingDpopNonce::new(Duration::from_secs(300))))
+        .failure_throttle(FailureThrottle::new(1, Duration::from_secs(60)));
+    let peer = "192.0.2.1:1".parse::<SocketAddr>().unwrap();
+    let mut nonce = None;
+    for _ in 0..3 {
+        let proof = client.proof("GET", "http://api.example/resource", &token, None);
+        let req = request("DPoP", &token, Some(&proof)).peer_addr(peer);
+        let res = respond(jwt.clone(), req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        nonce = header(&res, "DPoP-Nonce");
+    }
+    let proof = client.proof(
+        "GET",
+        "http://api.example/resource",
+        &token,
+        nonce.as_deref(),
+    );
+    let req = request("DPoP", &token, Some(&proof)).peer_addr(peer);
+    assert_eq!(call(jwt, req).await, "ok");
+}
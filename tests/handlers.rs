@@ -0,0 +1,108 @@
+mod common;
+
+use actix_jwt_middleware::SuccessAction;
+use actix_web::{
+    error::{ErrorForbidden, ErrorUnauthorized},
+    http::StatusCode,
+    test, web, App, HttpMessage, HttpRequest, HttpResponse,
+};
+use common::{bearer, claims, middleware, token, TestClaims};
+
+async fn subject(req: HttpRequest) -> String {
+    req.extensions()
+        .get::<TestClaims>()
+        .map_or_else(String::new, |claims| claims.sub.clone())
+}
+
+#[actix_web::test]
+async fn async_error_handler_sees_the_request() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>().async_error_handler(|req, e| async move {
+                    ErrorUnauthorized(format!("{} {}", req.path(), e.kind()))
+                }),
+            )
+            .route("/path", web::get().to(subject)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri("/path")
+        .insert_header(bearer("garbage"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(test::read_body(res).await, "/path invalid_token");
+}
+
+#[actix_web::test]
+async fn error_handler_with_request_sees_the_request() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>()
+                    .required()
+                    .error_handler_with_request(|req, e| {
+                        ErrorForbidden(format!("{} {}", req.method(), e.kind()))
+                    }),
+            )
+            .route("/", web::get().to(subject)),
+    )
+    .await;
+    let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert_eq!(test::read_body(res).await, "GET missing_token");
+}
+
+#[actix_web::test]
+async fn async_success_handler_can_continue_respond_or_fail() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>().async_success_handler(|req, claims| async move {
+                    match claims.sub.as_str() {
+                        "blocked" => Err(ErrorForbidden("blocked")),
+                        "redirected" => Ok(SuccessAction::Respond(
+                            HttpResponse::TemporaryRedirect().finish(),
+                        )),
+                        _ => {
+                            req.extensions_mut().insert(claims);
+                            Ok(SuccessAction::Continue)
+                        }
+                    }
+                }),
+            )
+            .route("/", web::get().to(subject)),
+    )
+    .await;
+    let call = |sub: &str| {
+        test::TestRequest::get()
+            .insert_header(bearer(&token(&claims(sub))))
+            .to_request()
+    };
+    let res = test::call_service(&app, call("alice")).await;
+    assert_eq!(test::read_body(res).await, "alice");
+    let res = test::call_service(&app, call("blocked")).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = test::call_service(&app, call("redirected")).await;
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+}
+
+#[actix_web::test]
+async fn success_handler_replaces_the_default_insert() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>().success_handler(|req, mut claims| {
+                    claims.sub = claims.sub.to_uppercase();
+                    req.extensions_mut().insert(claims);
+                }),
+            )
+            .route("/", web::get().to(subject)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token(&claims("alice"))))
+        .to_request();
+    assert_eq!(test::call_and_read_body(&app, req).await, "ALICE");
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "http")]
+
+mod common;
+
+use std::time::Duration;
+
+use actix_jwt_middleware::{
+    Claims, HttpIntrospector, IntrospectionError, JwtMiddleware, TokenIntrospector,
+};
+use actix_web::{error::ErrorUnauthorized, test, web, App};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{bearer, HttpStub, TestClaims};
+use jsonwebtoken::get_current_timestamp;
+use serde_json::json;
+
+fn active(exp: u64) -> String {
+    json!({ "active": true, "sub": "alice", "exp": exp, "scope": "read" }).to_string()
+}
+
+// the body is the introspected subject, or the error kind
+async fn verify(introspector: HttpIntrospector, token: &str) -> String {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                JwtMiddleware::<TestClaims>::with_introspector(introspector)
+                    .error_handler(|e| ErrorUnauthorized(e.kind())),
+            )
+            .route(
+                "/",
+                web::get().to(|claims: Claims<TestClaims>| async move { claims.sub.clone() }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn active_tokens_provide_the_claims() {
+    let stub = HttpStub::start(200, &active(get_current_timestamp() + 600));
+    let introspector = HttpIntrospector::new(&stub.url);
+    assert_eq!(verify(introspector, "opaque-token").await, "alice");
+    let request = stub.requests().remove(0);
+    assert!(request.starts_with("POST / "));
+    assert!(request.ends_with("token=opaque-token&token_type_hint=access_token"));
+}
+
+#[actix_web::test]
+async fn inactive_tokens_are_rejected() {
+    let stub = HttpStub::start(200, r#"{"active":false}"#);
+    let introspector = HttpIntrospector::new(&stub.url);
+    assert_eq!(verify(introspector, "opaque-token").await, "inactive_token");
+}
+
+#[actix_web::test]
+async fn invalid_responses_make_introspection_unavailable() {
+    let stub = HttpStub::start(200, "<html>");
+    let introspector = HttpIntrospector::new(&stub.url);
+    assert!(matches!(
+        introspector.introspect("opaque-token").await,
+        Err(IntrospectionError::Json(_))
+    ));
+    assert_eq!(
+        verify(introspector, "opaque-token").await,
+        "introspection_unavailable"
+    );
+
+    stub.respond(200, r#"{"sub":"alice"}"#);
+    let introspector = HttpIntrospector::new(&stub.url);
+    assert!(matches!(
+        introspector.introspect("opaque-token").await,
+        Err(IntrospectionError::InvalidResponse)
+    ));
+    assert_eq!(
+        verify(introspector, "opaque-token").await,
+        "introspection_unavailable"
+    );
+
+    stub.respond(500, "");
+    let introspector = HttpIntrospector::new(&stub.url);
+    assert_eq!(
+        verify(introspector, "opaque-token").await,
+        "introspection_unavailable"
+    );
+}
+
+#[actix_web::test]
+async fn caches_active_responses() {
+    let stub = HttpStub::start(200, &active(get_current_timestamp() + 600));
+    let introspector = HttpIntrospector::new(&stub.url);
+    introspector.introspect("opaque-token").await.unwrap();
+    let cached = introspector.introspect("opaque-token").await.unwrap();
+    assert_eq!(cached.unwrap()["sub"], "alice");
+    assert_eq!(stub.requests().len(), 1);
+
+    introspector.introspect("other-token").await.unwrap();
+    assert_eq!(stub.requests().len(), 2);
+}
+
+#[actix_web::test]
+async fn caches_responses_no_longer_than_exp() {
+    let stub = HttpStub::start(200, &active(get_current_timestamp() + 2));
+    let introspector = HttpIntrospector::new(&stub.url).cache_ttl(Duration::from_secs(300));
+    introspector.introspect("opaque-token").await.unwrap();
+    introspector.introspect("opaque-token").await.unwrap();
+    assert_eq!(stub.requests().len(), 1);
+    actix_web::rt::time::sleep(Duration::from_millis(3100)).await;
+    introspector.introspect("opaque-token").await.unwrap();
+    assert_eq!(stub.requests().len(), 2);
+}
+
+#[actix_web::test]
+async fn does_not_cache_inactive_responses() {
+    let stub = HttpStub::start(200, r#"{"active":false}"#);
+    let introspector = HttpIntrospector::new(&stub.url);
+    assert!(introspector
+        .introspect("opaque-token")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(introspector
+        .introspect("opaque-token")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(stub.requests().len(), 2);
+}
+
+#[actix_web::test]
+async fn sends_client_credentials_as_basic_auth() {
+    let stub = HttpStub::start(200, &active(get_current_timestamp() + 600));
+    let introspector = HttpIntrospector::new(&stub.url)
+        .client_credentials("resource server", "s3cr:t")
+        .token_type_hint(None);
+    introspector.introspect("opaque-token").await.unwrap();
+    let request = stub.requests().remove(0).to_ascii_lowercase();
+    let expected = format!(
+        "authorization: basic {}\r\n",
+        STANDARD.encode("resource+server:s3cr%3At")
+    );
+    assert!(
+        request.contains(&expected.to_ascii_lowercase()),
+        "{}",
+        request
+    );
+    assert!(request.ends_with("token=opaque-token"));
+}
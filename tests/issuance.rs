@@ -0,0 +1,114 @@
+mod common;
+
+use std::time::Duration;
+
+use actix_jwt_middleware::{IssuancePolicy, JwtIssuer, JwtMiddleware, SigningKey, SigningKeySet};
+use actix_web::{error::ErrorUnauthorized, test, web, App, HttpMessage, HttpRequest};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{bearer, TestClaims};
+use jsonwebtoken::{decode_header, get_current_timestamp, Algorithm};
+use serde_json::{json, Value};
+
+fn key(kid: &str) -> SigningKey {
+    SigningKey::hmac(format!("secret-of-{}", kid).as_bytes(), Algorithm::HS256).kid(kid)
+}
+
+fn policy() -> IssuancePolicy {
+    IssuancePolicy::new()
+        .issuer("https://issuer.example")
+        .audience(["api"])
+        .lifetime(Duration::from_secs(300))
+}
+
+// the body is the verified subject, or the error kind
+async fn verify(middleware: JwtMiddleware<TestClaims>, token: &str) -> String {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware.error_handler(|e| ErrorUnauthorized(e.kind())))
+            .route(
+                "/",
+                web::get().to(|req: HttpRequest| async move {
+                    req.extensions().get::<TestClaims>().unwrap().sub.clone()
+                }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn issued_tokens_pass_the_issuers_middleware() {
+    let issuer = JwtIssuer::<TestClaims>::new(key("k1")).policy(policy());
+    let token = issuer.issue(&TestClaims::new("alice")).unwrap();
+    assert_eq!(verify(issuer.middleware(), &token).await, "alice");
+}
+
+#[actix_web::test]
+async fn stamps_the_policy_claims() {
+    let issuer = JwtIssuer::<Value>::new(key("k1")).policy(policy());
+    let issued = issuer.issue_token(&json!({ "sub": "alice" })).unwrap();
+    let claims = &issued.claims;
+    assert_eq!(claims.iss.as_deref(), Some("https://issuer.example"));
+    assert!(claims.jti.as_ref().is_some_and(|jti| !jti.is_empty()));
+    let now = get_current_timestamp();
+    assert!(claims.exp.is_some_and(|exp| exp > now && exp <= now + 300));
+    assert_eq!(
+        decode_header(&issued.token).unwrap().kid.as_deref(),
+        Some("k1")
+    );
+
+    let payload = issued.token.split('.').nth(1).unwrap();
+    let payload: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+    assert_eq!(payload["aud"], "api");
+
+    let other = issuer.issue_token(&json!({ "sub": "alice" })).unwrap();
+    assert_ne!(other.claims.jti, issued.claims.jti);
+}
+
+#[actix_web::test]
+async fn keeps_registered_claims_of_the_payload() {
+    let issuer = JwtIssuer::<Value>::new(key("k1")).policy(policy());
+    let issued = issuer
+        .issue_token(&json!({ "sub": "alice", "jti": "fixed" }))
+        .unwrap();
+    assert_eq!(issued.claims.jti.as_deref(), Some("fixed"));
+}
+
+#[actix_web::test]
+async fn rotated_key_sets_verify_tokens_of_the_previous_key() {
+    let old = JwtIssuer::<TestClaims>::new(key("k1")).policy(policy());
+    let rotated =
+        JwtIssuer::<TestClaims>::with_key_set(SigningKeySet::new(key("k2")).key(key("k1")))
+            .policy(policy());
+    assert_eq!(rotated.key_set().active_kid(), Some("k2"));
+
+    let old_token = old.issue(&TestClaims::new("alice")).unwrap();
+    let new_token = rotated.issue(&TestClaims::new("bob")).unwrap();
+    assert_eq!(
+        decode_header(&new_token).unwrap().kid.as_deref(),
+        Some("k2")
+    );
+    assert_eq!(verify(rotated.middleware(), &old_token).await, "alice");
+    assert_eq!(verify(rotated.middleware(), &new_token).await, "bob");
+
+    let retired = JwtIssuer::<TestClaims>::new(key("k2")).policy(policy());
+    assert_eq!(
+        verify(retired.middleware(), &old_token).await,
+        "unknown_key"
+    );
+}
+
+#[actix_web::test]
+async fn middleware_checks_the_policy_audience() {
+    let issuer = JwtIssuer::<TestClaims>::new(key("k1")).policy(policy());
+    let other = JwtIssuer::<TestClaims>::new(key("k1")).policy(policy().audience(["other"]));
+    let token = other.issue(&TestClaims::new("alice")).unwrap();
+    assert_eq!(
+        verify(issuer.middleware(), &token).await,
+        "invalid_audience"
+    );
+}
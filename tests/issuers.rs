@@ -0,0 +1,124 @@
+mod common;
+
+use actix_jwt_middleware::{
+    IssuerRegistry, JwtMiddleware, ResolvedKey, TrustedIssuer, VerifiedIssuer,
+};
+use actix_web::{error::ErrorUnauthorized, test, web, App};
+use common::{bearer, TestClaims, SECRET};
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, DecodingKey, EncodingKey, Header};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde_json::{json, Value};
+
+const HMAC_ISSUER: &str = "https://hmac.example";
+const ED25519_ISSUER: &str = "https://ed25519.example";
+
+struct Ed25519 {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+fn ed25519() -> Ed25519 {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    Ed25519 {
+        encoding: EncodingKey::from_ed_der(pkcs8.as_ref()),
+        decoding: DecodingKey::from_ed_der(key_pair.public_key().as_ref()),
+    }
+}
+
+fn payload(iss: &str) -> Value {
+    json!({ "sub": "alice", "iss": iss, "aud": "api", "exp": get_current_timestamp() + 600 })
+}
+
+fn hmac_token(claims: &Value) -> String {
+    encode(
+        &Header::new(Algorithm::HS256),
+        claims,
+        &EncodingKey::from_secret(SECRET),
+    )
+    .unwrap()
+}
+
+fn ed25519_token(key: &Ed25519, claims: &Value) -> String {
+    encode(&Header::new(Algorithm::EdDSA), claims, &key.encoding).unwrap()
+}
+
+fn registry(key: &Ed25519) -> IssuerRegistry {
+    IssuerRegistry::new()
+        .issuer(
+            TrustedIssuer::new(
+                HMAC_ISSUER,
+                ResolvedKey::new(DecodingKey::from_secret(SECRET)),
+                &[Algorithm::HS256],
+            )
+            .audience(&["api"]),
+        )
+        .issuer(
+            TrustedIssuer::new(
+                ED25519_ISSUER,
+                ResolvedKey::new(key.decoding.clone()),
+                &[Algorithm::EdDSA],
+            )
+            .audience(&["api"]),
+        )
+}
+
+// the body is the verified issuer, or the error kind
+async fn verify(registry: IssuerRegistry, token: &str) -> String {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                JwtMiddleware::<TestClaims>::with_issuers(registry)
+                    .error_handler(|e| ErrorUnauthorized(e.kind())),
+            )
+            .route(
+                "/",
+                web::get().to(|issuer: VerifiedIssuer| async move { issuer.to_string() }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn verifies_each_issuer_with_its_own_keys() {
+    let key = ed25519();
+    let token = hmac_token(&payload(HMAC_ISSUER));
+    assert_eq!(verify(registry(&key), &token).await, HMAC_ISSUER);
+    let token = ed25519_token(&key, &payload(ED25519_ISSUER));
+    assert_eq!(verify(registry(&key), &token).await, ED25519_ISSUER);
+}
+
+#[actix_web::test]
+async fn rejects_unknown_issuers() {
+    let key = ed25519();
+    let token = hmac_token(&payload("https://unknown.example"));
+    assert_eq!(verify(registry(&key), &token).await, "unknown_issuer");
+}
+
+#[actix_web::test]
+async fn rejects_tokens_signed_with_another_issuers_key() {
+    let key = ed25519();
+    let token = ed25519_token(&key, &payload(HMAC_ISSUER));
+    assert_eq!(verify(registry(&key), &token).await, "invalid_algorithm");
+    let token = hmac_token(&payload(ED25519_ISSUER));
+    assert_eq!(verify(registry(&key), &token).await, "invalid_algorithm");
+}
+
+#[actix_web::test]
+async fn applies_the_issuers_validation() {
+    let key = ed25519();
+    let mut claims = payload(HMAC_ISSUER);
+    claims["aud"] = "other".into();
+    assert_eq!(
+        verify(registry(&key), &hmac_token(&claims)).await,
+        "invalid_audience"
+    );
+}
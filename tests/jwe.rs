@@ -0,0 +1,168 @@
+#![cfg(feature = "jwe")]
+
+mod common;
+
+use actix_jwt_middleware::{JweDecryptor, JweKey};
+use actix_web::{error::ErrorUnauthorized, test, web, App, HttpMessage, HttpRequest};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{bearer, claims, middleware, token, TestClaims};
+use p256::elliptic_curve::rand_core::OsRng;
+use p256::{ecdh::diffie_hellman, elliptic_curve::sec1::ToEncodedPoint, PublicKey, SecretKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+// ECDH-ES with A256GCM, the content key is derived with the Concat KDF of RFC 7518 section 4.6.2
+fn encrypt(recipient: &PublicKey, mut header: Value, payload: &str) -> String {
+    let ephemeral = SecretKey::random(&mut OsRng);
+    let point = ephemeral.public_key().to_encoded_point(false);
+    header["alg"] = "ECDH-ES".into();
+    header["enc"] = "A256GCM".into();
+    header["epk"] = json!({
+        "kty": "EC",
+        "crv": "P-256",
+        "x": URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+        "y": URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+    });
+    let shared = diffie_hellman(ephemeral.to_nonzero_scalar(), recipient.as_affine());
+    let mut kdf = Sha256::new();
+    kdf.update(1u32.to_be_bytes());
+    kdf.update(shared.raw_secret_bytes());
+    for value in [&b"A256GCM"[..], b"", b""] {
+        kdf.update((value.len() as u32).to_be_bytes());
+        kdf.update(value);
+    }
+    kdf.update(256u32.to_be_bytes());
+    let cek = kdf.finalize();
+
+    let protected = URL_SAFE_NO_PAD.encode(header.to_string());
+    let iv = [7u8; 12];
+    let sealed = Aes256Gcm::new_from_slice(&cek)
+        .unwrap()
+        .encrypt(
+            Nonce::from_slice(&iv),
+            Payload {
+                msg: payload.as_bytes(),
+                aad: protected.as_bytes(),
+            },
+        )
+        .unwrap();
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - 16);
+    format!(
+        "{}..{}.{}.{}",
+        protected,
+        URL_SAFE_NO_PAD.encode(iv),
+        URL_SAFE_NO_PAD.encode(ciphertext),
+        URL_SAFE_NO_PAD.encode(tag)
+    )
+}
+
+// the body is the subject, or the error kind
+async fn verify(key: JweKey, token: &str) -> String {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>()
+                    .jwe_decryptor(JweDecryptor::new().key(key))
+                    .error_handler(|e| ErrorUnauthorized(e.kind())),
+            )
+            .route(
+                "/",
+                web::get().to(|req: HttpRequest| async move {
+                    req.extensions().get::<TestClaims>().unwrap().sub.clone()
+                }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn decrypts_and_verifies_the_nested_token() {
+    let key = SecretKey::random(&mut OsRng);
+    let jwe = encrypt(&key.public_key(), json!({}), &token(&claims("alice")));
+    assert_eq!(verify(JweKey::p256(key), &jwe).await, "alice");
+}
+
+#[actix_web::test]
+async fn still_accepts_plain_tokens() {
+    let key = SecretKey::random(&mut OsRng);
+    assert_eq!(
+        verify(JweKey::p256(key), &token(&claims("alice"))).await,
+        "alice"
+    );
+}
+
+#[actix_web::test]
+async fn rejects_tokens_for_other_keys() {
+    let key = SecretKey::random(&mut OsRng);
+    let other = SecretKey::random(&mut OsRng);
+    let jwe = encrypt(&other.public_key(), json!({}), &token(&claims("alice")));
+    assert_eq!(
+        verify(JweKey::p256(key), &jwe).await,
+        "jwe_decryption_failed"
+    );
+
+    let key = SecretKey::random(&mut OsRng);
+    let jwe = encrypt(
+        &key.public_key(),
+        json!({ "kid": "b" }),
+        &token(&claims("alice")),
+    );
+    assert_eq!(
+        verify(JweKey::p256(key).kid("a"), &jwe).await,
+        "jwe_decryption_failed"
+    );
+}
+
+#[actix_web::test]
+async fn rejects_critical_header_extensions() {
+    let key = SecretKey::random(&mut OsRng);
+    let header = json!({ "crit": ["exp"], "exp": 0 });
+    let jwe = encrypt(&key.public_key(), header, &token(&claims("alice")));
+    assert_eq!(
+        verify(JweKey::p256(key.clone()), &jwe).await,
+        "unsupported_jwe_algorithm"
+    );
+
+    let jwe = encrypt(
+        &key.public_key(),
+        json!({ "crit": [] }),
+        &token(&claims("alice")),
+    );
+    assert_eq!(verify(JweKey::p256(key), &jwe).await, "invalid_jwe");
+}
+
+#[actix_web::test]
+async fn rejects_encrypted_payloads_that_are_not_signed() {
+    let key = SecretKey::random(&mut OsRng);
+    let jwe = encrypt(&key.public_key(), json!({}), r#"{"sub":"alice"}"#);
+    assert_eq!(verify(JweKey::p256(key), &jwe).await, "invalid_token");
+}
+
+#[cfg(not(feature = "jwe-rsa"))]
+#[actix_web::test]
+async fn rsa_oaep_requires_its_feature() {
+    let protected =
+        URL_SAFE_NO_PAD.encode(json!({ "alg": "RSA-OAEP", "enc": "A256GCM" }).to_string());
+    let jwe = [
+        &protected[..],
+        &URL_SAFE_NO_PAD.encode([1u8; 256]),
+        &URL_SAFE_NO_PAD.encode([7u8; 12]),
+        &URL_SAFE_NO_PAD.encode(b"payload"),
+        &URL_SAFE_NO_PAD.encode([0u8; 16]),
+    ]
+    .join(".");
+    let key = SecretKey::random(&mut OsRng);
+    assert_eq!(
+        verify(JweKey::p256(key), &jwe).await,
+        "unsupported_jwe_algorithm"
+    );
+}
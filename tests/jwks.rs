@@ -0,0 +1,139 @@
+#![cfg(feature = "http")]
+
+mod common;
+
+use std::time::Duration;
+
+use actix_jwt_middleware::{JwksError, JwksKeyResolver, JwksSource, JwtMiddleware};
+use actix_web::{error::ErrorUnauthorized, test, web, App, HttpResponse};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{bearer, claims, validation, HttpStub, TestClaims};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::json;
+
+fn jwks(kids: &[&str]) -> String {
+    let keys: Vec<_> = kids
+        .iter()
+        .map(|kid| {
+            json!({
+                "kty": "oct",
+                "kid": kid,
+                "alg": "HS256",
+                "k": URL_SAFE_NO_PAD.encode(secret(kid)),
+            })
+        })
+        .collect();
+    json!({ "keys": keys }).to_string()
+}
+
+fn secret(kid: &str) -> Vec<u8> {
+    format!("secret-of-{}", kid).into_bytes()
+}
+
+fn token(kid: &str) -> String {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some(kid.into());
+    encode(
+        &header,
+        &claims("alice"),
+        &EncodingKey::from_secret(&secret(kid)),
+    )
+    .unwrap()
+}
+
+// the body is the error kind, or "ok"
+async fn verify(resolver: JwksKeyResolver, token: &str) -> String {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                JwtMiddleware::<TestClaims>::with_key_resolver(resolver, validation())
+                    .error_handler(|e| ErrorUnauthorized(e.kind())),
+            )
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn fetches_keys_on_first_use() {
+    let stub = HttpStub::start(200, &jwks(&["a"]));
+    let resolver = JwksKeyResolver::new(JwksSource::Url(stub.url.clone()));
+    assert!(resolver.last_refreshed().is_none());
+    assert_eq!(verify(resolver.clone(), &token("a")).await, "ok");
+    assert_eq!(stub.requests().len(), 1);
+    assert!(resolver.last_refreshed().is_some());
+}
+
+#[actix_web::test]
+async fn refreshes_on_unknown_kid() {
+    let stub = HttpStub::start(200, &jwks(&["a"]));
+    let resolver = JwksKeyResolver::load(JwksSource::Url(stub.url.clone()))
+        .unwrap()
+        .min_refresh_interval(Duration::ZERO);
+    stub.respond(200, &jwks(&["a", "b"]));
+    assert_eq!(verify(resolver, &token("b")).await, "ok");
+    assert_eq!(stub.requests().len(), 2);
+}
+
+#[actix_web::test]
+async fn refreshes_when_the_only_key_has_no_kid() {
+    let unnamed = json!({
+        "keys": [{ "kty": "oct", "alg": "HS256", "k": URL_SAFE_NO_PAD.encode(secret("a")) }],
+    });
+    let stub = HttpStub::start(200, &unnamed.to_string());
+    let resolver = JwksKeyResolver::load(JwksSource::Url(stub.url.clone()))
+        .unwrap()
+        .min_refresh_interval(Duration::ZERO);
+    stub.respond(200, &jwks(&["b"]));
+    assert_eq!(verify(resolver, &token("b")).await, "ok");
+    assert_eq!(stub.requests().len(), 2);
+}
+
+#[actix_web::test]
+async fn rate_limits_refreshes() {
+    let stub = HttpStub::start(200, &jwks(&["a"]));
+    let resolver = JwksKeyResolver::load(JwksSource::Url(stub.url.clone()))
+        .unwrap()
+        .min_refresh_interval(Duration::from_secs(60));
+    stub.respond(200, &jwks(&["a", "b"]));
+    assert_eq!(verify(resolver.clone(), &token("b")).await, "unknown_key");
+    assert_eq!(verify(resolver, &token("c")).await, "unknown_key");
+    assert_eq!(stub.requests().len(), 1);
+}
+
+#[actix_web::test]
+async fn serves_the_cached_set_when_a_fetch_fails() {
+    let stub = HttpStub::start(200, &jwks(&["a"]));
+    let resolver = JwksKeyResolver::load(JwksSource::Url(stub.url.clone()))
+        .unwrap()
+        .min_refresh_interval(Duration::ZERO);
+    stub.respond(500, "");
+    assert!(matches!(resolver.refresh(), Err(JwksError::Http(_))));
+    assert_eq!(verify(resolver.clone(), &token("a")).await, "ok");
+    // a fetch failed, but keys were loaded before, so the kid really is unknown
+    assert_eq!(verify(resolver, &token("b")).await, "unknown_key");
+    assert_eq!(stub.requests().len(), 3);
+}
+
+#[actix_web::test]
+async fn reports_unavailable_keys_when_nothing_was_loaded() {
+    let stub = HttpStub::start(503, "");
+    assert!(JwksKeyResolver::load(JwksSource::Url(stub.url.clone())).is_err());
+    let resolver = JwksKeyResolver::new(JwksSource::Url(stub.url.clone()));
+    assert_eq!(verify(resolver, &token("a")).await, "keys_unavailable");
+}
+
+#[actix_web::test]
+async fn rejects_sets_without_usable_keys() {
+    let stub = HttpStub::start(200, r#"{"keys":[]}"#);
+    let resolver = JwksKeyResolver::new(JwksSource::Url(stub.url.clone()));
+    assert!(matches!(resolver.refresh(), Err(JwksError::NoUsableKeys)));
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use actix_jwt_middleware::{
+    FileKeyResolver, JwtMiddleware, KeyFile, RejectedTokenCache, VerifiedTokenCache,
+};
+use actix_web::{error::ErrorUnauthorized, test, web, App, HttpResponse};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{bearer, claims, TestClaims};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header, Validation};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+
+fn directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("key-files-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn sign(key: &EncodingKey, alg: Algorithm, kid: Option<&str>) -> String {
+    let mut header = Header::new(alg);
+    header.kid = kid.map(str::to_owned);
+    encode(&header, &claims("alice"), key).unwrap()
+}
+
+fn hs256(secret: &[u8], kid: Option<&str>) -> String {
+    sign(&EncodingKey::from_secret(secret), Algorithm::HS256, kid)
+}
+
+// the body is "ok", or the error kind
+async fn call_all(jwt: JwtMiddleware<TestClaims>, tokens: &[&str]) -> Vec<String> {
+    let app = test::init_service(
+        App::new()
+            .wrap(jwt.error_handler(|e| ErrorUnauthorized(e.kind())))
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+            ),
+    )
+    .await;
+    let mut bodies = Vec::new();
+    for token in tokens {
+        let req = test::TestRequest::get()
+            .insert_header(bearer(token))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        bodies.push(String::from_utf8(body.to_vec()).unwrap());
+    }
+    bodies
+}
+
+fn hs256_middleware(resolver: FileKeyResolver) -> JwtMiddleware<TestClaims> {
+    JwtMiddleware::with_key_resolver(resolver, Validation::new(Algorithm::HS256))
+}
+
+#[actix_web::test]
+async fn key_without_kid_only_matches_tokens_without_kid() {
+    let directory = directory("single");
+    let path = directory.join("secret");
+    // the trailing newline isn't part of the secret
+    fs::write(&path, "file-secret\n").unwrap();
+    let resolver = FileKeyResolver::load(vec![KeyFile::secret(&path, Algorithm::HS256)]).unwrap();
+    let bodies = call_all(
+        hs256_middleware(resolver),
+        &[
+            &hs256(b"file-secret", None),
+            &hs256(b"other-secret", None),
+            &hs256(b"file-secret", Some("rotated-1")),
+        ],
+    )
+    .await;
+    assert_eq!(bodies, ["ok", "invalid_signature", "unknown_key"]);
+}
+
+#[actix_web::test]
+async fn loads_pem_public_keys() {
+    let directory = directory("pem");
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    // SubjectPublicKeyInfo of an Ed25519 key is a fixed prefix followed by the raw key
+    let mut spki = vec![
+        0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+    ];
+    spki.extend_from_slice(key_pair.public_key().as_ref());
+    let pem = format!(
+        "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+        STANDARD.encode(&spki)
+    );
+    let path = directory.join("public.pem");
+    fs::write(&path, pem).unwrap();
+    let resolver = FileKeyResolver::load(vec![KeyFile::pem(&path)]).unwrap();
+    let jwt = JwtMiddleware::with_key_resolver(resolver, Validation::new(Algorithm::EdDSA));
+    let key = EncodingKey::from_ed_der(pkcs8.as_ref());
+    let bodies = call_all(
+        jwt,
+        &[
+            &sign(&key, Algorithm::EdDSA, None),
+            &sign(&key, Algorithm::EdDSA, Some("issuer-key-2024")),
+        ],
+    )
+    .await;
+    assert_eq!(bodies, ["ok", "unknown_key"]);
+}
+
+#[actix_web::test]
+async fn selects_keys_by_kid() {
+    let directory = directory("kids");
+    fs::write(directory.join("one"), "secret-one").unwrap();
+    fs::write(directory.join("two"), "secret-two").unwrap();
+    let resolver = FileKeyResolver::load(vec![
+        KeyFile::secret(directory.join("one"), Algorithm::HS256).kid("one"),
+        KeyFile::secret(directory.join("two"), Algorithm::HS256).kid("two"),
+    ])
+    .unwrap();
+    let bodies = call_all(
+        hs256_middleware(resolver),
+        &[
+            &hs256(b"secret-one", Some("one")),
+            &hs256(b"secret-two", Some("two")),
+            &hs256(b"secret-two", Some("one")),
+            &hs256(b"secret-two", Some("three")),
+        ],
+    )
+    .await;
+    assert_eq!(bodies, ["ok", "ok", "invalid_signature", "unknown_key"]);
+}
+
+#[actix_web::test]
+async fn reload_invalidates_cached_results() {
+    let directory = directory("reload");
+    let path = directory.join("secret");
+    fs::write(&path, "old-secret").unwrap();
+    let resolver = FileKeyResolver::load(vec![KeyFile::secret(&path, Algorithm::HS256)]).unwrap();
+    let jwt = hs256_middleware(resolver.clone())
+        .verified_token_cache(VerifiedTokenCache::new(16, Duration::from_secs(600)))
+        .rejected_token_cache(RejectedTokenCache::new(16, Duration::from_secs(600)));
+    let old = hs256(b"old-secret", None);
+    let new = hs256(b"new-secret", None);
+    let bodies = call_all(jwt.clone(), &[&old, &new]).await;
+    assert_eq!(bodies, ["ok", "invalid_signature"]);
+
+    fs::write(&path, "new-secret").unwrap();
+    resolver.reload().unwrap();
+    let bodies = call_all(jwt, &[&old, &new]).await;
+    assert_eq!(bodies, ["invalid_signature", "ok"]);
+}
+
+#[actix_web::test]
+async fn failed_reloads_keep_the_previous_keys() {
+    let directory = directory("failed");
+    let path = directory.join("jwks.json");
+    fs::write(
+        &path,
+        r#"{"keys":[{"kty":"oct","kid":"k1","alg":"HS256","k":"c2VjcmV0LW9uZQ"}]}"#,
+    )
+    .unwrap();
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let reported = outcomes.clone();
+    let resolver = FileKeyResolver::load_with_reporting(vec![KeyFile::jwks(&path)], move |r| {
+        reported.lock().unwrap().push(r.is_ok());
+    })
+    .unwrap();
+
+    fs::write(&path, "{ not json").unwrap();
+    assert!(resolver.reload().is_err());
+    assert_eq!(*outcomes.lock().unwrap(), [true, false]);
+    let bodies = call_all(
+        hs256_middleware(resolver),
+        &[&hs256(b"secret-one", Some("k1"))],
+    )
+    .await;
+    assert_eq!(bodies, ["ok"]);
+}
+
+#[actix_web::test]
+async fn picks_up_changed_files_on_requests() {
+    let directory = directory("poll");
+    let path = directory.join("secret");
+    fs::write(&path, "old-secret").unwrap();
+    let resolver = FileKeyResolver::load(vec![KeyFile::secret(&path, Algorithm::HS256)])
+        .unwrap()
+        .poll_interval(Duration::ZERO);
+    let before = resolver.last_reloaded();
+
+    fs::write(&path, "new-secret").unwrap();
+    // modification times can be coarse, make sure the change is visible
+    let modified = SystemTime::now() + Duration::from_secs(10);
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    // the first request is checked against the keys it found, the reload runs in the background
+    let jwt = hs256_middleware(resolver.clone());
+    call_all(jwt.clone(), &[&hs256(b"old-secret", None)]).await;
+    for _ in 0..50 {
+        if resolver.last_reloaded() != before {
+            break;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_ne!(resolver.last_reloaded(), before);
+    let bodies = call_all(jwt, &[&hs256(b"new-secret", None)]).await;
+    assert_eq!(bodies, ["ok"]);
+}
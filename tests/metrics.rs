@@ -0,0 +1,166 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_jwt_middleware::{FailureThrottle, JwtMiddleware, VerifiedTokenCache};
+use actix_web::{test, web, App, HttpResponse};
+use common::{bearer, claims, middleware, token, TestClaims};
+use metrics::{
+    Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+
+type Values = Arc<Mutex<HashMap<String, f64>>>;
+
+// sums every counter and histogram by name and labels, e.g. `jwt_verifications_total{outcome=success}`
+#[derive(Clone, Default)]
+struct Metrics(Values);
+
+struct Handle {
+    name: String,
+    values: Values,
+}
+
+impl Handle {
+    fn add(&self, value: f64) {
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .entry(self.name.clone())
+            .or_default() += value;
+    }
+}
+
+impl CounterFn for Handle {
+    fn increment(&self, value: u64) {
+        self.add(value as f64);
+    }
+
+    fn absolute(&self, value: u64) {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(self.name.clone(), value as f64);
+    }
+}
+
+impl HistogramFn for Handle {
+    fn record(&self, _value: f64) {
+        // histograms count their samples
+        self.add(1.0);
+    }
+}
+
+impl Metrics {
+    fn handle(&self, key: &Key) -> Arc<Handle> {
+        let labels: Vec<_> = key
+            .labels()
+            .map(|label| format!("{}={}", label.key(), label.value()))
+            .collect();
+        Arc::new(Handle {
+            name: format!("{}{{{}}}", key.name(), labels.join(",")),
+            values: self.0.clone(),
+        })
+    }
+
+    fn get(&self, name: &str) -> f64 {
+        self.0
+            .lock()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl Recorder for Metrics {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.handle(key))
+    }
+
+    fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(self.handle(key))
+    }
+}
+
+async fn call_all(metrics: &Metrics, jwt: JwtMiddleware<TestClaims>, tokens: &[&str]) {
+    let _guard = metrics::set_default_local_recorder(metrics);
+    let app = test::init_service(
+        App::new()
+            .wrap(jwt)
+            .route("/", web::get().to(|| async { HttpResponse::Ok().finish() })),
+    )
+    .await;
+    for token in tokens {
+        let req = test::TestRequest::get()
+            .peer_addr("192.0.2.1:1234".parse().unwrap())
+            .insert_header(bearer(token))
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+}
+
+#[actix_web::test]
+async fn counts_verification_outcomes() {
+    let metrics = Metrics::default();
+    let valid = token(&claims("alice"));
+    let tampered = format!("{}x", valid);
+    call_all(&metrics, middleware(), &[&valid, &valid, &tampered]).await;
+    assert_eq!(metrics.get("jwt_verifications_total{outcome=success}"), 2.0);
+    assert_eq!(
+        metrics.get("jwt_verifications_total{outcome=invalid_signature}"),
+        1.0
+    );
+    assert_eq!(metrics.get("jwt_key_resolution_seconds{}"), 3.0);
+}
+
+#[actix_web::test]
+async fn counts_cache_lookups() {
+    let metrics = Metrics::default();
+    let valid = token(&claims("alice"));
+    let jwt =
+        middleware().verified_token_cache(VerifiedTokenCache::new(16, Duration::from_secs(60)));
+    call_all(&metrics, jwt, &[&valid, &valid, &valid]).await;
+    assert_eq!(
+        metrics.get("jwt_cache_lookups_total{cache=verified_token,result=miss}"),
+        1.0
+    );
+    assert_eq!(
+        metrics.get("jwt_cache_lookups_total{cache=verified_token,result=hit}"),
+        2.0
+    );
+    assert_eq!(metrics.get("jwt_verifications_total{outcome=success}"), 3.0);
+}
+
+#[actix_web::test]
+async fn counts_throttled_requests() {
+    let metrics = Metrics::default();
+    let invalid = format!("{}x", token(&claims("alice")));
+    let jwt = middleware().failure_throttle(FailureThrottle::new(1, Duration::from_secs(60)));
+    call_all(&metrics, jwt, &[&invalid, &invalid, &invalid]).await;
+    assert_eq!(
+        metrics.get("jwt_verifications_total{outcome=invalid_signature}"),
+        1.0
+    );
+    assert_eq!(
+        metrics.get("jwt_verifications_total{outcome=throttled}"),
+        2.0
+    );
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use actix_jwt_middleware::{CertificateBinding, JwtMiddleware};
+use actix_web::{error::ErrorUnauthorized, http::header::HeaderName, test, web, App, HttpResponse};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use common::{bearer, middleware, token, TestClaims};
+use jsonwebtoken::get_current_timestamp;
+use ring::digest;
+use serde_json::json;
+
+// only the thumbprint is compared, the bytes don't have to be a real certificate
+const CERTIFICATE: &[u8] = b"client certificate der bytes, long enough to wrap in pem";
+
+fn bound_token(certificate: &[u8]) -> String {
+    let thumbprint = URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, certificate));
+    token(&json!({
+        "sub": "alice",
+        "exp": get_current_timestamp() + 600,
+        "cnf": { "x5t#S256": thumbprint },
+    }))
+}
+
+fn client_cert_header() -> HeaderName {
+    HeaderName::from_static("x-client-cert")
+}
+
+async fn call(jwt: JwtMiddleware<TestClaims>, token: &str, certificate: Option<&str>) -> String {
+    let app = test::init_service(
+        App::new()
+            .wrap(jwt.error_handler(|e| ErrorUnauthorized(e.kind())))
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+            ),
+    )
+    .await;
+    let mut req = test::TestRequest::get().insert_header(bearer(token));
+    if let Some(certificate) = certificate {
+        req = req.insert_header((client_cert_header(), certificate));
+    }
+    let body = test::call_and_read_body(&app, req.to_request()).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn binding() -> CertificateBinding {
+    CertificateBinding::new().trusted_header(client_cert_header())
+}
+
+#[actix_web::test]
+async fn accepts_the_bound_certificate_from_the_trusted_header() {
+    let token = bound_token(CERTIFICATE);
+    let jwt = middleware().certificate_binding(binding());
+    let der = STANDARD.encode(CERTIFICATE);
+    assert_eq!(call(jwt.clone(), &token, Some(&der)).await, "ok");
+
+    let other = STANDARD.encode(b"another client certificate");
+    assert_eq!(
+        call(jwt.clone(), &token, Some(&other)).await,
+        "certificate_mismatch"
+    );
+    assert_eq!(call(jwt, &token, None).await, "certificate_mismatch");
+}
+
+#[actix_web::test]
+async fn parses_url_encoded_pem() {
+    let token = bound_token(CERTIFICATE);
+    let jwt = middleware().certificate_binding(binding());
+    let encoded = STANDARD.encode(CERTIFICATE);
+    let (first, second) = encoded.split_at(40);
+    let pem = format!(
+        "-----BEGIN CERTIFICATE-----\n{}\n{}\n-----END CERTIFICATE-----\n",
+        first, second
+    );
+    let header = pem
+        .replace('\n', "%0A")
+        .replace(' ', "%20")
+        .replace('/', "%2F")
+        .replace('=', "%3D");
+    assert_eq!(call(jwt, &token, Some(&header)).await, "ok");
+}
+
+#[actix_web::test]
+async fn ignores_the_header_unless_trusted() {
+    let token = bound_token(CERTIFICATE);
+    let jwt = middleware().certificate_binding(CertificateBinding::new());
+    let der = STANDARD.encode(CERTIFICATE);
+    assert_eq!(call(jwt, &token, Some(&der)).await, "certificate_mismatch");
+}
+
+#[actix_web::test]
+async fn unbound_tokens_are_rejected_only_when_required() {
+    let unbound = token(&common::claims("alice"));
+    let jwt = middleware().certificate_binding(binding());
+    assert_eq!(call(jwt, &unbound, None).await, "ok");
+
+    let jwt = middleware().certificate_binding(binding().required(true));
+    assert_eq!(call(jwt, &unbound, None).await, "certificate_mismatch");
+}
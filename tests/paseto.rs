@@ -0,0 +1,184 @@
+#![cfg(feature = "paseto")]
+
+mod common;
+
+use actix_jwt_middleware::{Claims, JwtMiddleware, PasetoKey, PasetoV4};
+use actix_web::{error::ErrorUnauthorized, test, web, App};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{bearer, claims, middleware, token};
+use jsonwebtoken::Validation;
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde_json::{json, Value};
+
+struct Signer {
+    key_pair: Ed25519KeyPair,
+}
+
+impl Signer {
+    fn new() -> Self {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Self {
+            key_pair: Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap(),
+        }
+    }
+
+    fn key(&self) -> PasetoKey {
+        PasetoKey::public(self.key_pair.public_key().as_ref().try_into().unwrap())
+    }
+
+    fn sign(&self, claims: &Value, footer: &[u8], implicit: &[u8]) -> String {
+        let message = claims.to_string().into_bytes();
+        let header = b"v4.public.";
+        let signature = self
+            .key_pair
+            .sign(&pae(&[header, &message, footer, implicit]));
+        let mut payload = message;
+        payload.extend_from_slice(signature.as_ref());
+        let mut token = format!("v4.public.{}", URL_SAFE_NO_PAD.encode(payload));
+        if !footer.is_empty() {
+            token = format!("{}.{}", token, URL_SAFE_NO_PAD.encode(footer));
+        }
+        token
+    }
+}
+
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut output = (pieces.len() as u64).to_le_bytes().to_vec();
+    for piece in pieces {
+        output.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+        output.extend_from_slice(piece);
+    }
+    output
+}
+
+fn paseto_claims(sub: &str, exp: &str) -> Value {
+    json!({ "sub": sub, "exp": exp, "aud": "api" })
+}
+
+fn validation() -> Validation {
+    let mut validation = Validation::default();
+    validation.set_audience(&["api"]);
+    validation
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct PasetoClaims {
+    sub: String,
+}
+
+// the body is the subject, or the error kind
+async fn verify(middleware: JwtMiddleware<PasetoClaims>, token: &str) -> String {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware.error_handler(|e| ErrorUnauthorized(e.kind())))
+            .route(
+                "/",
+                web::get().to(|claims: Claims<PasetoClaims>| async move { claims.sub.clone() }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn verifies_public_tokens() {
+    let signer = Signer::new();
+    let format = PasetoV4::new(validation()).key(signer.key());
+    let token = signer.sign(&paseto_claims("alice", "2999-01-01T00:00:00Z"), b"", b"");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format.clone()), &token).await,
+        "alice"
+    );
+
+    let mut tampered = token.into_bytes();
+    let last = tampered.len() - 2;
+    tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+    let tampered = String::from_utf8(tampered).unwrap();
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format), &tampered).await,
+        "invalid_signature"
+    );
+}
+
+#[actix_web::test]
+async fn checks_registered_claims() {
+    let signer = Signer::new();
+    let format = || PasetoV4::new(validation()).key(signer.key());
+    let expired = signer.sign(&paseto_claims("alice", "2000-01-01T00:00:00Z"), b"", b"");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format()), &expired).await,
+        "expired"
+    );
+    let mut claims = paseto_claims("alice", "2999-01-01T00:00:00Z");
+    claims["aud"] = "other".into();
+    let token = signer.sign(&claims, b"", b"");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format()), &token).await,
+        "invalid_audience"
+    );
+}
+
+#[actix_web::test]
+async fn selects_keys_by_footer_kid() {
+    let current = Signer::new();
+    let previous = Signer::new();
+    let format = PasetoV4::new(validation())
+        .key(current.key().kid("current"))
+        .key(previous.key().kid("previous"));
+    let claims = paseto_claims("alice", "2999-01-01T00:00:00Z");
+    let token = previous.sign(&claims, br#"{"kid":"previous"}"#, b"");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format.clone()), &token).await,
+        "alice"
+    );
+    let token = previous.sign(&claims, br#"{"kid":"current"}"#, b"");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format), &token).await,
+        "invalid_signature"
+    );
+}
+
+#[actix_web::test]
+async fn binds_footer_and_implicit_assertion() {
+    let signer = Signer::new();
+    let claims = paseto_claims("alice", "2999-01-01T00:00:00Z");
+    let format = PasetoV4::new(validation())
+        .key(signer.key())
+        .footer("tenant-a")
+        .implicit_assertion("api.example");
+    let token = signer.sign(&claims, b"tenant-a", b"api.example");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format.clone()), &token).await,
+        "alice"
+    );
+    let token = signer.sign(&claims, b"tenant-b", b"api.example");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format.clone()), &token).await,
+        "invalid_token"
+    );
+    let token = signer.sign(&claims, b"tenant-a", b"other.example");
+    assert_eq!(
+        verify(JwtMiddleware::with_token_format(format), &token).await,
+        "invalid_signature"
+    );
+}
+
+#[actix_web::test]
+async fn jwts_keep_working_next_to_paseto() {
+    let signer = Signer::new();
+    let middleware =
+        middleware::<PasetoClaims>().token_format(PasetoV4::new(validation()).key(signer.key()));
+    assert_eq!(
+        verify(middleware.clone(), &token(&claims("alice"))).await,
+        "alice"
+    );
+    let paseto = signer.sign(&paseto_claims("bob", "2999-01-01T00:00:00Z"), b"", b"");
+    assert_eq!(verify(middleware, &paseto).await, "bob");
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use actix_jwt_middleware::{
+    JwtIssuer, MemoryRefreshTokenStore, RefreshError, RefreshTokenRecord, RefreshTokenStore,
+    RefreshTokens, SigningKey,
+};
+use actix_web::{http::StatusCode, test, App};
+use common::{TestClaims, SECRET};
+use futures::future::LocalBoxFuture;
+use jsonwebtoken::{get_current_timestamp, Algorithm};
+use serde_json::{Map, Value};
+
+fn tokens<S: RefreshTokenStore + 'static>(store: S) -> RefreshTokens<TestClaims> {
+    RefreshTokens::new(
+        JwtIssuer::new(SigningKey::hmac(SECRET, Algorithm::HS256)),
+        store,
+    )
+}
+
+// revokes the family of the next rotated token at a chosen point, like a concurrent
+// request presenting an already used token of the same family would
+#[derive(Clone, Default)]
+struct RacingStore {
+    inner: MemoryRefreshTokenStore,
+    revoke_after_mark_used: Arc<AtomicBool>,
+    revoke_after_check: Arc<AtomicBool>,
+}
+
+impl RefreshTokenStore for RacingStore {
+    fn insert<'a>(
+        &'a self,
+        token_hash: &'a str,
+        record: RefreshTokenRecord,
+    ) -> LocalBoxFuture<'a, Result<(), RefreshError>> {
+        self.inner.insert(token_hash, record)
+    }
+
+    fn mark_used<'a>(
+        &'a self,
+        token_hash: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<RefreshTokenRecord>, RefreshError>> {
+        Box::pin(async move {
+            let record = self.inner.mark_used(token_hash).await?;
+            if let Some(record) = &record {
+                if self.revoke_after_mark_used.swap(false, Ordering::SeqCst) {
+                    self.inner.revoke_family(&record.family_id).await?;
+                }
+            }
+            Ok(record)
+        })
+    }
+
+    fn revoke_family<'a>(
+        &'a self,
+        family_id: &'a str,
+    ) -> LocalBoxFuture<'a, Result<(), RefreshError>> {
+        self.inner.revoke_family(family_id)
+    }
+
+    fn is_family_revoked<'a>(
+        &'a self,
+        family_id: &'a str,
+    ) -> LocalBoxFuture<'a, Result<bool, RefreshError>> {
+        Box::pin(async move {
+            let revoked = self.inner.is_family_revoked(family_id).await?;
+            if self.revoke_after_check.swap(false, Ordering::SeqCst) {
+                self.inner.revoke_family(family_id).await?;
+            }
+            Ok(revoked)
+        })
+    }
+}
+
+#[actix_web::test]
+async fn rotates_refresh_tokens() {
+    let tokens = tokens(MemoryRefreshTokenStore::new());
+    let first = tokens.issue(&TestClaims::new("alice")).await.unwrap();
+    let second = tokens.refresh(&first.refresh_token).await.unwrap();
+    assert_ne!(first.refresh_token, second.refresh_token);
+    assert_eq!(second.token_type, "Bearer");
+    tokens.refresh(&second.refresh_token).await.unwrap();
+}
+
+#[actix_web::test]
+async fn reuse_revokes_the_family() {
+    let reused = Arc::new(AtomicBool::new(false));
+    let flag = reused.clone();
+    let tokens = tokens(MemoryRefreshTokenStore::new())
+        .reuse_handler(move |_| flag.store(true, Ordering::SeqCst));
+    let first = tokens.issue(&TestClaims::new("alice")).await.unwrap();
+    let second = tokens.refresh(&first.refresh_token).await.unwrap();
+    assert!(matches!(
+        tokens.refresh(&first.refresh_token).await,
+        Err(RefreshError::Reused)
+    ));
+    assert!(reused.load(Ordering::SeqCst));
+    assert!(matches!(
+        tokens.refresh(&second.refresh_token).await,
+        Err(RefreshError::InvalidGrant)
+    ));
+}
+
+#[actix_web::test]
+async fn reuse_wins_over_a_rotation_that_already_marked_its_token() {
+    let store = RacingStore::default();
+    let tokens = tokens(store.clone());
+    let pair = tokens.issue(&TestClaims::new("alice")).await.unwrap();
+    store.revoke_after_mark_used.store(true, Ordering::SeqCst);
+    assert!(matches!(
+        tokens.refresh(&pair.refresh_token).await,
+        Err(RefreshError::InvalidGrant)
+    ));
+}
+
+#[actix_web::test]
+async fn reuse_wins_over_a_rotation_that_already_passed_the_checks() {
+    let store = RacingStore::default();
+    let tokens = tokens(store.clone());
+    let pair = tokens.issue(&TestClaims::new("alice")).await.unwrap();
+    store.revoke_after_check.store(true, Ordering::SeqCst);
+    assert!(matches!(
+        tokens.refresh(&pair.refresh_token).await,
+        Err(RefreshError::InvalidGrant)
+    ));
+}
+
+#[actix_web::test]
+async fn memory_store_refuses_records_of_revoked_families() {
+    let store = MemoryRefreshTokenStore::new();
+    store.revoke_family("family").await.unwrap();
+    assert!(store.is_family_revoked("family").await.unwrap());
+    let record = RefreshTokenRecord {
+        family_id: "family".into(),
+        claims: Map::new(),
+        expires_at: get_current_timestamp() + 600,
+        used: false,
+        revoked: false,
+    };
+    assert!(matches!(
+        store.insert("hash", record).await,
+        Err(RefreshError::InvalidGrant)
+    ));
+    assert!(store.mark_used("hash").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn revoke_ends_the_family() {
+    let tokens = tokens(MemoryRefreshTokenStore::new());
+    let pair = tokens.issue(&TestClaims::new("alice")).await.unwrap();
+    tokens.revoke(&pair.refresh_token).await.unwrap();
+    assert!(tokens.refresh(&pair.refresh_token).await.is_err());
+}
+
+#[actix_web::test]
+async fn token_endpoint_rotates_and_rejects_reuse() {
+    let tokens = tokens(MemoryRefreshTokenStore::new());
+    let pair = tokens.issue(&TestClaims::new("alice")).await.unwrap();
+    let app = test::init_service(App::new().service(tokens.resource("/token"))).await;
+    let form = [
+        ("grant_type", "refresh_token"),
+        ("refresh_token", pair.refresh_token.as_str()),
+    ];
+
+    let req = test::TestRequest::post()
+        .uri("/token")
+        .set_form(form)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Cache-Control").unwrap(), "no-store");
+    let body: Value = test::read_body_json(res).await;
+    assert!(body["access_token"].is_string());
+
+    let req = test::TestRequest::post()
+        .uri("/token")
+        .set_form(form)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["error"], "invalid_grant");
+}
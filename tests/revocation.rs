@@ -0,0 +1,105 @@
+mod common;
+
+use std::{fs, time::Duration};
+
+use actix_jwt_middleware::{
+    FileRevocationStore, MemoryRevocationStore, RegisteredClaims, RevocationError, RevocationStore,
+};
+use actix_web::{error::ErrorUnauthorized, test, web, App, HttpResponse};
+use common::{bearer, middleware, token, TestClaims};
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::get_current_timestamp;
+use serde_json::json;
+
+const TTL: Duration = Duration::from_secs(3600);
+
+struct UnavailableStore;
+
+impl RevocationStore for UnavailableStore {
+    fn is_revoked<'a>(
+        &'a self,
+        _claims: &'a RegisteredClaims,
+    ) -> LocalBoxFuture<'a, Result<bool, RevocationError>> {
+        Box::pin(ready(Err(RevocationError::Backend("down".into()))))
+    }
+}
+
+// the body is "ok", or the error kind
+async fn verify<R>(store: R, token: &str) -> String
+where
+    R: RevocationStore + 'static,
+{
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>()
+                    .revocation_store(store)
+                    .error_handler(|e| ErrorUnauthorized(e.kind())),
+            )
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn token_with(sub: &str, jti: &str, iat: u64) -> String {
+    token(&json!({ "sub": sub, "jti": jti, "iat": iat, "exp": get_current_timestamp() + 600 }))
+}
+
+#[actix_web::test]
+async fn rejects_revoked_token_ids() {
+    let store = MemoryRevocationStore::new();
+    store.revoke_token("revoked", TTL);
+    let now = get_current_timestamp();
+    assert_eq!(
+        verify(store.clone(), &token_with("alice", "revoked", now)).await,
+        "revoked"
+    );
+    assert_eq!(
+        verify(store, &token_with("alice", "other", now)).await,
+        "ok"
+    );
+}
+
+#[actix_web::test]
+async fn rejects_subject_tokens_issued_before_the_cutoff() {
+    let store = MemoryRevocationStore::new();
+    let now = get_current_timestamp();
+    store.revoke_subject("alice", now, TTL);
+    assert_eq!(
+        verify(store.clone(), &token_with("alice", "1", now - 10)).await,
+        "revoked"
+    );
+    assert_eq!(
+        verify(store.clone(), &token_with("alice", "2", now)).await,
+        "ok"
+    );
+    assert_eq!(verify(store, &token_with("bob", "3", now - 10)).await, "ok");
+}
+
+#[actix_web::test]
+async fn persists_revocations_to_disk() {
+    let path = std::env::temp_dir().join(format!("revocations-{}.json", std::process::id()));
+    let store = FileRevocationStore::open(&path).unwrap();
+    store.revoke_token("revoked", TTL).unwrap();
+    let reopened = FileRevocationStore::open(&path).unwrap();
+    let token = token_with("alice", "revoked", get_current_timestamp());
+    assert_eq!(verify(reopened, &token).await, "revoked");
+    fs::remove_file(path).unwrap();
+}
+
+#[actix_web::test]
+async fn fails_closed_when_the_store_is_unavailable() {
+    let token = token_with("alice", "1", get_current_timestamp());
+    assert_eq!(
+        verify(UnavailableStore, &token).await,
+        "revocation_unavailable"
+    );
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use actix_jwt_middleware::Rfc6750;
+use actix_web::{
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    test, web, App, HttpResponse,
+};
+use common::{bearer, middleware, token, TestClaims};
+use jsonwebtoken::get_current_timestamp;
+use serde_json::{json, Value};
+
+async fn ok() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+fn www_authenticate<B>(res: &ServiceResponse<B>) -> &str {
+    res.headers()
+        .get(header::WWW_AUTHENTICATE)
+        .unwrap()
+        .to_str()
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn missing_token_gets_a_bare_challenge() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>()
+                    .required()
+                    .rfc6750_errors(Rfc6750::new().realm("api")),
+            )
+            .route("/", web::get().to(ok)),
+    )
+    .await;
+    let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(www_authenticate(&res), "Bearer realm=\"api\"");
+}
+
+#[actix_web::test]
+async fn expired_token_is_an_invalid_token() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<TestClaims>().rfc6750_errors(Rfc6750::new()))
+            .route("/", web::get().to(ok)),
+    )
+    .await;
+    let expired = token(&json!({ "sub": "alice", "exp": get_current_timestamp() - 3600 }));
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&expired))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(
+        www_authenticate(&res).starts_with("Bearer error=\"invalid_token\", error_description=")
+    );
+}
+
+#[actix_web::test]
+async fn malformed_header_is_an_invalid_request() {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>()
+                    .rfc6750_errors(Rfc6750::new().error_description(false).json_body(true)),
+            )
+            .route("/", web::get().to(ok)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header((header::AUTHORIZATION, "Bearer"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(www_authenticate(&res), "Bearer error=\"invalid_request\"");
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body, json!({ "error": "invalid_request" }));
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "testing")]
+
+mod common;
+
+use actix_jwt_middleware::{
+    Claims, IssuancePolicy, JwtMiddleware, TestAuthority, TestKeyKind, TestRequestExt,
+    TEST_AUDIENCE, TEST_ISSUER,
+};
+use actix_web::{error::ErrorUnauthorized, http::header, test, web, App, HttpResponse};
+use common::TestClaims;
+use serde_json::{json, Map, Value};
+
+async fn call<T>(jwt: JwtMiddleware<T>, token: &str) -> String
+where
+    T: serde::de::DeserializeOwned + Clone + 'static,
+{
+    let app = test::init_service(
+        App::new()
+            .wrap(jwt.error_handler(|e| ErrorUnauthorized(e.kind())))
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get().bearer_token(token).to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn issues_valid_and_broken_tokens() {
+    for authority in [
+        TestAuthority::<TestClaims>::hs256(),
+        TestAuthority::ed25519(),
+    ] {
+        let claims = TestClaims::new("alice");
+        let jwt = authority.middleware();
+        assert_eq!(call(jwt.clone(), &authority.token(&claims)).await, "ok");
+        let cases = [
+            (authority.expired_token(&claims), "expired"),
+            (authority.wrong_audience_token(&claims), "invalid_audience"),
+            (authority.wrong_issuer_token(&claims), "invalid_issuer"),
+            (
+                authority.wrong_signature_token(&claims),
+                "invalid_signature",
+            ),
+        ];
+        for (token, kind) in cases {
+            assert_eq!(call(jwt.clone(), &token).await, kind);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn authorities_use_their_own_keys() {
+    let authority = TestAuthority::<TestClaims>::default();
+    assert_eq!(authority.key_kind(), TestKeyKind::Ed25519);
+    let other = TestAuthority::<TestClaims>::default();
+    let token = other.token(&TestClaims::new("alice"));
+    assert_eq!(
+        call(authority.middleware(), &token).await,
+        "invalid_signature"
+    );
+}
+
+#[actix_web::test]
+async fn stamps_the_test_issuer_and_audience() {
+    let authority = TestAuthority::<Value>::hs256();
+    let app = test::init_service(App::new().wrap(authority.middleware()).route(
+        "/",
+        web::get().to(|claims: Claims<Value>| async move { HttpResponse::Ok().json(&*claims) }),
+    ))
+    .await;
+    let mut overrides = Map::new();
+    overrides.insert("sub".into(), "bob".into());
+    overrides.insert("jti".into(), "custom-jti".into());
+    let token = authority.token_with(&json!({ "sub": "alice", "scope": "read" }), overrides);
+    let req = test::TestRequest::get().bearer_token(&token).to_request();
+    let claims: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(claims["sub"], "bob");
+    assert_eq!(claims["jti"], "custom-jti");
+    assert_eq!(claims["scope"], "read");
+    assert_eq!(claims["iss"], TEST_ISSUER);
+    assert_eq!(claims["aud"], TEST_AUDIENCE);
+}
+
+#[actix_web::test]
+async fn policy_replaces_the_defaults() {
+    let authority = TestAuthority::<TestClaims>::hs256().policy(
+        IssuancePolicy::new()
+            .issuer("https://other.test")
+            .audience(["other-audience"]),
+    );
+    let claims = TestClaims::new("alice");
+    assert_eq!(
+        call(authority.middleware(), &authority.token(&claims)).await,
+        "ok"
+    );
+    let default = TestAuthority::<TestClaims>::hs256();
+    let mut overrides = Map::new();
+    overrides.insert("iss".into(), "https://other.test".into());
+    let token = default.token_with(&claims, overrides);
+    assert_eq!(call(default.middleware(), &token).await, "invalid_issuer");
+}
+
+#[actix_web::test]
+async fn bearer_token_sets_the_authorization_header() {
+    let req = test::TestRequest::get()
+        .bearer_token("abc.def.ghi")
+        .to_http_request();
+    assert_eq!(
+        req.headers().get(header::AUTHORIZATION).unwrap(),
+        "Bearer abc.def.ghi"
+    );
+}
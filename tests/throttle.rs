@@ -0,0 +1,239 @@
+mod common;
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use actix_jwt_middleware::{
+    FailureThrottle, JwtDecodeErrors, JwtMiddleware, KeyResolver, RejectedTokenCache, ResolvedKey,
+};
+use actix_web::{
+    error::ErrorUnauthorized,
+    http::{header, StatusCode},
+    test, web, App, HttpResponse,
+};
+use common::{bearer, claims, token, token_with_header, validation, TestClaims, SECRET};
+use futures::future::{ready, LocalBoxFuture};
+use jsonwebtoken::{Algorithm, DecodingKey, Header};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+// counts lookups, the kid "unknown" isn't found and "down" fails like an unreachable JWKS endpoint
+#[derive(Clone, Default)]
+struct CountingKeys(Arc<AtomicUsize>);
+
+impl KeyResolver for CountingKeys {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header,
+    ) -> LocalBoxFuture<'a, Result<ResolvedKey, JwtDecodeErrors>> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Box::pin(ready(match header.kid.as_deref() {
+            Some("unknown") => Err(JwtDecodeErrors::UnknownKey),
+            Some("down") => Err(JwtDecodeErrors::KeysUnavailable),
+            _ => Ok(ResolvedKey::new(DecodingKey::from_secret(SECRET))),
+        }))
+    }
+}
+
+fn token_with_kid(kid: &str) -> String {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some(kid.into());
+    token_with_header(&header, &claims("alice"))
+}
+
+fn tampered() -> String {
+    format!("{}x", token(&claims("alice")))
+}
+
+// status, `Retry-After` and body (the error kind) of every request
+async fn call_all(
+    jwt: JwtMiddleware<TestClaims>,
+    requests: &[(&str, &str)],
+) -> Vec<(StatusCode, Option<String>, String)> {
+    let app = test::init_service(
+        App::new()
+            .wrap(jwt.error_handler(|e| ErrorUnauthorized(e.kind())))
+            .route(
+                "/",
+                web::get().to(|| async { HttpResponse::Ok().body("ok") }),
+            ),
+    )
+    .await;
+    let mut responses = Vec::new();
+    for (peer, token) in requests {
+        let req = test::TestRequest::get()
+            .peer_addr(peer.parse::<SocketAddr>().unwrap())
+            .insert_header(bearer(token))
+            .insert_header(("X-Forwarded-For", "198.51.100.7"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_owned());
+        let body = test::read_body(res).await;
+        responses.push((
+            status,
+            retry_after,
+            String::from_utf8(body.to_vec()).unwrap(),
+        ));
+    }
+    responses
+}
+
+fn statuses(responses: &[(StatusCode, Option<String>, String)]) -> Vec<u16> {
+    responses
+        .iter()
+        .map(|(status, ..)| status.as_u16())
+        .collect()
+}
+
+fn throttled(throttle: FailureThrottle) -> JwtMiddleware<TestClaims> {
+    JwtMiddleware::with_key_resolver(CountingKeys::default(), validation())
+        .failure_throttle(throttle)
+}
+
+#[actix_web::test]
+async fn rejected_tokens_are_answered_from_the_cache() {
+    let keys = CountingKeys::default();
+    let jwt = JwtMiddleware::with_key_resolver(keys.clone(), validation())
+        .rejected_token_cache(RejectedTokenCache::new(16, WINDOW));
+    let tampered = tampered();
+    let responses = call_all(
+        jwt.clone(),
+        &[("192.0.2.1:1", &tampered), ("192.0.2.1:1", &tampered)],
+    )
+    .await;
+    assert_eq!(responses[0].2, "invalid_signature");
+    assert_eq!(responses[1].2, "invalid_signature");
+    assert_eq!(keys.0.load(Ordering::Relaxed), 1);
+
+    // keys that might show up after a refresh are looked up again
+    let unknown = token_with_kid("unknown");
+    let responses = call_all(jwt, &[("192.0.2.1:1", &unknown), ("192.0.2.1:1", &unknown)]).await;
+    assert_eq!(responses[1].2, "unknown_key");
+    assert_eq!(keys.0.load(Ordering::Relaxed), 3);
+}
+
+#[actix_web::test]
+async fn full_rejected_token_cache_replaces_the_oldest_entry() {
+    let keys = CountingKeys::default();
+    let jwt = JwtMiddleware::with_key_resolver(keys.clone(), validation())
+        .rejected_token_cache(RejectedTokenCache::new(1, WINDOW));
+    let first = tampered();
+    let second = format!("{}y", token(&claims("bob")));
+    let peer = "192.0.2.1:1";
+    call_all(
+        jwt,
+        &[
+            (peer, &first),
+            (peer, &second),
+            (peer, &second),
+            (peer, &first),
+        ],
+    )
+    .await;
+    assert_eq!(keys.0.load(Ordering::Relaxed), 3);
+}
+
+#[actix_web::test]
+async fn throttles_clients_after_repeated_failures() {
+    let tampered = tampered();
+    let valid = token(&claims("alice"));
+    let responses = call_all(
+        throttled(FailureThrottle::new(2, WINDOW)),
+        &[
+            ("192.0.2.1:1", &tampered),
+            ("192.0.2.1:2", &tampered),
+            ("192.0.2.1:3", &valid),
+            ("192.0.2.2:1", &valid),
+        ],
+    )
+    .await;
+    assert_eq!(statuses(&responses), [401, 401, 429, 200]);
+    let retry_after: u64 = responses[2].1.as_deref().unwrap().parse().unwrap();
+    assert!((1..=60).contains(&retry_after));
+    assert_eq!(responses[0].1, None);
+}
+
+#[actix_web::test]
+async fn server_side_failures_are_not_counted() {
+    let down = token_with_kid("down");
+    let valid = token(&claims("alice"));
+    let peer = "192.0.2.1:1";
+    let responses = call_all(
+        throttled(FailureThrottle::new(1, WINDOW)),
+        &[(peer, &down), (peer, &down), (peer, &valid)],
+    )
+    .await;
+    assert_eq!(responses[1].2, "keys_unavailable");
+    assert_eq!(responses[2].0, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn ipv6_clients_are_tracked_by_network() {
+    let tampered = tampered();
+    let valid = token(&claims("alice"));
+    let requests = [
+        ("[2001:db8:1:1::1]:1", tampered.as_str()),
+        ("[2001:db8:1:1::2]:1", &valid),
+        ("[2001:db8:1:2::1]:1", &valid),
+    ];
+    let responses = call_all(throttled(FailureThrottle::new(1, WINDOW)), &requests).await;
+    assert_eq!(statuses(&responses), [401, 429, 200]);
+
+    let throttle = FailureThrottle::new(1, WINDOW).ipv6_prefix(128);
+    let responses = call_all(throttled(throttle), &requests).await;
+    assert_eq!(statuses(&responses), [401, 200, 200]);
+}
+
+#[actix_web::test]
+async fn ipv4_mapped_addresses_share_the_ipv4_window() {
+    let tampered = tampered();
+    let valid = token(&claims("alice"));
+    let responses = call_all(
+        throttled(FailureThrottle::new(1, WINDOW)),
+        &[("192.0.2.1:1", &tampered), ("[::ffff:192.0.2.1]:1", &valid)],
+    )
+    .await;
+    assert_eq!(statuses(&responses), [401, 429]);
+}
+
+#[actix_web::test]
+async fn full_client_table_drops_the_oldest_window() {
+    let tampered = tampered();
+    let valid = token(&claims("alice"));
+    let responses = call_all(
+        throttled(FailureThrottle::new(1, WINDOW).max_clients(1)),
+        &[
+            ("192.0.2.1:1", &tampered),
+            ("192.0.2.1:1", &valid),
+            ("192.0.2.2:1", &tampered),
+            ("192.0.2.2:1", &valid),
+            ("192.0.2.1:1", &valid),
+        ],
+    )
+    .await;
+    assert_eq!(statuses(&responses), [401, 429, 401, 429, 200]);
+}
+
+#[actix_web::test]
+async fn forwarded_addresses_are_used_only_when_enabled() {
+    let tampered = tampered();
+    let valid = token(&claims("alice"));
+    // every request carries the same `X-Forwarded-For`
+    let requests = [("192.0.2.1:1", tampered.as_str()), ("192.0.2.2:1", &valid)];
+    let responses = call_all(throttled(FailureThrottle::new(1, WINDOW)), &requests).await;
+    assert_eq!(statuses(&responses), [401, 200]);
+
+    let throttle = FailureThrottle::new(1, WINDOW).real_ip(true);
+    let responses = call_all(throttled(throttle), &requests).await;
+    assert_eq!(statuses(&responses), [401, 429]);
+}
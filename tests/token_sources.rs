@@ -0,0 +1,97 @@
+mod common;
+
+use actix_jwt_middleware::{
+    Claims, CookieSource, FormSource, HeaderSource, QuerySource, TokenScheme, TokenSource,
+    TokenSourceChain,
+};
+use actix_web::{
+    cookie::Cookie, error::ErrorUnauthorized, http::header::HeaderName, test, web, App,
+};
+use common::{claims, middleware, token, TestClaims};
+
+// the body is the subject, or the error kind
+async fn verify<S>(source: S, req: test::TestRequest) -> String
+where
+    S: TokenSource + 'static,
+{
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                middleware::<TestClaims>()
+                    .required()
+                    .token_source(source)
+                    .error_handler(|e| ErrorUnauthorized(e.kind())),
+            )
+            .route(
+                "/",
+                web::route().to(|claims: Claims<TestClaims>, body: String| async move {
+                    format!("{}{}", claims.sub, body)
+                }),
+            ),
+    )
+    .await;
+    let body = test::call_and_read_body(&app, req.to_request()).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn reads_tokens_from_cookies() {
+    let token = token(&claims("alice"));
+    let req = test::TestRequest::get().cookie(Cookie::new("session", token));
+    assert_eq!(verify(CookieSource::new("session"), req).await, "alice");
+}
+
+#[actix_web::test]
+async fn reads_tokens_from_the_query() {
+    let token = token(&claims("alice"));
+    let req = test::TestRequest::get().uri(&format!("/?access_token={}", token));
+    assert_eq!(verify(QuerySource::access_token(), req).await, "alice");
+}
+
+#[actix_web::test]
+async fn reads_tokens_from_custom_headers() {
+    let token = token(&claims("alice"));
+    let source = HeaderSource::new(HeaderName::from_static("x-api-token"))
+        .scheme(TokenScheme::Custom("Token".into()));
+    let req = test::TestRequest::get().insert_header(("X-Api-Token", format!("token {}", token)));
+    assert_eq!(verify(source, req).await, "alice");
+
+    let source = HeaderSource::new(HeaderName::from_static("x-api-token"))
+        .scheme(TokenScheme::Custom("Token".into()));
+    let req = test::TestRequest::get().insert_header(("X-Api-Token", format!("Bearer {}", token)));
+    assert_eq!(verify(source, req).await, "invalid_jwt_header");
+}
+
+#[actix_web::test]
+async fn reads_tokens_from_forms_and_keeps_the_body() {
+    let token = token(&claims("alice"));
+    let body = format!("access_token={}&extra=1", token);
+    let req = test::TestRequest::post()
+        .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+        .insert_header(("Content-Length", body.len()))
+        .set_payload(body.clone());
+    assert_eq!(
+        verify(FormSource::access_token(), req).await,
+        format!("alice{}", body)
+    );
+}
+
+#[actix_web::test]
+async fn chains_sources_in_order() {
+    let token = token(&claims("alice"));
+    let chain = || {
+        TokenSourceChain::new()
+            .source(HeaderSource::authorization())
+            .source(CookieSource::new("session"))
+    };
+    let req = test::TestRequest::get().cookie(Cookie::new("session", token.clone()));
+    assert_eq!(verify(chain(), req).await, "alice");
+    let req = test::TestRequest::get()
+        .insert_header(("Authorization", "Bearer not-a-token"))
+        .cookie(Cookie::new("session", token));
+    assert_eq!(verify(chain(), req).await, "invalid_token");
+    assert_eq!(
+        verify(chain(), test::TestRequest::get()).await,
+        "missing_token"
+    );
+}
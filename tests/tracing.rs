@@ -0,0 +1,184 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use actix_jwt_middleware::{JwtMiddleware, SubjectRedaction};
+use actix_web::{test, web, App, HttpResponse};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{bearer, middleware, token, token_with_header, TestClaims};
+use jsonwebtoken::{get_current_timestamp, Algorithm, Header};
+use ring::digest;
+use serde_json::json;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Metadata, Subscriber,
+};
+use tracing_core::span::Current;
+
+type Fields = HashMap<String, String>;
+
+// keeps the recorded fields of every span, only enough of a subscriber for `Span::current()`
+#[derive(Clone, Default)]
+struct Spans {
+    next_id: Arc<AtomicU64>,
+    spans: Arc<Mutex<HashMap<u64, (&'static Metadata<'static>, Fields)>>>,
+    entered: Arc<Mutex<Vec<Id>>>,
+}
+
+impl Spans {
+    fn named(&self, name: &str) -> Vec<Fields> {
+        let spans = self.spans.lock().unwrap();
+        let mut ids: Vec<_> = spans
+            .iter()
+            .filter(|(_, (metadata, _))| metadata.name() == name)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        ids.iter().map(|id| spans[id].1.clone()).collect()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut fields = Fields::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        self.spans
+            .lock()
+            .unwrap()
+            .insert(id, (span.metadata(), fields));
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some((_, fields)) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut FieldVisitor(fields));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, span: &Id) {
+        let mut entered = self.entered.lock().unwrap();
+        if let Some(position) = entered.iter().rposition(|id| id == span) {
+            entered.remove(position);
+        }
+    }
+
+    fn current_span(&self) -> Current {
+        let Some(id) = self.entered.lock().unwrap().last().cloned() else {
+            return Current::none();
+        };
+        let metadata = self.spans.lock().unwrap()[&id.into_u64()].0;
+        Current::new(id, metadata)
+    }
+}
+
+async fn verify(jwt: JwtMiddleware<TestClaims>, token: &str) -> Fields {
+    let spans = Spans::default();
+    let _guard = tracing::subscriber::set_default(spans.clone());
+    let app = test::init_service(
+        App::new()
+            .wrap(jwt)
+            .route("/", web::get().to(|| async { HttpResponse::Ok().finish() })),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(token))
+        .to_request();
+    test::call_service(&app, req).await;
+    let mut verifications = spans.named("jwt_verification");
+    assert_eq!(verifications.len(), 1);
+    verifications.remove(0)
+}
+
+fn issued_token(sub: &str) -> String {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some("key-1".into());
+    token_with_header(
+        &header,
+        &json!({
+            "sub": sub,
+            "iss": "https://issuer.example",
+            "exp": get_current_timestamp() + 600,
+        }),
+    )
+}
+
+#[actix_web::test]
+async fn records_the_verification_with_a_hashed_subject() {
+    let fields = verify(middleware(), &issued_token("alice")).await;
+    assert_eq!(fields["outcome"], "success");
+    assert_eq!(fields["alg"], "HS256");
+    assert_eq!(fields["kid"], "key-1");
+    assert_eq!(fields["issuer"], "https://issuer.example");
+    let hash = URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, b"alice"));
+    assert_eq!(fields["sub"], hash);
+}
+
+#[actix_web::test]
+async fn subject_redaction_is_configurable() {
+    let jwt = middleware().subject_redaction(SubjectRedaction::Plain);
+    let fields = verify(jwt, &issued_token("alice")).await;
+    assert_eq!(fields["sub"], "alice");
+
+    let jwt = middleware().subject_redaction(SubjectRedaction::Omit);
+    let fields = verify(jwt, &issued_token("alice")).await;
+    assert!(!fields.contains_key("sub"));
+    assert_eq!(fields["outcome"], "success");
+}
+
+#[actix_web::test]
+async fn records_the_header_and_outcome_of_rejected_tokens() {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some("key-1".into());
+    let expired = token_with_header(
+        &header,
+        &json!({ "sub": "alice", "exp": get_current_timestamp() - 600 }),
+    );
+    let fields = verify(middleware(), &expired).await;
+    assert_eq!(fields["outcome"], "expired");
+    assert_eq!(fields["kid"], "key-1");
+    assert!(!fields.contains_key("sub"));
+
+    let fields = verify(
+        middleware(),
+        &format!("{}x", token(&common::claims("alice"))),
+    )
+    .await;
+    assert_eq!(fields["outcome"], "invalid_signature");
+    assert_eq!(fields["alg"], "HS256");
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use actix_jwt_middleware::{Claims, JwtMiddleware, ResolvedKey, VerifiedToken};
+use actix_web::{http::StatusCode, test, web, App, HttpMessage, HttpRequest, HttpResponse};
+use common::{bearer, claims, middleware, token_with_header, validation, TestClaims, SECRET};
+use jsonwebtoken::{Algorithm, DecodingKey, Header};
+
+#[actix_web::test]
+async fn exposes_header_claims_and_raw_token() {
+    let resolver = ResolvedKey::new(DecodingKey::from_secret(SECRET)).kid("key-1");
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                JwtMiddleware::<TestClaims>::with_key_resolver(resolver, validation())
+                    .store_verified_token(),
+            )
+            .route(
+                "/",
+                web::get().to(
+                    |req: HttpRequest, token: VerifiedToken<TestClaims>| async move {
+                        let header = token.header().unwrap();
+                        assert_eq!(header.alg, Algorithm::HS256);
+                        assert_eq!(header.kid.as_deref(), Some("key-1"));
+                        assert_eq!(token.key_id(), Some("key-1"));
+                        assert_eq!(token.claims().sub, "alice");
+                        assert!(token.issuer().is_none());
+                        assert!(req.extensions().get::<TestClaims>().is_none());
+                        HttpResponse::Ok().body(token.raw_token().to_owned())
+                    },
+                ),
+            ),
+    )
+    .await;
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some("key-1".into());
+    let token = token_with_header(&header, &claims("alice"));
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&token))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, token);
+}
+
+#[actix_web::test]
+async fn shares_claims_with_the_claims_extractor() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::<TestClaims>().store_verified_token())
+            .route(
+                "/",
+                web::get().to(
+                    |token: VerifiedToken<TestClaims>, claims: Claims<TestClaims>| async move {
+                        assert!(std::ptr::eq(token.claims(), &*claims));
+                        HttpResponse::Ok().finish()
+                    },
+                ),
+            ),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&common::token(&claims("alice"))))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn is_only_stored_when_enabled() {
+    let app = test::init_service(App::new().wrap(middleware::<TestClaims>()).route(
+        "/",
+        web::get().to(|_: VerifiedToken<TestClaims>| async { "" }),
+    ))
+    .await;
+    let req = test::TestRequest::get()
+        .insert_header(bearer(&common::token(&claims("alice"))))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
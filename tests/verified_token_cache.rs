@@ -0,0 +1,129 @@
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_jwt_middleware::{
+    JwtMiddleware, MemoryRevocationStore, VerifiedToken, VerifiedTokenCache,
+};
+use actix_web::{error::ErrorUnauthorized, test, web, App, HttpResponse};
+use common::{bearer, middleware, token, TestClaims};
+use jsonwebtoken::get_current_timestamp;
+use serde_json::json;
+
+const MAX_TTL: Duration = Duration::from_secs(3600);
+
+fn token_expiring_in(sub: &str, seconds: u64) -> String {
+    token(&json!({ "sub": sub, "jti": sub, "exp": get_current_timestamp() + seconds }))
+}
+
+// the body is the verification time in nanoseconds, or the error kind
+async fn call_all(jwt: JwtMiddleware<TestClaims>, tokens: &[&str]) -> Vec<String> {
+    let app = test::init_service(
+        App::new()
+            .wrap(
+                jwt.store_verified_token()
+                    .error_handler(|e| ErrorUnauthorized(e.kind())),
+            )
+            .route(
+                "/",
+                web::get().to(|token: VerifiedToken<TestClaims>| async move {
+                    let verified_at = token.verified_at().duration_since(UNIX_EPOCH).unwrap();
+                    HttpResponse::Ok().body(verified_at.as_nanos().to_string())
+                }),
+            ),
+    )
+    .await;
+    let mut bodies = Vec::new();
+    for token in tokens {
+        let req = test::TestRequest::get()
+            .insert_header(bearer(token))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        bodies.push(String::from_utf8(body.to_vec()).unwrap());
+        // keeps verification times of separate requests apart
+        actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+    }
+    bodies
+}
+
+#[actix_web::test]
+async fn hits_keep_the_original_verification_time() {
+    let cache = VerifiedTokenCache::new(16, MAX_TTL);
+    let valid = token_expiring_in("alice", 600);
+    let bodies = call_all(
+        middleware().verified_token_cache(cache.clone()),
+        &[&valid, &valid, &valid],
+    )
+    .await;
+    assert_eq!(bodies[0], bodies[1]);
+    assert_eq!(bodies[0], bodies[2]);
+    assert_eq!(cache.misses(), 1);
+    assert_eq!(cache.hits(), 2);
+    assert_eq!(cache.len(), 1);
+
+    cache.clear();
+    assert!(cache.is_empty());
+    let again = call_all(middleware().verified_token_cache(cache.clone()), &[&valid]).await;
+    assert_ne!(again[0], bodies[0]);
+    assert_eq!(cache.misses(), 2);
+}
+
+#[actix_web::test]
+async fn full_cache_evicts_the_entry_expiring_soonest() {
+    let cache = VerifiedTokenCache::new(2, MAX_TTL);
+    let soon = token_expiring_in("soon", 100);
+    let late = token_expiring_in("late", 300);
+    let middle = token_expiring_in("middle", 200);
+    let jwt = middleware().verified_token_cache(cache.clone());
+    call_all(jwt.clone(), &[&soon, &late, &middle]).await;
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.misses(), 3);
+
+    call_all(jwt.clone(), &[&late, &middle]).await;
+    assert_eq!(cache.hits(), 2);
+    call_all(jwt, &[&soon]).await;
+    assert_eq!(cache.misses(), 4);
+}
+
+#[actix_web::test]
+async fn entries_are_shared_but_not_across_configurations() {
+    let cache = VerifiedTokenCache::new(16, MAX_TTL);
+    let valid = token_expiring_in("alice", 600);
+    let jwt = middleware().verified_token_cache(cache.clone());
+    call_all(jwt.clone(), &[&valid]).await;
+    // e.g. the middleware of another worker
+    call_all(jwt, &[&valid]).await;
+    assert_eq!(cache.hits(), 1);
+
+    // a token verified by other keys or rules is verified again
+    call_all(middleware().verified_token_cache(cache.clone()), &[&valid]).await;
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(cache.misses(), 2);
+}
+
+#[actix_web::test]
+async fn cached_tokens_are_still_checked_for_revocation() {
+    let cache = VerifiedTokenCache::new(16, MAX_TTL);
+    let store = MemoryRevocationStore::new();
+    let valid = token_expiring_in("alice", 600);
+    let jwt = middleware()
+        .verified_token_cache(cache.clone())
+        .revocation_store(store.clone());
+    call_all(jwt.clone(), &[&valid]).await;
+    store.revoke_token("alice", MAX_TTL);
+    assert_eq!(call_all(jwt, &[&valid]).await, ["revoked"]);
+    assert_eq!(cache.hits(), 1);
+}
+
+#[actix_web::test]
+async fn rejected_tokens_are_not_cached() {
+    let cache = VerifiedTokenCache::new(16, MAX_TTL);
+    let expired = token(&json!({ "sub": "alice", "exp": get_current_timestamp() - 600 }));
+    let bodies = call_all(
+        middleware().verified_token_cache(cache.clone()),
+        &[&expired],
+    )
+    .await;
+    assert_eq!(bodies, ["expired"]);
+    assert!(cache.is_empty());
+}